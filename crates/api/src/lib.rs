@@ -1,23 +1,55 @@
-use rocket::{self, get, post, put, delete, serde::json::Json, State, routes};
-use rocket::http::Method;
+use rocket::{self, get, post, put, patch, delete, catch, catchers, serde::json::Json, State, routes};
+use rocket::data::{self, Data, FromData};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Method, Status};
+use rocket::http::uri::Origin;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::content::RawHtml;
+use rocket::response::status;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use anyhow::{Result, anyhow};
-use core::types::database::Database;
-use core::types::schema::{DbValue, DbSchema};
+use db_core::types::database::{Database, RelationshipGraph};
+use db_core::types::schema::{DbValue, DbSchema, validate_identifier};
+use db_core::types::filter::{self, ColumnPredicate, Filter};
+use db_core::types::table::{parse_direction, AggregateOp, ColumnStats, InsertOutcome, OnConflict, Row, SortKey};
 use std::sync::Mutex;
 use rocket_cors::{AllowedHeaders, AllowedOrigins, CorsOptions};
 use std::time::Duration;
 use tokio::time::interval;
-use core::io::{save_to_file, load_from_file};
+use tokio::sync::broadcast;
+use rocket::response::stream::{ByteStream, Event, EventStream};
+use rocket::Shutdown;
+use db_core::io::{self as db_io, save_to_file, save_to_file_compressed, load_from_file_compressed, ImportOptions, RetryPolicy};
 use std::env;
 use std::fs;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+use std::io::{Cursor, Write};
 use dotenv::dotenv;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Record {
     pub id: String,
     pub values: Vec<DbValue>,
+    /// Unix time in seconds; see `Row::created_at`.
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl From<Row> for Record {
+    fn from(row: Row) -> Self {
+        Record {
+            id: row.id.to_string(),
+            values: row.values,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,486 +57,4105 @@ pub struct NewRecord {
     pub values: Vec<DbValue>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameTable {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateTable {
+    pub name: String,
+}
+
+/// A record submitted as text, one string per column, as an HTML form would.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormRecord {
+    pub values: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateRecord {
     pub values: Vec<DbValue>,
 }
 
-pub struct ApiState {
-    pub db: Arc<Mutex<Database>>,
-    pub db_path: String,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetClause {
+    pub column: String,
+    pub value: DbValue,
 }
 
-pub async fn start_autosave(db: Arc<Mutex<Database>>, db_path: String) {
-    let mut interval = interval(Duration::from_secs(30)); // Save every 30 seconds
-    
-    loop {
-        interval.tick().await;
-        if let Ok(db) = db.lock() {
-            if let Err(e) = save_to_file(&*db, &db_path) {
-                eprintln!("Error autosaving database: {}", e);
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateWhere {
+    pub filter: Filter,
+    pub set: SetClause,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatedCount {
+    pub updated: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletedCount {
+    pub deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// One step of a `POST /transaction` batch.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Insert { table: String, values: Vec<DbValue> },
+    Update { table: String, id: u32, values: Vec<DbValue> },
+    Delete { table: String, id: u32 },
+}
+
+impl Operation {
+    fn table(&self) -> &str {
+        match self {
+            Operation::Insert { table, .. } | Operation::Update { table, .. } | Operation::Delete { table, .. } => table,
+        }
+    }
+
+    fn kind(&self) -> RowOp {
+        match self {
+            Operation::Insert { .. } => RowOp::Insert,
+            Operation::Update { .. } => RowOp::Update,
+            Operation::Delete { .. } => RowOp::Delete,
+        }
+    }
+
+    /// Applies the operation, returning the id of the row it touched.
+    fn apply(&self, db: &mut Database) -> Result<u32> {
+        let table = db.get_table_mut(self.table())
+            .ok_or_else(|| anyhow!("Table '{}' not found", self.table()))?;
+
+        match self {
+            Operation::Insert { values, .. } => table.insert(values.clone()),
+            Operation::Update { id, values, .. } => {
+                table.get_row(*id)?;
+                table.update(*id, values.clone())?;
+                Ok(*id)
+            }
+            Operation::Delete { id, .. } => {
+                table.delete(*id)?;
+                Ok(*id)
             }
         }
     }
 }
 
-pub fn cors() -> CorsOptions {
-    CorsOptions {
-        allowed_origins: AllowedOrigins::all(),
-        allowed_methods: vec![Method::Get, Method::Post, Method::Put, Method::Delete]
-            .into_iter()
-            .map(From::from)
-            .collect(),
-        allow_credentials: true,
-        allowed_headers: AllowedHeaders::all(),
-        ..Default::default()
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionApplied {
+    pub applied: usize,
+}
+
+/// Why a transaction was rolled back; `index` is the position of the failing
+/// operation, absent when the failure was not caused by one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionError {
+    pub error: String,
+    pub index: Option<usize>,
+}
+
+/// JSON request body that rejects malformed input with a 400 carrying the
+/// serde error message, reported by the `bad_request` catcher.
+pub struct JsonBody<T>(pub T);
+
+impl<T> JsonBody<T> {
+    pub fn into_inner(self) -> T {
+        self.0
     }
 }
 
-#[post("/tables/<table_name>", data = "<schema>")]
-pub async fn create_table(table_name: &str, schema: Json<DbSchema>, state: &State<ApiState>) -> Result<(), rocket::response::Debug<anyhow::Error>> {
-    let mut db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = core::types::table::Table::new(table_name.to_string(), schema.into_inner());
-    db.add_table(table);
-    save_to_file(&*db, &state.db_path)?;
-    Ok(())
+impl<T> std::ops::Deref for JsonBody<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
 }
 
-#[get("/tables/<table_name>/records")]
-pub async fn get_all(table_name: &str, state: &State<ApiState>) -> Result<Json<Vec<Record>>, rocket::response::Debug<anyhow::Error>> {
-    let db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    
-    let records = table.get_rows().into_iter()
-        .map(|r| Record {
-            id: r.id.to_string(),
-            values: r.values.clone(),
-        })
-        .collect();
-    
-    Ok(Json(records))
+struct BodyError(Option<String>);
+
+#[rocket::async_trait]
+impl<'r, T: Deserialize<'r>> FromData<'r> for JsonBody<T> {
+    type Error = String;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        match Json::<T>::from_data(req, data).await {
+            Outcome::Success(json) => Outcome::Success(JsonBody(json.into_inner())),
+            Outcome::Error((status, e)) => {
+                let (status, message) = match e {
+                    rocket::serde::json::Error::Parse(_, e) => (Status::BadRequest, e.to_string()),
+                    rocket::serde::json::Error::Io(e) => (status, e.to_string()),
+                };
+                req.local_cache(|| BodyError(Some(message.clone())));
+                Outcome::Error((status, message))
+            }
+            Outcome::Forward(f) => Outcome::Forward(f),
+        }
+    }
 }
 
-#[get("/tables/<table_name>/records/<id>")]
-pub async fn get_by_id(table_name: &str, id: &str, state: &State<ApiState>) -> Result<Json<Record>, rocket::response::Debug<anyhow::Error>> {
-    let db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    let id = id.parse::<u32>().map_err(|_| anyhow!("Invalid ID format"))?;
-    
-    let row = table.get_row(id)?;
-    Ok(Json(Record {
-        id: row.id.to_string(),
-        values: row.values.clone(),
-    }))
+/// Handler failure, answered with the matching status and an `ErrorBody`.
+/// `?` on an `anyhow::Error` yields `Internal`; handlers map client mistakes
+/// to the other variants explicitly.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    Conflict(String),
+    Internal(anyhow::Error),
 }
 
-#[post("/tables/<table_name>/records", data = "<record>")]
-pub async fn create(table_name: &str, record: Json<NewRecord>, state: &State<ApiState>) -> Result<Json<Record>, rocket::response::Debug<anyhow::Error>> {
-    let mut db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table_mut(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    
-    let id = table.insert(record.values.clone())?;
-    save_to_file(&*db, &state.db_path)?;
-    Ok(Json(Record {
-        id: id.to_string(),
-        values: record.values.clone(),
-    }))
+impl ApiError {
+    fn not_found(error: impl std::fmt::Display) -> Self {
+        ApiError::NotFound(error.to_string())
+    }
+
+    fn bad_request(error: impl std::fmt::Display) -> Self {
+        ApiError::BadRequest(error.to_string())
+    }
+
+    fn conflict(error: impl std::fmt::Display) -> Self {
+        ApiError::Conflict(error.to_string())
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
 }
 
-#[put("/tables/<table_name>/records/<id>", data = "<record>")]
-pub async fn update(table_name: &str, id: &str, record: Json<UpdateRecord>, state: &State<ApiState>) -> Result<Json<Record>, rocket::response::Debug<anyhow::Error>> {
-    let mut db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table_mut(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    let id = id.parse::<u32>().map_err(|_| anyhow!("Invalid ID format"))?;
-    
-    table.update(id, record.values.clone())?;
-    save_to_file(&*db, &state.db_path)?;
-    Ok(Json(Record {
-        id: id.to_string(),
-        values: record.values.clone(),
-    }))
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::NotFound(e) | ApiError::BadRequest(e) | ApiError::Conflict(e) => write!(f, "{}", e),
+            ApiError::Internal(e) => write!(f, "{}", e),
+        }
+    }
 }
 
-#[delete("/tables/<table_name>/records/<id>")]
-pub async fn delete(table_name: &str, id: &str, state: &State<ApiState>) -> Result<(), rocket::response::Debug<anyhow::Error>> {
-    let mut db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table_mut(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    let id = id.parse::<u32>().map_err(|_| anyhow!("Invalid ID format"))?;
-    table.delete(id)?;
-    save_to_file(&*db, &state.db_path)?;
-    Ok(())
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ApiError::Internal(error)
+    }
 }
 
-#[get("/intersection/<table1>/<table2>")]
-pub async fn intersection(table1: &str, table2: &str, state: &State<ApiState>) -> Result<Json<Vec<Record>>, rocket::response::Debug<anyhow::Error>> {
-    let db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table1 = db.get_table(table1).ok_or_else(|| anyhow!("Table 1 not found"))?;
-    let table2 = db.get_table(table2).ok_or_else(|| anyhow!("Table 2 not found"))?;
-    
-    let intersection = table1.intersection(table2)?;
-    let records = intersection.into_iter()
-        .map(|r| Record {
-            id: r.id.to_string(),
-            values: r.values.clone(),
-        })
-        .collect();
-    
-    Ok(Json(records))
+impl<'r> rocket::response::Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        status::Custom(self.status(), Json(ErrorBody { error: self.to_string() })).respond_to(req)
+    }
 }
 
-#[derive(Debug, Serialize)]
-pub struct TableList {
-    tables: Vec<String>
+#[catch(400)]
+pub fn bad_request(req: &Request) -> Json<ErrorBody> {
+    let error = req.local_cache(|| BodyError(None)).0.clone()
+        .unwrap_or_else(|| "Bad request".to_string());
+    Json(ErrorBody { error })
 }
 
-#[get("/health")]
-pub async fn health_check() -> &'static str {
-    "OK"
+#[catch(423)]
+pub fn locked() -> Json<ErrorBody> {
+    Json(ErrorBody { error: "Database is locked for maintenance".to_string() })
 }
 
-#[get("/tables")]
-pub async fn list_tables(state: &State<ApiState>) -> Result<Json<TableList>, rocket::response::Debug<anyhow::Error>> {
-    let db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let tables = db.tables.iter().map(|t| t.name().to_string()).collect();
-    Ok(Json(TableList { tables }))
+const UNAUTHORIZED: &str = "Missing or invalid API key";
+
+#[catch(401)]
+pub fn unauthorized() -> Json<ErrorBody> {
+    Json(ErrorBody { error: UNAUTHORIZED.to_string() })
 }
 
-#[get("/tables/<table_name>/details")]
-pub async fn get_table_details(table_name: &str, state: &State<ApiState>) -> Result<Json<TableDetails>, rocket::response::Debug<anyhow::Error>> {
-    let db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    let table = db.get_table(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    
-    Ok(Json(TableDetails {
-        schema: table.schema.clone(),
-        rows: table.get_rows().into_iter()
-            .map(|r| Record {
-                id: r.id.to_string(),
-                values: r.values.clone(),
-            })
-            .collect(),
-    }))
+pub struct ApiState {
+    pub db: Arc<Mutex<Database>>,
+    pub db_path: String,
+    /// Set by `/admin/lock`; mutation handlers refuse to run while it is.
+    pub locked: AtomicBool,
+    pub metrics: Metrics,
+    /// Row changes, fanned out to `/tables/<name>/events` subscribers.
+    pub changes: broadcast::Sender<ChangeEvent>,
+    /// Key expected in `X-API-Key` by `ApiKey`; `None` disables
+    /// authentication.
+    pub api_key: Option<String>,
+    /// Demand the key on reads too; see `Readable`.
+    pub require_auth: bool,
+    /// Set while the latest changes may not be on disk: a save failed and no
+    /// later one has succeeded yet.
+    pub dirty: Arc<AtomicBool>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct TableDetails {
-    schema: DbSchema,
-    rows: Vec<Record>,
+impl ApiState {
+    pub fn new(db: Arc<Mutex<Database>>, db_path: String) -> Self {
+        let (changes, _) = broadcast::channel(1024);
+        ApiState {
+            db,
+            db_path,
+            locked: AtomicBool::new(false),
+            metrics: Metrics::default(),
+            changes,
+            api_key: None,
+            require_auth: false,
+            dirty: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether `key`, the request's `X-API-Key`, grants access.
+    pub fn authorized(&self, key: Option<&str>) -> bool {
+        match &self.api_key {
+            Some(expected) => key == Some(expected.as_str()),
+            None => true,
+        }
+    }
+
+    /// Announces a change to `table`; `id` is `None` when several rows changed
+    /// at once.
+    pub fn publish(&self, table: &str, op: RowOp, id: Option<u32>) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.changes.send(ChangeEvent { table: table.to_string(), op, id });
+    }
+
+    /// Writes the database to `db_path`, recording how long it took. A
+    /// failure is still returned, but also marks the state dirty and starts
+    /// `persist_with_retry` in the background, unless the state was already
+    /// dirty and a retry or the autosave task is taking care of it.
+    pub fn save(&self, db: &Database) -> Result<()> {
+        let started = Instant::now();
+        let result = persist(db, &self.db_path);
+        self.metrics.record_save(started.elapsed());
+
+        if result.is_ok() {
+            self.dirty.store(false, Ordering::SeqCst);
+        } else if !self.dirty.swap(true, Ordering::SeqCst) {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let (db, db_path, dirty) = (self.db.clone(), self.db_path.clone(), self.dirty.clone());
+                runtime.spawn(async move {
+                    if let Err(e) = persist_with_retry(&db, &db_path, &RetryPolicy::default(), &dirty).await {
+                        rocket::error!("Error saving database: {}", e);
+                    }
+                });
+            }
+        }
+        result
+    }
 }
 
-#[delete("/tables/<table_name>")]
-pub async fn delete_table(table_name: &str, state: &State<ApiState>) -> Result<(), rocket::response::Debug<anyhow::Error>> {
-    let mut db = state.db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
-    db.delete_table(table_name).ok_or_else(|| anyhow!("Table not found"))?;
-    save_to_file(&*db, &state.db_path)?;
-    Ok(())
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RowOp {
+    Insert,
+    Update,
+    Delete,
 }
 
-pub fn rocket() -> rocket::Rocket<rocket::Build> {
-    let db_path = env::var("DATABASE_FILE").unwrap_or_else(|_| "database.db".to_string());
-    
-    // Load existing database or create new one
-    let db = if fs::metadata(&db_path).is_ok() {
-        load_from_file(&db_path).unwrap_or_else(|_| Database::new(&db_path))
-    } else {
-        let db = Database::new(&db_path);
-        save_to_file(&db, &db_path).unwrap_or_default();
-        db
-    };
-    let db = Arc::new(Mutex::new(db));
-    let state = ApiState { db, db_path: db_path.clone() };
-    
-    let cors = cors().to_cors().expect("Failed to create CORS fairing");
-    
-    rocket::build()
-        .attach(cors)
-        .mount("/", routes![health_check])
-        .mount("/api", routes![
-            list_tables,
-            create_table,
-            delete_table,
-            get_table_details,
-            get_all,
-            get_by_id,
-            create,
-            update,
-            delete,
-            intersection,
-        ])
-        .manage(state)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChangeEvent {
+    pub table: String,
+    pub op: RowOp,
+    pub id: Option<u32>,
 }
 
-pub async fn run_server() -> Result<()> {
-    dotenv().ok();
-    rocket()
-        .launch()
-        .await
-        .map_err(|e| anyhow!("Rocket server error: {}", e))?;
-    Ok(())
+/// Counters served in Prometheus text format by `GET /metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<String, u64>>,
+    inserts: AtomicU64,
+    updates: AtomicU64,
+    deletes: AtomicU64,
+    saves: AtomicU64,
+    save_micros: AtomicU64,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rocket::local::blocking::Client;
-    use rocket::http::{Status, ContentType};
-    use core::types::schema::{DbSchema, DbColumn, DbColumnType};
+impl Metrics {
+    pub fn record_request(&self, method: Method) {
+        if let Ok(mut requests) = self.requests.lock() {
+            *requests.entry(method.as_str().to_string()).or_default() += 1;
+        }
+    }
 
-    fn create_test_client() -> Client {
-        let db = Arc::new(Mutex::new(Database::new("test.db")));
-        let state = ApiState { db, db_path: "test.db".to_string() };
-        
-        let rocket = rocket::build()
-            .mount("/api", routes![
-                create_table,
-                get_all,
-                get_by_id,
-                create,
-                update,
-                delete,
-                intersection,
-            ])
-            .manage(state);
-            
-        Client::tracked(rocket).expect("valid rocket instance")
+    pub fn record_rows(&self, op: RowOp, count: u64) {
+        let counter = match op {
+            RowOp::Insert => &self.inserts,
+            RowOp::Update => &self.updates,
+            RowOp::Delete => &self.deletes,
+        };
+        counter.fetch_add(count, Ordering::Relaxed);
     }
 
-    fn create_test_schema() -> DbSchema {
-        DbSchema {
-            columns: vec![
-                DbColumn {
-                    name: "id".to_string(),
-                    column_type: DbColumnType::Integer,
-                },
-                DbColumn {
-                    name: "name".to_string(),
-                    column_type: DbColumnType::String,
-                },
-                DbColumn {
-                    name: "balance".to_string(),
-                    column_type: DbColumnType::Money,
-                },
-            ],
+    pub fn record_save(&self, duration: Duration) {
+        self.saves.fetch_add(1, Ordering::Relaxed);
+        self.save_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Renders every metric, with per-table row counts taken from `db`.
+    pub fn render(&self, db: &Database) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP api_requests_total Requests received, by HTTP method.\n");
+        out.push_str("# TYPE api_requests_total counter\n");
+        if let Ok(requests) = self.requests.lock() {
+            for (method, count) in requests.iter() {
+                out.push_str(&format!("api_requests_total{{method=\"{}\"}} {}\n", method, count));
+            }
         }
+
+        out.push_str("# HELP db_table_rows Rows currently stored, by table.\n");
+        out.push_str("# TYPE db_table_rows gauge\n");
+        for table in &db.tables {
+            out.push_str(&format!("db_table_rows{{table=\"{}\"}} {}\n", escape_label(table.name()), table.len()));
+        }
+
+        out.push_str("# HELP db_row_operations_total Rows inserted, updated and deleted.\n");
+        out.push_str("# TYPE db_row_operations_total counter\n");
+        for (op, counter) in [("insert", &self.inserts), ("update", &self.updates), ("delete", &self.deletes)] {
+            out.push_str(&format!("db_row_operations_total{{op=\"{}\"}} {}\n", op, counter.load(Ordering::Relaxed)));
+        }
+
+        out.push_str("# HELP db_save_duration_seconds Time spent writing the database file.\n");
+        out.push_str("# TYPE db_save_duration_seconds summary\n");
+        let seconds = self.save_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        out.push_str(&format!("db_save_duration_seconds_sum {}\n", seconds));
+        out.push_str(&format!("db_save_duration_seconds_count {}\n", self.saves.load(Ordering::Relaxed)));
+
+        out
     }
+}
 
-    fn create_test_record() -> Record {
-        Record {
-            id: "0".to_string(),
-            values: vec![
-                DbValue::Integer(1),
-                DbValue::String("John Doe".to_string()),
-                DbValue::Money(1000.0),
-            ],
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Counts every request by method in `ApiState::metrics`.
+pub fn request_metrics() -> AdHoc {
+    AdHoc::on_request("Request metrics", |req, _| Box::pin(async move {
+        if let Some(state) = req.rocket().state::<ApiState>() {
+            state.metrics.record_request(req.method());
+        }
+    }))
+}
+
+/// `API_KEY`; unset or empty leaves the API open, for local development.
+pub fn api_key_from_env() -> Option<String> {
+    env::var("API_KEY").ok().filter(|key| !key.is_empty())
+}
+
+/// `REQUIRE_AUTH=1` or `true` extends the API key check to reads.
+pub fn require_auth_from_env() -> bool {
+    matches!(env::var("REQUIRE_AUTH").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Request guard failing with 401 Unauthorized unless the `X-API-Key`
+/// header matches `ApiState::api_key`. Passes everything while no key is
+/// configured.
+pub struct ApiKey;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        match req.rocket().state::<ApiState>() {
+            Some(state) if !state.authorized(req.headers().get_one("X-API-Key")) => {
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+            _ => Outcome::Success(ApiKey),
+        }
+    }
+}
+
+/// Request guard for read handlers: fails with 401 Unauthorized as `ApiKey`
+/// does, but only while `ApiState::require_auth` is set. `/health` goes
+/// without it.
+pub struct Readable;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Readable {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        match req.rocket().state::<ApiState>() {
+            Some(state) if state.require_auth => req.guard::<ApiKey>().await.map(|_| Readable),
+            _ => Outcome::Success(Readable),
+        }
+    }
+}
+
+/// Request guard for mutation handlers: fails with 401 Unauthorized as
+/// `ApiKey` does, and with 423 Locked while writes are blocked through
+/// `/admin/lock`.
+pub struct Writable;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Writable {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        rocket::outcome::try_outcome!(req.guard::<ApiKey>().await);
+        match req.rocket().state::<ApiState>() {
+            Some(state) if state.locked.load(Ordering::SeqCst) => Outcome::Error((Status::Locked, ())),
+            _ => Outcome::Success(Writable),
         }
     }
+}
+
+/// Saves gzip-compressed when `path` ends in `.gz`, as plain JSON otherwise.
+/// Loading goes through `load_from_file_compressed`, which reads either.
+pub fn persist(db: &Database, path: &str) -> Result<()> {
+    if path.ends_with(".gz") {
+        save_to_file_compressed(db, path)
+    } else {
+        save_to_file(db, path)
+    }
+}
+
+/// Autosave period used when `AUTOSAVE_INTERVAL_SECS` is unset or invalid.
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Reads `AUTOSAVE_INTERVAL_SECS`; `None` means autosave is disabled.
+pub fn autosave_interval_from_env() -> Option<Duration> {
+    parse_autosave_interval(env::var("AUTOSAVE_INTERVAL_SECS").ok().as_deref())
+}
+
+/// `0` disables autosave; a missing or unparseable value falls back to
+/// `DEFAULT_AUTOSAVE_INTERVAL`.
+fn parse_autosave_interval(value: Option<&str>) -> Option<Duration> {
+    match value.and_then(|v| v.trim().parse::<u64>().ok()) {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(DEFAULT_AUTOSAVE_INTERVAL),
+    }
+}
+
+/// Persists the database, retrying failures with `policy`'s backoff. The
+/// lock is taken only for each attempt and the backoff is a
+/// `tokio::time::sleep`, so neither handlers nor the worker thread wait it
+/// out. `dirty` is cleared by a successful attempt and left set when every
+/// attempt fails.
+pub async fn persist_with_retry(db: &Mutex<Database>, db_path: &str, policy: &RetryPolicy, dirty: &AtomicBool) -> Result<()> {
+    let mut attempt = 1;
+    let mut delay = policy.base_delay;
+    loop {
+        let result = {
+            let db = db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
+            persist(&db, db_path)
+        };
+        match result {
+            Ok(()) => {
+                dirty.store(false, Ordering::SeqCst);
+                return Ok(());
+            }
+            Err(e) if attempt >= policy.attempts => {
+                dirty.store(true, Ordering::SeqCst);
+                return Err(anyhow!("Giving up after {} attempts: {}", attempt, e));
+            }
+            Err(_) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Persists the database every `period` until `shutdown` resolves. `rocket()`
+/// passes the server's `Shutdown` so the task stops along with the server.
+/// `dirty` is shared with `ApiState`; see `persist_with_retry`.
+pub async fn start_autosave(db: Arc<Mutex<Database>>, db_path: String, dirty: Arc<AtomicBool>, period: Duration, shutdown: impl std::future::Future<Output = ()>) {
+    let mut interval = interval(period);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut shutdown => break,
+        }
+        if let Err(e) = persist_with_retry(&db, &db_path, &RetryPolicy::default(), &dirty).await {
+            rocket::error!("Error autosaving database: {}", e);
+        }
+    }
+}
+
+/// Settings for the background compaction task, which is off by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionConfig {
+    pub interval: Duration,
+    /// Fragmentation ratio above which a table is compacted.
+    pub threshold: f64,
+}
+
+impl CompactionConfig {
+    pub const DEFAULT_THRESHOLD: f64 = 0.5;
+
+    /// Reads `COMPACTION_INTERVAL_SECS` and `COMPACTION_THRESHOLD`. Returns
+    /// `None` (compaction disabled) unless a non-zero interval is set.
+    pub fn from_env() -> Option<Self> {
+        Self::parse(
+            env::var("COMPACTION_INTERVAL_SECS").ok().as_deref(),
+            env::var("COMPACTION_THRESHOLD").ok().as_deref(),
+        )
+    }
+
+    fn parse(interval: Option<&str>, threshold: Option<&str>) -> Option<Self> {
+        let secs = interval?.trim().parse::<u64>().ok().filter(|s| *s > 0)?;
+        let threshold = threshold
+            .and_then(|t| t.trim().parse::<f64>().ok())
+            .unwrap_or(Self::DEFAULT_THRESHOLD);
+        Some(CompactionConfig {
+            interval: Duration::from_secs(secs),
+            threshold,
+        })
+    }
+}
+
+/// Releases the storage of fragmented tables; see `Database::compact`. Row
+/// ids and the saved file are unchanged, so nothing is persisted.
+pub fn compact_tables(db: &Mutex<Database>, threshold: f64) -> Result<Vec<String>> {
+    let mut db = db.lock().map_err(|_| anyhow!("Failed to lock database"))?;
+    Ok(db.compact(threshold))
+}
+
+/// Compacts every `config.interval` until `shutdown` resolves, as
+/// `start_autosave` does.
+pub async fn start_compaction(db: Arc<Mutex<Database>>, config: CompactionConfig, shutdown: impl std::future::Future<Output = ()>) {
+    let mut interval = interval(config.interval);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut shutdown => break,
+        }
+        match compact_tables(&db, config.threshold) {
+            Ok(tables) if !tables.is_empty() => rocket::info!("Compacted tables: {}", tables.join(", ")),
+            Ok(_) => {}
+            Err(e) => rocket::error!("Error compacting database: {}", e),
+        }
+    }
+}
+
+/// Reads the comma-separated `ALLOWED_ORIGINS`; see `cors_from`.
+pub fn cors() -> CorsOptions {
+    cors_from(env::var("ALLOWED_ORIGINS").ok().as_deref())
+}
+
+/// Restricts requests to the listed origins with credentials enabled. With no
+/// origins every origin is allowed, and credentials are then disabled since
+/// browsers reject a wildcard origin combined with credentials.
+fn cors_from(origins: Option<&str>) -> CorsOptions {
+    let origins: Vec<&str> = origins
+        .map(|v| v.split(',').map(str::trim).filter(|o| !o.is_empty()).collect())
+        .unwrap_or_default();
+    let (allowed_origins, allow_credentials) = if origins.is_empty() {
+        (AllowedOrigins::all(), false)
+    } else {
+        (AllowedOrigins::some_exact(&origins), true)
+    };
+
+    CorsOptions {
+        allowed_origins,
+        allowed_methods: vec![Method::Get, Method::Post, Method::Put, Method::Patch, Method::Delete]
+            .into_iter()
+            .map(From::from)
+            .collect(),
+        allow_credentials,
+        allowed_headers: AllowedHeaders::all(),
+        ..Default::default()
+    }
+}
+
+/// Responds 400 for an invalid table name or schema (see `Table::try_new`)
+/// and 409 when a table named `table_name` already exists.
+#[post("/tables/<table_name>", data = "<schema>")]
+pub async fn create_table(table_name: &str, schema: JsonBody<DbSchema>, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let table = db_core::types::table::Table::try_new(table_name.to_string(), schema.into_inner())
+        .map_err(ApiError::bad_request)?;
+
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    db.add_table(table).map_err(ApiError::conflict)?;
+    state.save(&db)?;
+    Ok(())
+}
+
+/// Body of `GET /tables/<table_name>/records`: a page of full records, or
+/// only their ids when `ids_only` is set. Pass `next` as `after` (or
+/// advance `offset`) to fetch the following page; `total` counts every row
+/// matching the filters.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecordList {
+    Ids { ids: Vec<String>, next: Option<String>, total: usize },
+    Page { records: Vec<Record>, next: Option<String>, total: usize },
+}
+
+/// Page size when `limit` is not given.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Query parameters of `GET /tables/<table_name>/records`; see `get_all`.
+#[derive(Debug, rocket::FromForm)]
+pub struct RecordQuery<'r> {
+    sort: Option<&'r str>,
+    order: Option<&'r str>,
+    filter: Vec<&'r str>,
+    ids_only: Option<bool>,
+    after: Option<&'r str>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// `sort` takes a comma-separated list of `column[:asc|desc]` keys; `order`
+/// (asc or desc) sets the direction of keys that do not give one. Each
+/// `filter` is a `column:op:value` predicate (op is eq, gt, lt or contains);
+/// repeat it to require several. Rows are sorted (display order by default)
+/// and filtered first, then paged: `after` is the `next` cursor of the
+/// previous page, `offset` skips that many further rows and `limit` (default
+/// `DEFAULT_PAGE_LIMIT`) caps the page. In id order a cursor stays valid
+/// when its row is deleted; with `sort`, or once rows have been moved, it
+/// must still name a row.
+#[get("/tables/<table_name>/records?<query..>")]
+pub async fn get_all(table_name: &str, query: RecordQuery<'_>, _readable: Readable, state: &State<ApiState>) -> Result<Json<RecordList>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::not_found("Table not found"))?;
+    let predicates = query.filter.iter()
+        .map(|spec| ColumnPredicate::parse(spec, &table.schema))
+        .collect::<Result<Vec<_>>>()
+        .map_err(ApiError::bad_request)?;
+    let predicates = filter::resolve(&predicates, &table.schema).map_err(ApiError::bad_request)?;
+    let descending = query.order.map(parse_direction).transpose()
+        .map_err(ApiError::bad_request)?
+        .unwrap_or(false);
+    let after = query.after
+        .map(|cursor| cursor.parse::<u32>().map_err(|_| ApiError::bad_request(format!("Invalid cursor '{}'", cursor))))
+        .transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if limit == 0 {
+        return Err(ApiError::bad_request("limit must be at least 1"));
+    }
+
+    let sorted;
+    let mut rows: Vec<&Row> = match query.sort {
+        Some(spec) => {
+            sorted = SortKey::parse_list_with_default(spec, descending)
+                .and_then(|keys| table.sorted_by(&keys))
+                .map_err(ApiError::bad_request)?;
+            sorted.iter().collect()
+        }
+        None => table.get_rows_ordered(),
+    };
+    rows.retain(|r| predicates.iter().all(|(i, p)| p.matches(&r.values[*i])));
+
+    let start = match after {
+        None => 0,
+        Some(after) if query.sort.is_none() && table.order.is_none() => rows.partition_point(|r| r.id <= after),
+        Some(after) => rows.iter().position(|r| r.id == after)
+            .map(|i| i + 1)
+            .ok_or_else(|| ApiError::bad_request(format!("Cursor row {} no longer exists", after)))?,
+    };
+    let start = start.saturating_add(query.offset.unwrap_or(0)).min(rows.len());
+    let end = start.saturating_add(limit).min(rows.len());
+    let (page, total) = (&rows[start..end], rows.len());
+    let next = match page.last() {
+        Some(last) if end < total => Some(last.id.to_string()),
+        _ => None,
+    };
+
+    if query.ids_only.unwrap_or(false) {
+        let ids = page.iter().map(|r| r.id.to_string()).collect();
+        return Ok(Json(RecordList::Ids { ids, next, total }));
+    }
+
+    let columns = table.schema.list_columns();
+    let records = page.iter()
+        .map(|r| r.project(&columns))
+        .map(Record::from)
+        .collect();
+    Ok(Json(RecordList::Page { records, next, total }))
+}
+
+/// Rows fetched per lock while streaming a table.
+const STREAM_CHUNK_SIZE: usize = 500;
+
+/// Streams the table as JSON Lines, one `Record` per line in id order. The
+/// lock is taken once per chunk, so rows written mid-stream may or may not
+/// appear; a table dropped mid-stream ends it early.
+#[get("/tables/<table_name>/records/stream")]
+pub async fn stream_records(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<(ContentType, ByteStream![Vec<u8>]), ApiError> {
+    {
+        let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+        db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    }
+    let db = state.db.clone();
+    let table_name = table_name.to_string();
+
+    let stream = ByteStream! {
+        let mut after = None;
+        loop {
+            let chunk = {
+                let Ok(db) = db.lock() else { break };
+                let Some(table) = db.get_table(&table_name) else { break };
+                let columns = table.schema.list_columns();
+                let rows = table.rows_after(after, STREAM_CHUNK_SIZE);
+                let Some(last) = rows.last() else { break };
+                after = Some(last.id);
+
+                let mut chunk = Vec::new();
+                for row in rows {
+                    if serde_json::to_writer(&mut chunk, &Record::from(row.project(&columns))).is_ok() {
+                        chunk.push(b'\n');
+                    }
+                }
+                chunk
+            };
+            yield chunk;
+        }
+    };
+    Ok((ContentType::new("application", "x-ndjson"), stream))
+}
+
+/// One record per distinct value vector, in id order.
+#[get("/tables/<table_name>/records/distinct")]
+pub async fn get_distinct(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+
+    let records = table.distinct().into_iter()
+        .map(Record::from)
+        .collect();
+
+    Ok(Json(records))
+}
+
+/// Up to `n` (default 10) records picked at random, for previewing a large
+/// table; see `Table::sample`.
+#[get("/tables/<table_name>/records/sample?<n>")]
+pub async fn sample_records(table_name: &str, n: Option<usize>, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+
+    let records = table.sample(n.unwrap_or(10)).into_iter()
+        .map(Record::from)
+        .collect();
+
+    Ok(Json(records))
+}
+
+/// Ranked after the static `/records/...` routes such as `distinct`.
+#[get("/tables/<table_name>/records/<id>", rank = 2)]
+pub async fn get_by_id(table_name: &str, id: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    
+    let row = table.get_row(id).map_err(ApiError::not_found)?;
+    Ok(Json(Record::from(row.clone())))
+}
+
+#[post("/tables/<table_name>/records?<on_conflict>", data = "<record>")]
+pub async fn create(table_name: &str, on_conflict: Option<&str>, record: JsonBody<NewRecord>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let on_conflict = on_conflict.map(str::parse::<OnConflict>).transpose()
+        .map_err(ApiError::bad_request)?
+        .unwrap_or_default();
+    
+    let outcome = table.insert_with(record.values.clone(), on_conflict).map_err(ApiError::bad_request)?;
+    let record = Record::from(table.get_row(outcome.id())?.clone());
+    // An ignored row changed nothing, so there is nothing to save or report
+    let op = match outcome {
+        InsertOutcome::Inserted(_) => RowOp::Insert,
+        InsertOutcome::Replaced(_) => RowOp::Update,
+        InsertOutcome::Ignored(_) => return Ok(Json(record)),
+    };
+    state.save(&db)?;
+    state.metrics.record_rows(op, 1);
+    state.publish(table_name, op, Some(outcome.id()));
+    Ok(Json(record))
+}
+
+/// Inserts all records or, if any of them is invalid, none; see
+/// `Table::insert_many`.
+#[post("/tables/<table_name>/records/batch", data = "<records>")]
+pub async fn create_batch(table_name: &str, records: JsonBody<Vec<NewRecord>>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+
+    let rows = records.into_inner().into_iter().map(|r| r.values).collect();
+    let ids = table.insert_many(rows).map_err(ApiError::bad_request)?;
+    let records = ids.iter()
+        .map(|id| table.get_row(*id).cloned().map(Record::from))
+        .collect::<Result<Vec<_>>>()?;
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Insert, ids.len() as u64);
+    for id in ids {
+        state.publish(table_name, RowOp::Insert, Some(id));
+    }
+    Ok(Json(records))
+}
+
+/// Like `create`, but coerces string values to the column types first.
+#[post("/tables/<table_name>/records/form", data = "<record>")]
+pub async fn create_from_form(table_name: &str, record: JsonBody<FormRecord>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+
+    let values = table.coerce_row(&record.values).map_err(ApiError::bad_request)?;
+    let id = table.insert(values).map_err(ApiError::bad_request)?;
+    let record = Record::from(table.get_row(id)?.clone());
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Insert, 1);
+    state.publish(table_name, RowOp::Insert, Some(id));
+    Ok(Json(record))
+}
+
+#[put("/tables/<table_name>/records/<id>", data = "<record>")]
+pub async fn update(table_name: &str, id: &str, record: JsonBody<UpdateRecord>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    table.get_row(id).map_err(ApiError::not_found)?;
+    
+    table.update(id, record.values.clone()).map_err(ApiError::bad_request)?;
+    let record = Record::from(table.get_row(id)?.clone());
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Update, 1);
+    state.publish(table_name, RowOp::Update, Some(id));
+    Ok(Json(record))
+}
+
+/// Changes only the columns named in the body, a `{ column: value }` object.
+#[patch("/tables/<table_name>/records/<id>", data = "<changes>")]
+pub async fn update_partial(table_name: &str, id: &str, changes: JsonBody<HashMap<String, DbValue>>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    table.get_row(id).map_err(ApiError::not_found)?;
+
+    table.update_partial(id, changes.into_inner()).map_err(ApiError::bad_request)?;
+    let record = Record::from(table.get_row(id)?.clone());
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Update, 1);
+    state.publish(table_name, RowOp::Update, Some(id));
+    Ok(Json(record))
+}
+
+#[patch("/tables/<table_name>/records/<id>/columns/<column>", data = "<value>")]
+pub async fn set_cell(table_name: &str, id: &str, column: &str, value: JsonBody<DbValue>, _writable: Writable, state: &State<ApiState>) -> Result<Json<Record>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    table.get_row(id).map_err(ApiError::not_found)?;
+
+    table.set_cell(id, column, value.into_inner()).map_err(ApiError::bad_request)?;
+    let record = Record::from(table.get_row(id)?.clone());
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Update, 1);
+    state.publish(table_name, RowOp::Update, Some(id));
+    Ok(Json(record))
+}
+
+/// Moves a record to `position` (0-based, clamped to the end) in the table's
+/// display order, without changing any id; see `Table::move_row`.
+#[put("/tables/<table_name>/records/<id>/position?<position>")]
+pub async fn move_record(table_name: &str, id: &str, position: usize, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    table.move_row(id, position).map_err(ApiError::not_found)?;
+    state.save(&db)?;
+    state.publish(table_name, RowOp::Update, Some(id));
+    Ok(())
+}
+
+#[post("/tables/<table_name>/update-where", data = "<request>")]
+pub async fn update_where(table_name: &str, request: JsonBody<UpdateWhere>, _writable: Writable, state: &State<ApiState>) -> Result<Json<UpdatedCount>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+
+    let request = request.into_inner();
+    let updated = table.update_where(&request.filter, &request.set.column, request.set.value)
+        .map_err(ApiError::bad_request)?;
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Update, updated as u64);
+    if updated > 0 {
+        state.publish(table_name, RowOp::Update, None);
+    }
+    Ok(Json(UpdatedCount { updated }))
+}
+
+#[delete("/tables/<table_name>/records/<id>")]
+pub async fn delete(table_name: &str, id: &str, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    table.delete(id).map_err(ApiError::not_found)?;
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Delete, 1);
+    state.publish(table_name, RowOp::Delete, Some(id));
+    Ok(())
+}
+
+/// Deletes the rows matching every `filter` (`column:op:value`, as for
+/// `get_all`), or with no filter every row, keeping the table and its schema;
+/// ids then restart at 0. Any other query parameter is rejected with 400, so
+/// a mistyped `filter` cannot empty the table.
+#[delete("/tables/<table_name>/records?<filter>")]
+pub async fn clear_table(table_name: &str, filter: Vec<&str>, uri: &Origin<'_>, _writable: Writable, state: &State<ApiState>) -> Result<Json<DeletedCount>, ApiError> {
+    let unknown = uri.query().into_iter()
+        .flat_map(|query| query.segments())
+        .find(|(name, _)| *name != "filter");
+    if let Some((name, _)) = unknown {
+        return Err(ApiError::bad_request(format!("Unknown query parameter '{}'", name)));
+    }
+
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let deleted = if filter.is_empty() {
+        let removed = table.len();
+        table.clear();
+        removed
+    } else {
+        let predicates = filter.iter()
+            .map(|spec| ColumnPredicate::parse(spec, &table.schema))
+            .collect::<Result<Vec<_>>>()
+            .map_err(ApiError::bad_request)?;
+        table.delete_where(&predicates).map_err(ApiError::bad_request)?
+    };
+    state.save(&db)?;
+    state.metrics.record_rows(RowOp::Delete, deleted as u64);
+    if deleted > 0 {
+        state.publish(table_name, RowOp::Delete, None);
+    }
+    Ok(Json(DeletedCount { deleted }))
+}
+
+/// `epsilon` overrides the tolerance used to compare floating-point values.
+#[get("/intersection/<table1>/<table2>?<epsilon>")]
+pub async fn intersection(table1: &str, table2: &str, epsilon: Option<f64>, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table1 = db.get_table(table1).ok_or_else(|| ApiError::NotFound("Table 1 not found".to_string()))?;
+    let table2 = db.get_table(table2).ok_or_else(|| ApiError::NotFound("Table 2 not found".to_string()))?;
+    
+    let intersection = match epsilon {
+        Some(epsilon) => table1.intersection_with_epsilon(table2, epsilon),
+        None => table1.intersection(table2),
+    }.map_err(ApiError::bad_request)?;
+    let records = intersection.into_iter()
+        .map(Record::from)
+        .collect();
+    
+    Ok(Json(records))
+}
+
+#[get("/union/<table1>/<table2>")]
+pub async fn union(table1: &str, table2: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table1 = db.get_table(table1).ok_or_else(|| ApiError::NotFound("Table 1 not found".to_string()))?;
+    let table2 = db.get_table(table2).ok_or_else(|| ApiError::NotFound("Table 2 not found".to_string()))?;
+
+    let records = table1.union(table2).map_err(ApiError::bad_request)?.into_iter()
+        .map(Record::from)
+        .collect();
+
+    Ok(Json(records))
+}
+
+/// Rows of `table1` paired with the rows of `table2` whose `right` column
+/// equals their `left` column; see `Table::join`.
+#[get("/join/<table1>/<table2>?<left>&<right>")]
+pub async fn join(table1: &str, table2: &str, left: &str, right: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<TableDetails>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table1 = db.get_table(table1).ok_or_else(|| ApiError::NotFound("Table 1 not found".to_string()))?;
+    let table2 = db.get_table(table2).ok_or_else(|| ApiError::NotFound("Table 2 not found".to_string()))?;
+
+    let (schema, rows) = table1.join(table2, left, right).map_err(ApiError::bad_request)?;
+    Ok(Json(TableDetails {
+        schema,
+        rows: rows.into_iter()
+            .map(Record::from)
+            .collect(),
+    }))
+}
+
+/// Rows of `table1` that do not appear in `table2`.
+#[get("/difference/<table1>/<table2>")]
+pub async fn difference(table1: &str, table2: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<Record>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table1 = db.get_table(table1).ok_or_else(|| ApiError::NotFound("Table 1 not found".to_string()))?;
+    let table2 = db.get_table(table2).ok_or_else(|| ApiError::NotFound("Table 2 not found".to_string()))?;
+
+    let records = table1.difference(table2).map_err(ApiError::bad_request)?.into_iter()
+        .map(Record::from)
+        .collect();
+
+    Ok(Json(records))
+}
+
+/// A row tagged with the name of the table it came from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourcedRecord {
+    pub source: String,
+    pub row: Record,
+}
+
+#[get("/symmetric_difference/<table1>/<table2>")]
+pub async fn symmetric_difference(table1: &str, table2: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<SourcedRecord>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let first = db.get_table(table1).ok_or_else(|| ApiError::NotFound("Table 1 not found".to_string()))?;
+    let second = db.get_table(table2).ok_or_else(|| ApiError::NotFound("Table 2 not found".to_string()))?;
+
+    let (only_first, only_second) = first.symmetric_difference_parts(second).map_err(ApiError::bad_request)?;
+    let tag = |source: &str, rows: Vec<Row>| rows.into_iter()
+        .map(|row| SourcedRecord { source: source.to_string(), row: Record::from(row) })
+        .collect::<Vec<_>>();
+    let mut records = tag(table1, only_first);
+    records.extend(tag(table2, only_second));
+
+    Ok(Json(records))
+}
+
+/// Same as `symmetric_difference`, under the hyphenated path.
+#[get("/symmetric-difference/<table1>/<table2>")]
+pub async fn symmetric_difference_hyphenated(table1: &str, table2: &str, readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<SourcedRecord>>, ApiError> {
+    symmetric_difference(table1, table2, readable, state).await
+}
+
+/// Rows of any table holding the value in the body; see `Database::search`.
+#[post("/search", data = "<value>")]
+pub async fn search(value: JsonBody<DbValue>, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<SourcedRecord>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let records = db.search(&value).into_iter()
+        .map(|(source, row)| SourcedRecord { source, row: Record::from(row) })
+        .collect();
+    Ok(Json(records))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableList {
+    tables: Vec<String>
+}
+
+#[get("/columns/<name>/tables")]
+pub async fn tables_with_column(name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<TableList>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    Ok(Json(TableList { tables: db.tables_with_column(name) }))
+}
+
+#[get("/schema/graph")]
+pub async fn schema_graph(_readable: Readable, state: &State<ApiState>) -> Result<Json<RelationshipGraph>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    Ok(Json(db.relationship_graph()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseStats {
+    pub name: String,
+    pub table_count: usize,
+    pub total_rows: usize,
+    /// Row count per table name.
+    pub tables: BTreeMap<String, usize>,
+}
+
+#[get("/stats")]
+pub async fn stats(_readable: Readable, state: &State<ApiState>) -> Result<Json<DatabaseStats>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let tables: BTreeMap<String, usize> = db.tables.iter()
+        .map(|t| (t.name().to_string(), t.len()))
+        .collect();
+    Ok(Json(DatabaseStats {
+        name: db.name.clone(),
+        table_count: tables.len(),
+        total_rows: tables.values().sum(),
+        tables,
+    }))
+}
+
+#[get("/health")]
+pub async fn health_check() -> &'static str {
+    "OK"
+}
+
+#[get("/tables")]
+pub async fn list_tables(_readable: Readable, state: &State<ApiState>) -> Result<Json<TableList>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let tables = db.tables.iter().map(|t| t.name().to_string()).collect();
+    Ok(Json(TableList { tables }))
+}
+
+#[get("/tables/<table_name>/details")]
+pub async fn get_table_details(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<TableDetails>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    
+    Ok(Json(TableDetails {
+        schema: table.schema.clone(),
+        rows: table.get_rows().into_iter()
+            .map(Record::from)
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RowCount {
+    pub count: usize,
+}
+
+#[get("/tables/<table_name>/count")]
+pub async fn count(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<RowCount>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    Ok(Json(RowCount { count: table.len() }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    /// The first row that does not fit the schema, and why.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Re-checks every stored row against the table's schema; see
+/// `Table::validate_all`. Answers 200 either way.
+#[get("/tables/<table_name>/validate")]
+pub async fn validate_table(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<ValidationReport>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let error = table.validate_all().err().map(|e| e.to_string());
+    Ok(Json(ValidationReport { valid: error.is_none(), error }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RowExists {
+    pub exists: bool,
+}
+
+/// Answers 200 whether or not the row is there, without sending its values.
+#[get("/tables/<table_name>/records/<id>/exists")]
+pub async fn exists(table_name: &str, id: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<RowExists>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let id = id.parse::<u32>().map_err(|_| ApiError::BadRequest("Invalid ID format".to_string()))?;
+    Ok(Json(RowExists { exists: table.contains(id) }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateValue {
+    pub value: f64,
+}
+
+/// `op` is one of sum, avg, min, max or count. Nulls are skipped unless
+/// `ignore_nulls=false`; see `Table::aggregate_with`.
+#[get("/tables/<table_name>/aggregate?<column>&<op>&<ignore_nulls>")]
+pub async fn aggregate(table_name: &str, column: &str, op: &str, ignore_nulls: Option<bool>, _readable: Readable, state: &State<ApiState>) -> Result<Json<AggregateValue>, ApiError> {
+    let op = op.parse::<AggregateOp>().map_err(ApiError::bad_request)?;
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    let value = table.aggregate_with(column, op, ignore_nulls.unwrap_or(true)).map_err(ApiError::bad_request)?;
+    Ok(Json(AggregateValue { value }))
+}
+
+#[get("/tables/<table_name>/describe-stats")]
+pub async fn describe_stats(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<Vec<ColumnStats>>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    Ok(Json(table.describe()))
+}
+
+#[get("/tables/<table_name>/export/html")]
+pub async fn export_html(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<RawHtml<String>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    Ok(RawHtml(table.to_html()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableDetails {
+    schema: DbSchema,
+    rows: Vec<Record>,
+}
+
+/// A download: `body` is sent as `content_type` with a `Content-Disposition`
+/// header asking clients to save it as `filename`.
+pub struct Attachment {
+    pub filename: &'static str,
+    pub content_type: ContentType,
+    pub body: Vec<u8>,
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for Attachment {
+    fn respond_to(self, _req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        rocket::Response::build()
+            .header(self.content_type)
+            .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// The whole database as JSON, in the format `save_to_file` writes.
+#[get("/export")]
+pub async fn export(_readable: Readable, state: &State<ApiState>) -> Result<Attachment, ApiError> {
+    let mut body = Vec::new();
+    {
+        let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+        db_io::save_to_writer(&*db, &mut body)?;
+    }
+    Ok(Attachment { filename: "database.json", content_type: ContentType::JSON, body })
+}
+
+/// Replaces the whole database with the uploaded one, as produced by
+/// `/export`. Responds 400 and keeps the current database if it fails
+/// `Database::validate`. The old tables' rows count as deleted and the new
+/// ones' as inserted, in the metrics and on the change feed.
+/// Replaces the whole database with the one in the body. Empty strings in
+/// nullable columns stay as they are unless `treat_empty_as_null=true`, and
+/// short rows are rejected unless `fill_defaults=true`; see `ImportOptions`.
+#[post("/import?<treat_empty_as_null>&<fill_defaults>", data = "<database>")]
+pub async fn import(database: JsonBody<Database>, treat_empty_as_null: Option<bool>, fill_defaults: Option<bool>, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let mut database = database.into_inner();
+    let options = ImportOptions {
+        treat_empty_as_null: treat_empty_as_null.unwrap_or(false),
+        fill_defaults: fill_defaults.unwrap_or(false),
+        ..ImportOptions::default()
+    };
+    options.apply(&mut database).map_err(ApiError::bad_request)?;
+    database.validate().map_err(ApiError::bad_request)?;
+
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    state.save(&database)?;
+    let replaced = std::mem::replace(&mut *db, database);
+
+    for (tables, op) in [(&replaced.tables, RowOp::Delete), (&db.tables, RowOp::Insert)] {
+        for table in tables.iter().filter(|t| !t.is_empty()) {
+            state.metrics.record_rows(op, table.len() as u64);
+            state.publish(table.name(), op, None);
+        }
+    }
+    Ok(())
+}
+
+/// Zip archive with one file per table, as `<table>.csv` or `<table>.json`.
+/// Exports every table when `tables` (a comma-separated list) is omitted.
+#[get("/export/archive?<tables>&<format>")]
+pub async fn export_archive(tables: Option<&str>, format: Option<&str>, _readable: Readable, state: &State<ApiState>) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let format = format.unwrap_or("csv");
+    if format != "csv" && format != "json" {
+        return Err(ApiError::BadRequest(format!("Unsupported export format '{}', expected csv or json", format)));
+    }
+
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let selected = match tables {
+        Some(names) => names.split(',')
+            .map(|name| db.get_table(name.trim()).ok_or_else(|| ApiError::NotFound(format!("Table '{}' not found", name.trim()))))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => db.tables.iter().collect(),
+    };
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    for table in selected {
+        // Entry names come from table names, so refuse anything that could
+        // escape the archive root when extracted.
+        validate_identifier(table.name()).map_err(ApiError::bad_request)?;
+        if table.name().contains('\\') {
+            return Err(ApiError::BadRequest(format!("Table name '{}' cannot be used as a file name", table.name())));
+        }
+
+        archive.start_file(format!("{}.{}", table.name(), format), options).map_err(anyhow::Error::from)?;
+        match format {
+            "csv" => db_io::csv::write_table(table, &mut archive)?,
+            _ => {
+                serde_json::to_writer_pretty(&mut archive, table).map_err(anyhow::Error::from)?;
+                archive.flush().map_err(anyhow::Error::from)?;
+            }
+        }
+    }
+    let bytes = archive.finish().map_err(anyhow::Error::from)?.into_inner();
+
+    Ok((ContentType::ZIP, bytes))
+}
+
+/// Responds 404 when the table is missing, 400 for an invalid new name and
+/// 409 when the new name is taken.
+#[put("/tables/<table_name>/rename", data = "<rename>")]
+pub async fn rename_table(table_name: &str, rename: JsonBody<RenameTable>, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    validate_identifier(&rename.name).map_err(ApiError::bad_request)?;
+
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    if db.get_table(table_name).is_none() {
+        return Err(ApiError::not_found(format!("Table '{}' not found", table_name)));
+    }
+    db.rename_table(table_name, &rename.name).map_err(ApiError::conflict)?;
+    state.save(&db)?;
+    Ok(())
+}
+
+/// Only the table's columns, without transferring its rows.
+#[get("/tables/<table_name>/schema")]
+pub async fn get_schema(table_name: &str, _readable: Readable, state: &State<ApiState>) -> Result<Json<DbSchema>, ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table(table_name)
+        .ok_or_else(|| ApiError::NotFound(format!("Table '{}' not found", table_name)))?;
+    Ok(Json(table.schema.clone()))
+}
+
+/// Copies the table, rows included, under the name in the body. Responds 404
+/// when the table is missing, 400 for an invalid name and 409 when the name
+/// is taken.
+#[post("/tables/<table_name>/duplicate", data = "<duplicate>")]
+pub async fn duplicate_table(table_name: &str, duplicate: JsonBody<DuplicateTable>, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    validate_identifier(&duplicate.name).map_err(ApiError::bad_request)?;
+
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    if db.get_table(table_name).is_none() {
+        return Err(ApiError::NotFound(format!("Table '{}' not found", table_name)));
+    }
+    db.duplicate_table(table_name, &duplicate.name).map_err(ApiError::conflict)?;
+    state.save(&db)?;
+    Ok(())
+}
+
+/// Renumbers the table's rows to consecutive ids; see `Table::renumber`.
+/// Every record id and `after` cursor clients hold for the table becomes
+/// stale, so this only happens on request, never in the background.
+#[post("/tables/<table_name>/renumber")]
+pub async fn renumber(table_name: &str, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    table.renumber();
+    state.save(&db)?;
+    state.publish(table_name, RowOp::Update, None);
+    Ok(())
+}
+
+/// Migrates every row to `schema`; see `Table::alter_schema`. Responds 400
+/// when a row cannot be migrated, leaving the table unchanged.
+#[put("/tables/<table_name>/schema", data = "<schema>")]
+pub async fn alter_schema(table_name: &str, schema: JsonBody<DbSchema>, _writable: Writable, state: &State<ApiState>) -> Result<Json<DbSchema>, ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    let table = db.get_table_mut(table_name)
+        .ok_or_else(|| ApiError::not_found(format!("Table '{}' not found", table_name)))?;
+    table.alter_schema(schema.into_inner()).map_err(ApiError::bad_request)?;
+    let schema = table.schema.clone();
+    state.save(&db)?;
+    Ok(Json(schema))
+}
+
+#[delete("/tables/<table_name>")]
+pub async fn delete_table(table_name: &str, _writable: Writable, state: &State<ApiState>) -> Result<(), ApiError> {
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    db.delete_table(table_name).ok_or_else(|| ApiError::NotFound("Table not found".to_string()))?;
+    state.save(&db)?;
+    Ok(())
+}
+
+/// Applies the operations in order to a copy of the database. The copy
+/// replaces the live database and is saved only if every operation succeeds;
+/// otherwise nothing changes and the index of the failing operation is returned.
+#[post("/transaction", data = "<operations>")]
+pub async fn transaction(operations: JsonBody<Vec<Operation>>, _writable: Writable, state: &State<ApiState>) -> Result<Json<TransactionApplied>, status::Custom<Json<TransactionError>>> {
+    let internal = |e: anyhow::Error| status::Custom(
+        Status::InternalServerError,
+        Json(TransactionError { error: e.to_string(), index: None }),
+    );
+
+    let mut db = state.db.lock().map_err(|_| internal(anyhow!("Failed to lock database")))?;
+    let mut staged = db.clone();
+
+    let mut ids = Vec::with_capacity(operations.len());
+    for (index, operation) in operations.iter().enumerate() {
+        let id = operation.apply(&mut staged).map_err(|e| status::Custom(
+            Status::BadRequest,
+            Json(TransactionError { error: e.to_string(), index: Some(index) }),
+        ))?;
+        ids.push(id);
+    }
+
+    state.save(&staged).map_err(internal)?;
+    *db = staged;
+    for (operation, id) in operations.iter().zip(ids) {
+        state.metrics.record_rows(operation.kind(), 1);
+        state.publish(operation.table(), operation.kind(), Some(id));
+    }
+    Ok(Json(TransactionApplied { applied: operations.len() }))
+}
+
+/// Streams the table's row changes as server-sent events until the client
+/// disconnects or the server shuts down. Dropping the stream drops its
+/// channel subscription.
+#[get("/tables/<table_name>/events")]
+pub async fn table_events(table_name: &str, _readable: Readable, state: &State<ApiState>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut changes = state.changes.subscribe();
+    let table_name = table_name.to_string();
+
+    EventStream! {
+        loop {
+            let event = tokio::select! {
+                event = changes.recv() => match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+            if event.table == table_name {
+                yield Event::json(&event);
+            }
+        }
+    }
+}
+
+#[get("/metrics")]
+pub async fn metrics(_readable: Readable, state: &State<ApiState>) -> Result<(ContentType, String), ApiError> {
+    let db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    Ok((ContentType::Plain, state.metrics.render(&db)))
+}
+
+/// Blocks writes until `/admin/unlock`; reads keep working.
+#[post("/admin/lock")]
+pub async fn lock(_key: ApiKey, state: &State<ApiState>) {
+    state.locked.store(true, Ordering::SeqCst);
+}
+
+#[post("/admin/unlock")]
+pub async fn unlock(_key: ApiKey, state: &State<ApiState>) {
+    state.locked.store(false, Ordering::SeqCst);
+}
+
+/// Replaces the in-memory database with the current contents of the database
+/// file. The running state is left untouched if the file cannot be parsed or
+/// holds rows that do not fit their table's schema. A file edited outside the
+/// server fails its checksum until its `.sha256` sidecar is deleted.
+#[post("/admin/reload")]
+pub async fn reload(_key: ApiKey, state: &State<ApiState>) -> Result<(), ApiError> {
+    let reloaded: Database = load_from_file_compressed(&state.db_path)
+        .map_err(|e| anyhow!("Failed to reload database: {}", e))?;
+    reloaded.validate().map_err(|e| anyhow!("Failed to reload database: {}", e))?;
+    let mut db = state.db.lock().map_err(|_| ApiError::Internal(anyhow!("Failed to lock database")))?;
+    *db = reloaded;
+    Ok(())
+}
+
+/// Loads the database at `path`, creating and saving an empty one only when
+/// the file does not exist. Any other failure, a checksum mismatch included,
+/// is returned: starting empty would overwrite the file on the next save. A
+/// file failing `Database::validate` is refused too, as `reload` does.
+pub fn load_database(path: &str) -> Result<Database> {
+    match fs::metadata(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let db = Database::new(path);
+            persist(&db, path).unwrap_or_default();
+            Ok(db)
+        }
+        _ => {
+            let db: Database = load_from_file_compressed(path)
+                .map_err(|e| anyhow!("Failed to load database from '{}': {}", path, e))?;
+            db.validate().map_err(|e| anyhow!("Failed to load database from '{}': {}", path, e))?;
+            Ok(db)
+        }
+    }
+}
+
+/// Fails when the database file exists but cannot be loaded or is invalid;
+/// see `load_database`.
+pub fn rocket() -> Result<rocket::Rocket<rocket::Build>> {
+    // Built first, as this installs the logger the messages below go to
+    let mut rocket = rocket::build();
+    let db_path = env::var("DATABASE_FILE").unwrap_or_else(|_| "database.db".to_string());
+    let db = load_database(&db_path)?;
+    let db = Arc::new(Mutex::new(db));
+    let mut state = ApiState::new(db.clone(), db_path.clone());
+    state.api_key = api_key_from_env();
+    state.require_auth = require_auth_from_env();
+    match (&state.api_key, state.require_auth) {
+        (None, true) => rocket::warn!("REQUIRE_AUTH is set but API_KEY is not; authentication disabled"),
+        (None, false) => rocket::info!("API_KEY not set; authentication disabled"),
+        (Some(_), _) => {}
+    }
+    
+    let cors = cors().to_cors().expect("Failed to create CORS fairing");
+
+    match autosave_interval_from_env() {
+        Some(period) => {
+            rocket::info!("Autosaving every {}s", period.as_secs());
+            let (db, db_path, dirty) = (db.clone(), db_path.clone(), state.dirty.clone());
+            rocket = rocket.attach(AdHoc::on_liftoff("Autosave", move |rocket| Box::pin(async move {
+                tokio::spawn(start_autosave(db, db_path, dirty, period, rocket.shutdown()));
+            })));
+        }
+        None => rocket::info!("Autosave disabled"),
+    }
+    if let Some(config) = CompactionConfig::from_env() {
+        rocket = rocket.attach(AdHoc::on_liftoff("Compaction", move |rocket| Box::pin(async move {
+            tokio::spawn(start_compaction(db, config, rocket.shutdown()));
+        })));
+    }
+    
+    Ok(rocket
+        .attach(cors)
+        .mount("/", routes![health_check])
+        .mount("/api", routes![
+            list_tables,
+            create_table,
+            delete_table,
+            get_table_details,
+            export_html,
+            get_all,
+            get_by_id,
+            create,
+            update,
+            set_cell,
+            move_record,
+            update_where,
+            delete,
+            intersection,
+            symmetric_difference,
+            symmetric_difference_hyphenated,
+            transaction,
+            tables_with_column,
+            reload,
+            lock,
+            unlock,
+            metrics,
+            schema_graph,
+            export_archive,
+            describe_stats,
+            table_events,
+            create_from_form,
+            union,
+            difference,
+            count,
+            rename_table,
+            alter_schema,
+            create_batch,
+            get_distinct,
+            aggregate,
+            join,
+            get_schema,
+            update_partial,
+            search,
+            export,
+            import,
+            duplicate_table,
+            stats,
+            clear_table,
+            exists,
+            stream_records,
+            validate_table,
+            sample_records,
+            renumber,
+        ])
+        .register("/", catchers![bad_request, locked, unauthorized])
+        .attach(request_metrics())
+        .manage(state))
+}
+
+pub async fn run_server() -> Result<()> {
+    dotenv().ok();
+    rocket()?
+        .launch()
+        .await
+        .map_err(|e| anyhow!("Rocket server error: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::{Client, LocalResponse};
+    use rocket::http::{Status, ContentType};
+    use db_core::types::schema::{DbSchema, DbColumn, DbColumnType};
+
+    /// A client whose database file lives in a temporary directory that is
+    /// removed along with it.
+    struct TestClient {
+        client: Client,
+        _dir: tempfile::TempDir,
+    }
+
+    impl std::ops::Deref for TestClient {
+        type Target = Client;
+
+        fn deref(&self) -> &Client {
+            &self.client
+        }
+    }
+
+    fn create_test_client() -> TestClient {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        TestClient { client: create_test_client_at(path.to_str().unwrap()), _dir: dir }
+    }
+
+    /// Reads the records out of a `get_all` page.
+    fn page_records(response: LocalResponse<'_>) -> Vec<Record> {
+        match serde_json::from_str(&response.into_string().unwrap()).unwrap() {
+            RecordList::Page { records, .. } => records,
+            other => panic!("expected a page of records, got {:?}", other),
+        }
+    }
+
+    fn create_test_client_at(db_path: &str) -> Client {
+        Client::tracked(create_test_rocket(db_path)).expect("valid rocket instance")
+    }
+
+    fn create_test_rocket(db_path: &str) -> rocket::Rocket<rocket::Build> {
+        let db = Arc::new(Mutex::new(Database::new(db_path)));
+        create_test_rocket_with(ApiState::new(db, db_path.to_string()))
+    }
+
+    fn create_test_rocket_with(state: ApiState) -> rocket::Rocket<rocket::Build> {
+        rocket::build()
+            .mount("/", routes![health_check])
+            .mount("/api", routes![
+                create_table,
+                delete_table,
+                get_all,
+                get_by_id,
+                create,
+                update,
+                set_cell,
+                move_record,
+                update_where,
+                delete,
+                intersection,
+                symmetric_difference,
+                symmetric_difference_hyphenated,
+                transaction,
+                tables_with_column,
+                list_tables,
+                export_html,
+                reload,
+                lock,
+                unlock,
+                metrics,
+                schema_graph,
+                export_archive,
+                describe_stats,
+                table_events,
+                create_from_form,
+                union,
+                difference,
+                count,
+                rename_table,
+                alter_schema,
+                create_batch,
+                get_distinct,
+                aggregate,
+                join,
+                get_schema,
+                update_partial,
+                search,
+                export,
+                import,
+                duplicate_table,
+                stats,
+                clear_table,
+                exists,
+                stream_records,
+                validate_table,
+                sample_records,
+                renumber,
+            ])
+            .register("/", catchers![bad_request, locked, unauthorized])
+            .attach(request_metrics())
+            .manage(state)
+    }
+
+    fn create_test_schema() -> DbSchema {
+        DbSchema {
+            columns: vec![
+                DbColumn {
+                    name: "id".to_string(),
+                    column_type: DbColumnType::Integer,
+                    ..Default::default()
+                },
+                DbColumn {
+                    name: "name".to_string(),
+                    column_type: DbColumnType::String,
+                    ..Default::default()
+                },
+                DbColumn {
+                    name: "balance".to_string(),
+                    column_type: DbColumnType::Money,
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    fn create_test_record() -> Record {
+        Record {
+            id: "0".to_string(),
+            values: vec![
+                DbValue::Integer(1),
+                DbValue::String("John Doe".to_string()),
+                DbValue::Money(1000.0),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_create_table() {
+        let client = create_test_client();
+        let schema = create_test_schema();
+        
+        let response = client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_create_and_get_record() {
+        let client = create_test_client();
+        
+        // First create a table
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        // Create a record
+        let record = create_test_record();
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        // Get the record back
+        let response = client.get("/api/tables/test_table/records/0")
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        let retrieved_record: Record = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        
+        assert_eq!(retrieved_record.values, record.values);
+    }
+
+    #[test]
+    fn test_update_record() {
+        let client = create_test_client();
+        
+        // Create table and initial record
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        let record = create_test_record();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+            
+        // Update the record
+        let mut updated_record = record.clone();
+        updated_record.values = vec![
+            DbValue::Integer(1),
+            DbValue::String("Jane Doe".to_string()),
+            DbValue::Money(2000.0),
+        ];
+        
+        let response = client.put("/api/tables/test_table/records/0")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&updated_record).unwrap())
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        // Verify the update
+        let response = client.get("/api/tables/test_table/records/0")
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        let retrieved_record: Record = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        
+        assert_eq!(retrieved_record.values, updated_record.values);
+        assert!(retrieved_record.created_at > 0);
+        assert!(retrieved_record.updated_at >= retrieved_record.created_at);
+    }
+
+    #[test]
+    fn test_update_partial() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let response = client.patch("/api/tables/test_table/records/0")
+            .header(ContentType::JSON)
+            .body(r#"{"balance": {"Money": 250.0}}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(record.values, vec![
+            DbValue::Integer(1),
+            DbValue::String("John Doe".to_string()),
+            DbValue::Money(250.0),
+        ]);
+
+        let response = client.patch("/api/tables/test_table/records/0")
+            .header(ContentType::JSON)
+            .body(r#"{"name": {"Integer": 5}}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.patch("/api/tables/test_table/records/9")
+            .header(ContentType::JSON)
+            .body(r#"{"name": {"String": "x"}}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_delete_record() {
+        let client = create_test_client();
+        
+        // Create table and record
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        let record = create_test_record();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+            
+        // Delete the record
+        let response = client.delete("/api/tables/test_table/records/0")
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        // Verify deletion
+        let response = client.get("/api/tables/test_table/records/0")
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_create_batch() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+
+        let batch: Vec<NewRecord> = (1..=3)
+            .map(|i| NewRecord { values: vec![
+                DbValue::Integer(i),
+                DbValue::String(format!("User {}", i)),
+                DbValue::Money(100.0 * i as f64),
+            ] })
+            .collect();
+        let response = client.post("/api/tables/test_table/records/batch")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&batch).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let records: Vec<Record> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let ids: Vec<&str> = records.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["0", "1", "2"]);
+
+        let mut bad_batch = batch;
+        bad_batch[1].values[0] = DbValue::String("not an integer".to_string());
+        let response = client.post("/api/tables/test_table/records/batch")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&bad_batch).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client.get("/api/tables/test_table/count").dispatch();
+        let count: RowCount = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(count.count, 3);
+    }
+
+    #[test]
+    fn test_get_distinct() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+
+        let mut other = create_test_record();
+        other.values[1] = DbValue::String("Jane Doe".to_string());
+        for record in [create_test_record(), other, create_test_record()] {
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/tables/test_table/records/distinct").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let records: Vec<Record> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let ids: Vec<&str> = records.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["0", "1"]);
+
+        let response = client.get("/api/tables/test_table/records/2").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let client = create_test_client();
+        let mut schema = create_test_schema();
+        schema.columns[2].nullable = true;
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        for balance in [DbValue::Money(100.0), DbValue::Money(300.0), DbValue::Null] {
+            let mut record = create_test_record();
+            record.values[2] = balance;
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let aggregate = |query: &str| {
+            let response = client.get(format!("/api/tables/test_table/aggregate?{}", query)).dispatch();
+            let status = response.status();
+            (status, response.into_string().unwrap())
+        };
+        let (status, body) = aggregate("column=balance&op=avg");
+        assert_eq!(status, Status::Ok);
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 200.0);
+
+        let (_, body) = aggregate("column=balance&op=avg&ignore_nulls=false");
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 400.0 / 3.0);
+
+        let (_, body) = aggregate("column=balance&op=count");
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 2.0);
+        let (_, body) = aggregate("column=balance&op=count&ignore_nulls=false");
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 3.0);
+        let (_, body) = aggregate("column=balance&op=sum&ignore_nulls=false");
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 400.0);
+
+        let (_, body) = aggregate("column=name&op=count");
+        assert_eq!(serde_json::from_str::<AggregateValue>(&body).unwrap().value, 3.0);
+
+        assert_eq!(aggregate("column=name&op=sum").0, Status::BadRequest);
+        assert_eq!(aggregate("column=balance&op=median").0, Status::BadRequest);
+    }
+
+    #[test]
+    fn test_stream_records() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for _ in 0..(STREAM_CHUNK_SIZE + 3) {
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/tables/test_table/records?limit=1000").dispatch();
+        let mut expected = page_records(response);
+        expected.sort_by_key(|r| r.id.parse::<u32>().unwrap());
+
+        let response = client.get("/api/tables/test_table/records/stream").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+        let body = response.into_string().unwrap();
+        let streamed: Vec<Record> = body.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(streamed, expected);
+
+        assert_eq!(client.get("/api/tables/missing/records/stream").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_exists() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        let response = client.get(format!("/api/tables/test_table/records/{}/exists", record.id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"exists":true}"#);
+
+        let response = client.get("/api/tables/test_table/records/99/exists").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"exists":false}"#);
+
+        assert_eq!(client.get("/api/tables/test_table/records/abc/exists").dispatch().status(), Status::BadRequest);
+        assert_eq!(client.get("/api/tables/missing/records/0/exists").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_clear_table() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for _ in 0..3 {
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let response = client.delete("/api/tables/test_table/records?filtr=id:eq:1").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(client.get("/api/tables/test_table/count").dispatch().into_string().unwrap(), r#"{"count":3}"#);
+
+        let response = client.delete("/api/tables/test_table/records").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"deleted":3}"#);
+        assert_eq!(client.get("/api/tables/test_table/count").dispatch().into_string().unwrap(), r#"{"count":0}"#);
+
+        let response = client.get("/api/tables/test_table/schema").dispatch();
+        let schema: DbSchema = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(schema, create_test_schema());
+
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(record.id, "0");
+
+        assert_eq!(client.delete("/api/tables/missing/records").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_error_statuses() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/tables/test_table/records/abc").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(body.error, "Invalid ID format");
+
+        let response = client.delete("/api/tables/test_table/records/-1").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client.get("/api/tables/missing/records/0").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.put("/api/tables/test_table/records/7")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let client = create_test_client();
+        
+        // Create two tables with the same schema
+        let schema = create_test_schema();
+        client.post("/api/tables/table1")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        client.post("/api/tables/table2")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        // Add same record to both tables
+        let record = create_test_record();
+        client.post("/api/tables/table1/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+            
+        client.post("/api/tables/table2/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+            
+        // Add different record to table2
+        let mut different_record = record.clone();
+        different_record.values = vec![
+            DbValue::Integer(2),
+            DbValue::String("Jane Doe".to_string()),
+            DbValue::Money(2000.0),
+        ];
+        
+        client.post("/api/tables/table2/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&different_record).unwrap())
+            .dispatch();
+            
+        // Get intersection
+        let response = client.get("/api/intersection/table1/table2")
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::Ok);
+        
+        let intersection: Vec<Record> = serde_json::from_str(
+            &response.into_string().unwrap()
+        ).unwrap();
+        
+        assert_eq!(intersection.len(), 1);
+        assert_eq!(intersection[0].values, record.values);
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let client = create_test_client();
+        
+        // Create table
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+            
+        // Test wrong number of values
+        let mut invalid_record = create_test_record();
+        invalid_record.values.pop();
+        
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&invalid_record).unwrap())
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::BadRequest);
+        
+        // Test wrong value type
+        let mut invalid_record = create_test_record();
+        invalid_record.values[0] = DbValue::String("not an integer".to_string());
+        
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&invalid_record).unwrap())
+            .dispatch();
+            
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_update_where() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        for (i, balance) in [500.0, 1500.0, 2500.0].into_iter().enumerate() {
+            let record = NewRecord {
+                values: vec![
+                    DbValue::Integer(i as i32),
+                    DbValue::String("active".to_string()),
+                    DbValue::Money(balance),
+                ],
+            };
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.post("/api/tables/test_table/update-where")
+            .header(ContentType::JSON)
+            .body(r#"{
+                "filter": [{"column": "balance", "op": "gt", "value": {"Money": 1000.0}}],
+                "set": {"column": "name", "value": {"String": "premium"}}
+            }"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let count: UpdatedCount = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(count.updated, 2);
+
+        let response = client.get("/api/tables/test_table/records/0").dispatch();
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(record.values[1], DbValue::String("active".to_string()));
+    }
+
+    #[test]
+    fn test_malformed_json_body() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(r#"{"oops":1}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(body.error.contains("missing field `values`"), "{}", body.error);
+
+        let response = client.put("/api/tables/test_table/records/0")
+            .header(ContentType::JSON)
+            .body("{not json")
+            .dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let path = path.to_str().unwrap();
+        let client = create_test_client_at(path);
+
+        let mut db = Database::new("restored");
+        db.add_table(db_core::types::table::Table::new("restored_table".to_string(), create_test_schema())).unwrap();
+        save_to_file(&db, path).unwrap();
+
+        let response = client.post("/api/admin/reload").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/api/tables").dispatch();
+        assert!(response.into_string().unwrap().contains("restored_table"));
+
+        // An edit made outside the server no longer matches the checksum
+        // sidecar; the error says how to accept it
+        let mut edited = db.clone();
+        edited.add_table(db_core::types::table::Table::new("edited_table".to_string(), create_test_schema())).unwrap();
+        fs::write(path, serde_json::to_string(&edited).unwrap()).unwrap();
+        let response = client.post("/api/admin/reload").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        let sidecar = db_io::checksum_path(std::path::Path::new(path));
+        assert!(response.into_string().unwrap().contains(&format!("delete or regenerate '{}'", sidecar.display())));
+
+        fs::remove_file(&sidecar).unwrap();
+        let response = client.post("/api/admin/reload").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/api/tables").dispatch();
+        assert!(response.into_string().unwrap().contains("edited_table"));
+
+        // A corrupt file is rejected and the loaded state is kept
+        fs::write(path, "{ not a database").unwrap();
+        let response = client.post("/api/admin/reload").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+
+        let response = client.get("/api/tables").dispatch();
+        assert!(response.into_string().unwrap().contains("restored_table"));
+
+        // So is a file with a row that does not fit its schema
+        let table = db.get_table_mut("restored_table").unwrap();
+        let id = table.insert(vec![DbValue::Integer(1), DbValue::String("a".to_string()), DbValue::Money(1.0)]).unwrap();
+        table.get_row_mut(id).unwrap().values[2] = DbValue::Integer(1);
+        save_to_file(&db, path).unwrap();
+        let response = client.post("/api/admin/reload").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        assert_eq!(client.get("/api/tables/restored_table/count").dispatch().into_string().unwrap(), r#"{"count":0}"#);
+    }
+
+    #[test]
+    fn test_load_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let path = path.to_str().unwrap();
+
+        // A missing file starts an empty database and saves it
+        assert!(load_database(path).unwrap().tables.is_empty());
+        assert!(fs::metadata(path).is_ok());
+
+        let mut db = Database::new("kept");
+        db.add_table(db_core::types::table::Table::new("kept_table".to_string(), create_test_schema())).unwrap();
+        save_to_file(&db, path).unwrap();
+        assert!(load_database(path).unwrap().get_table("kept_table").is_some());
+
+        // A file holding a row that does not fit its schema is refused
+        let table = db.get_table_mut("kept_table").unwrap();
+        let id = table.insert(create_test_record().values).unwrap();
+        table.get_row_mut(id).unwrap().values[2] = DbValue::Integer(1);
+        save_to_file(&db, path).unwrap();
+        let err = load_database(path).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to load database from"), "{}", err);
+
+        // A file failing its checksum is an error and is left as it is
+        let mut contents = fs::read_to_string(path).unwrap();
+        contents.push(' ');
+        fs::write(path, &contents).unwrap();
+        let err = load_database(path).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+        assert_eq!(fs::read_to_string(path).unwrap(), contents);
+    }
+
+    /// A client whose API requires the key `secret`, with the directory its
+    /// saves land in.
+    fn create_auth_client(require_auth: bool) -> (Client, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json").to_str().unwrap().to_string();
+        let mut state = ApiState::new(Arc::new(Mutex::new(Database::new("auth"))), path);
+        state.api_key = Some("secret".to_string());
+        state.require_auth = require_auth;
+        (Client::tracked(create_test_rocket_with(state)).unwrap(), dir)
+    }
+
+    #[test]
+    fn test_api_key() {
+        let (client, _dir) = create_auth_client(false);
+        let create = |key: Option<&str>| {
+            let mut request = client.post("/api/tables/test_table")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap());
+            if let Some(key) = key {
+                request = request.header(rocket::http::Header::new("X-API-Key", key.to_string()));
+            }
+            request.dispatch()
+        };
+
+        let response = create(None);
+        assert_eq!(response.status(), Status::Unauthorized);
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.error, UNAUTHORIZED);
+        assert_eq!(create(Some("wrong")).status(), Status::Unauthorized);
+        assert_eq!(client.post("/api/admin/lock").dispatch().status(), Status::Unauthorized);
+        assert_eq!(client.delete("/api/tables/test_table").dispatch().status(), Status::Unauthorized);
+
+        assert_eq!(create(Some("secret")).status(), Status::Ok);
+        // Reads stay open unless REQUIRE_AUTH is set.
+        assert_eq!(client.get("/api/tables/test_table/count").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_api_key_required_for_reads() {
+        let (client, _dir) = create_auth_client(true);
+        let key = rocket::http::Header::new("X-API-Key", "secret");
+
+        let response = client.get("/api/tables").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.error, UNAUTHORIZED);
+
+        assert_eq!(client.get("/api/tables").header(key).dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/health").dispatch().status(), Status::Ok);
+
+        // The guard fails before the handler runs: no 404 for a missing
+        // table, no export attachment and no event subscription.
+        assert_eq!(client.get("/api/tables/missing/count").dispatch().status(), Status::Unauthorized);
+        let response = client.get("/api/export").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(response.headers().get_one("Content-Disposition").is_none());
+        let response = client.get("/api/tables/watched/events").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let state = client.rocket().state::<ApiState>().unwrap();
+        assert_eq!(state.changes.receiver_count(), 0);
+    }
+
+    #[test]
+    fn test_api_key_disabled() {
+        let mut state = ApiState::new(Arc::new(Mutex::new(Database::new("open"))), "test.db".to_string());
+        state.require_auth = true;
+        assert!(state.authorized(None));
+        assert!(state.authorized(Some("anything")));
+
+        let client = create_test_client();
+        let response = client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.post("/api/admin/lock").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_sample_records() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for _ in 0..3 {
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/tables/test_table/records/sample?n=2").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let records: Vec<Record> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let response = client.get("/api/tables/test_table/records/sample").dispatch();
+        let records: Vec<Record> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(records.len(), 3);
+
+        let response = client.get("/api/tables/missing/records/sample").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_validate_table() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        let response = client.get("/api/tables/test_table/validate").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"valid":true}"#);
+
+        {
+            let state = client.rocket().state::<ApiState>().unwrap();
+            let mut db = state.db.lock().unwrap();
+            let id = record.id.parse().unwrap();
+            db.get_table_mut("test_table").unwrap().get_row_mut(id).unwrap().values[1] = DbValue::Boolean(true);
+        }
+        let response = client.get("/api/tables/test_table/validate").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let report: ValidationReport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.error.unwrap(), format!("Row {}: Value type does not match schema type", record.id));
+
+        assert_eq!(client.get("/api/tables/missing/validate").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_export_html() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let mut record = create_test_record();
+        record.values[1] = DbValue::String("<script>".to_string());
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/tables/test_table/export/html").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+
+        let html = response.into_string().unwrap();
+        assert!(html.contains("<th>balance</th>"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn test_set_cell() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let record = create_test_record();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+
+        let response = client.patch("/api/tables/test_table/records/0/columns/balance")
+            .header(ContentType::JSON)
+            .body(r#"{"Money": 2500.0}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let updated: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(updated.values, vec![
+            DbValue::Integer(1),
+            DbValue::String("John Doe".to_string()),
+            DbValue::Money(2500.0),
+        ]);
+
+        let response = client.patch("/api/tables/test_table/records/0/columns/balance")
+            .header(ContentType::JSON)
+            .body(r#"{"String": "lots"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_create_on_conflict() {
+        let client = create_test_client();
+
+        let mut schema = create_test_schema();
+        schema.columns[0].unique = true;
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let record = create_test_record();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+
+        let mut duplicate = record.clone();
+        duplicate.values[1] = DbValue::String("Jane Doe".to_string());
+        let body = serde_json::to_string(&duplicate).unwrap();
+
+        let response = client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(&body)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let state = client.rocket().state::<ApiState>().unwrap();
+        let mut changes = state.changes.subscribe();
+
+        let response = client.post("/api/tables/test_table/records?on_conflict=ignore")
+            .header(ContentType::JSON)
+            .body(&body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let ignored: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(ignored.id, "0");
+        assert_eq!(ignored.values, record.values);
+        assert!(changes.try_recv().is_err());
+
+        let response = client.post("/api/tables/test_table/records?on_conflict=replace")
+            .header(ContentType::JSON)
+            .body(&body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let replaced: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(replaced.id, "0");
+        assert_eq!(replaced.values, duplicate.values);
+        assert_eq!(changes.try_recv().unwrap(), ChangeEvent { table: "test_table".to_string(), op: RowOp::Update, id: Some(0) });
+        assert!(changes.try_recv().is_err());
+
+        let body = client.get("/api/metrics").dispatch().into_string().unwrap();
+        assert!(body.contains("db_row_operations_total{op=\"insert\"} 1"), "{}", body);
+        assert!(body.contains("db_row_operations_total{op=\"update\"} 1"), "{}", body);
+    }
+
+    #[test]
+    fn test_compaction_config() {
+        assert_eq!(CompactionConfig::parse(None, Some("0.2")), None);
+        assert_eq!(CompactionConfig::parse(Some("0"), None), None);
+        assert_eq!(CompactionConfig::parse(Some("60"), None), Some(CompactionConfig {
+            interval: Duration::from_secs(60),
+            threshold: CompactionConfig::DEFAULT_THRESHOLD,
+        }));
+        assert_eq!(CompactionConfig::parse(Some("5"), Some("0.25")).unwrap().threshold, 0.25);
+    }
+
+    #[test]
+    fn test_autosave_interval() {
+        assert_eq!(parse_autosave_interval(Some("0")), None);
+        assert_eq!(parse_autosave_interval(Some("120")), Some(Duration::from_secs(120)));
+        assert_eq!(parse_autosave_interval(Some(" 5 ")), Some(Duration::from_secs(5)));
+        assert_eq!(parse_autosave_interval(None), Some(DEFAULT_AUTOSAVE_INTERVAL));
+        assert_eq!(parse_autosave_interval(Some("soon")), Some(DEFAULT_AUTOSAVE_INTERVAL));
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        let open = cors_from(None);
+        assert!(open.allowed_origins.is_all());
+        assert!(!open.allow_credentials);
+        assert!(cors_from(Some(" , ")).allowed_origins.is_all());
+
+        let restricted = cors_from(Some("https://a.example, https://b.example"));
+        assert_eq!(
+            restricted.allowed_origins,
+            AllowedOrigins::some_exact(&["https://a.example", "https://b.example"])
+        );
+        assert!(restricted.allow_credentials);
+        restricted.to_cors().unwrap();
+    }
+
+    #[test]
+    fn test_cors_preflight_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let rocket = create_test_rocket(path.to_str().unwrap()).attach(cors_from(None).to_cors().unwrap());
+        let client = Client::tracked(rocket).unwrap();
+
+        for method in ["PATCH", "PUT", "DELETE"] {
+            let response = client.options("/api/tables/t/records/0")
+                .header(rocket::http::Header::new("Origin", "https://a.example"))
+                .header(rocket::http::Header::new("Access-Control-Request-Method", method))
+                .dispatch();
+            assert!(response.status().class().is_success(), "{}: {}", method, response.status());
+            let allowed = response.headers().get_one("Access-Control-Allow-Methods").unwrap_or_default();
+            assert!(allowed.contains(method), "{}: {}", method, allowed);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_autosave_persists_and_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json").to_str().unwrap().to_string();
+        let db = Arc::new(Mutex::new(Database::new("autosaved")));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+        let dirty = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(start_autosave(db.clone(), path.clone(), dirty, Duration::from_millis(20), async {
+            stopped.await.ok();
+        }));
+
+        let table = db_core::types::table::Table::new("unsaved".to_string(), create_test_schema());
+        db.lock().unwrap().add_table(table).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let saved: Database = load_from_file_compressed(&path).unwrap();
+        assert!(saved.get_table("unsaved").is_some());
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), task).await
+            .expect("autosave to stop on shutdown")
+            .unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn test_persist_with_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let path = missing.join("db.json").to_str().unwrap().to_string();
+        let db = Arc::new(Mutex::new(Database::new("retried")));
+        let dirty = Arc::new(AtomicBool::new(true));
+
+        let policy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(200) };
+        let task = tokio::spawn({
+            let (db, path, dirty) = (db.clone(), path.clone(), dirty.clone());
+            async move { persist_with_retry(&db, &path, &policy, &dirty).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // The first attempt failed; the lock is free while the retry waits.
+        assert!(db.try_lock().is_ok());
+        fs::create_dir(&missing).unwrap();
+        task.await.unwrap().unwrap();
+        assert!(!dirty.load(Ordering::SeqCst));
+        assert!(fs::metadata(&path).is_ok());
+
+        let unwritable = dir.path().join("absent").join("db.json");
+        let policy = RetryPolicy { attempts: 2, base_delay: Duration::from_millis(1) };
+        let err = persist_with_retry(&db, unwritable.to_str().unwrap(), &policy, &dirty).await.unwrap_err();
+        assert!(err.to_string().starts_with("Giving up after 2 attempts"), "{}", err);
+        assert!(dirty.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_failed_save_retried_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let client = create_test_client_at(missing.join("db.json").to_str().unwrap());
+
+        let response = client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        let state = client.rocket().state::<ApiState>().unwrap();
+        assert!(state.dirty.load(Ordering::SeqCst));
+
+        // The default policy retries after 100ms and 200ms.
+        fs::create_dir(&missing).unwrap();
+        std::thread::sleep(Duration::from_millis(600));
+        assert!(!state.dirty.load(Ordering::SeqCst));
+        let saved: Database = load_from_file_compressed(missing.join("db.json").to_str().unwrap()).unwrap();
+        assert!(saved.get_table("test_table").is_some());
+    }
+
+    #[test]
+    fn test_compact_tables() {
+        let mut table = db_core::types::table::Table::new("test_table".to_string(), create_test_schema());
+        for _ in 0..100 {
+            table.insert(create_test_record().values).unwrap();
+        }
+        table.delete(0).unwrap();
+        let mut db = Database::new("test_db");
+        db.add_table(table).unwrap();
+        let db = Mutex::new(db);
+
+        assert!(compact_tables(&db, 0.5).unwrap().is_empty());
+
+        for id in 1..90 {
+            db.lock().unwrap().get_table_mut("test_table").unwrap().delete(id).unwrap();
+        }
+        assert_eq!(compact_tables(&db, 0.5).unwrap(), vec!["test_table".to_string()]);
+
+        let db = db.lock().unwrap();
+        let table = db.get_table("test_table").unwrap();
+        assert_eq!(table.rows_after(None, 1)[0].id, 90);
+        assert_eq!(table.index, 100);
+    }
+
+    #[rocket::async_test]
+    async fn test_compaction_stops() {
+        let db = Arc::new(Mutex::new(Database::new("compacted")));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let config = CompactionConfig { interval: Duration::from_millis(20), threshold: 0.5 };
+        let task = tokio::spawn(start_compaction(db, config, async {
+            stopped.await.ok();
+        }));
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), task).await
+            .expect("compaction to stop on shutdown")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_renumber() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for _ in 0..3 {
+            client.post("/api/tables/test_table/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+        client.delete("/api/tables/test_table/records/0").dispatch();
+
+        let response = client.post("/api/tables/test_table/renumber").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/test_table/records/0").dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/test_table/records/2").dispatch().status(), Status::NotFound);
+
+        assert_eq!(client.post("/api/tables/missing/renumber").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_create_table_duplicate_name() {
+        let client = create_test_client();
+
+        let create = || client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        assert_eq!(create().status(), Status::Ok);
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let response = create();
+        assert_eq!(response.status(), Status::Conflict);
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.error, "Table 'test_table' already exists");
+
+        let response = client.get("/api/tables/test_table/records").dispatch();
+        let records = page_records(response);
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let client = create_test_client();
+
+        for table in ["people", "staff"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+        }
+        client.post("/api/tables/people/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let rename = |table: &str, name: &str| client.put(format!("/api/tables/{}/rename", table))
+            .header(ContentType::JSON)
+            .body(serde_json::json!({ "name": name }).to_string())
+            .dispatch()
+            .status();
+
+        assert_eq!(rename("people", "customers"), Status::Ok);
+        let response = client.get("/api/tables/customers/count").dispatch();
+        assert_eq!(serde_json::from_str::<RowCount>(&response.into_string().unwrap()).unwrap().count, 1);
+
+        assert_eq!(rename("customers", "staff"), Status::Conflict);
+        assert_eq!(rename("people", "other"), Status::NotFound);
+        assert_eq!(rename("customers", "a/b"), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_duplicate_table() {
+        let client = create_test_client();
+        client.post("/api/tables/original")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        client.post("/api/tables/original/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let duplicate = |source: &str, name: &str| {
+            client.post(format!("/api/tables/{}/duplicate", source))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&DuplicateTable { name: name.to_string() }).unwrap())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(duplicate("original", "copy"), Status::Ok);
+        assert_eq!(duplicate("original", "copy"), Status::Conflict);
+        assert_eq!(duplicate("missing", "other"), Status::NotFound);
+        assert_eq!(duplicate("original", "a/b"), Status::BadRequest);
+
+        client.delete("/api/tables/copy/records/0").dispatch();
+        let count = |table: &str| client.get(format!("/api/tables/{}/count", table)).dispatch().into_string().unwrap();
+        assert_eq!(count("copy"), r#"{"count":0}"#);
+        assert_eq!(count("original"), r#"{"count":1}"#);
+    }
+
+    #[test]
+    fn test_stats() {
+        let client = create_test_client();
+        for (table, rows) in [("first", 2), ("second", 0), ("third", 3)] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+            for _ in 0..rows {
+                client.post(format!("/api/tables/{}/records", table))
+                    .header(ContentType::JSON)
+                    .body(serde_json::to_string(&create_test_record()).unwrap())
+                    .dispatch();
+            }
+        }
+
+        let response = client.get("/api/stats").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let stats: DatabaseStats = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(stats.table_count, 3);
+        assert_eq!(stats.total_rows, 5);
+        assert_eq!(stats.tables.get("first"), Some(&2));
+        assert_eq!(stats.tables.get("second"), Some(&0));
+    }
+
+    #[test]
+    fn test_create_table_schema_without_name() {
+        let client = create_test_client();
+
+        let response = client.post("/api/tables/unnamed")
+            .header(ContentType::JSON)
+            .body(r#"{"columns":[{"name":"id","column_type":"integer"},{"name":"label","column_type":"string"}]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/api/tables/unnamed/schema").dispatch();
+        let schema: DbSchema = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(schema.columns.len(), 2);
+
+        // The path names the table; a stray name in the body is ignored.
+        let response = client.post("/api/tables/from_path")
+            .header(ContentType::JSON)
+            .body(r#"{"name":"from_body","columns":[{"name":"id","column_type":"integer"}]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/from_path/schema").dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/from_body/schema").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_get_schema() {
+        let client = create_test_client();
+        let mut schema = create_test_schema();
+        schema.columns[0].unique = true;
+        client.post("/api/tables/described")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/tables/described/schema").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let fetched: DbSchema = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(fetched, schema);
+
+        let response = client.get("/api/tables/missing/schema").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_alter_schema() {
+        let client = create_test_client();
+
+        client.post("/api/tables/altered")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for _ in 0..3 {
+            client.post("/api/tables/altered/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let mut schema = create_test_schema();
+        schema.columns.push(db_core::types::schema::DbColumn {
+            name: "visits".to_string(),
+            column_type: db_core::types::schema::DbColumnType::Integer,
+            ..Default::default()
+        });
+        let response = client.put("/api/tables/altered/schema")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/api/tables/altered/records").dispatch();
+        let records = page_records(response);
+        assert_eq!(records.len(), 3);
+        for record in records {
+            assert_eq!(record.values.len(), 4);
+            assert_eq!(record.values[3], DbValue::Integer(0));
+        }
+
+        schema.columns[1].column_type = db_core::types::schema::DbColumnType::Integer;
+        let response = client.put("/api/tables/altered/schema")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_create_table_invalid_names() {
+        let client = create_test_client();
+
+        let response = client.post("/api/tables/%20%20")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let mut schema = create_test_schema();
+        schema.columns[1].name = "first/last".to_string();
+        let response = client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        for name in ["id", ""] {
+            let mut schema = create_test_schema();
+            schema.columns[1].name = name.to_string();
+            let response = client.post("/api/tables/test_table")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&schema).unwrap())
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+        assert_eq!(client.get("/api/tables/test_table/schema").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_list_omits_excluded_columns() {
+        let client = create_test_client();
+
+        let mut schema = create_test_schema();
+        schema.columns[1].include_in_list = false;
+        client.post("/api/tables/list_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let record = create_test_record();
+        client.post("/api/tables/list_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&record).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/tables/list_table/records").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let listed = page_records(response);
+        assert_eq!(listed[0].values, vec![DbValue::Integer(1), DbValue::Money(1000.0)]);
+
+        let response = client.get("/api/tables/list_table/records/0").dispatch();
+        let fetched: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(fetched.values, record.values);
+    }
+
+    #[test]
+    fn test_symmetric_difference() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        for table in ["left", "right"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&schema).unwrap())
+                .dispatch();
+        }
+
+        let row = |id: i32, name: &str| Record {
+            id: "0".to_string(),
+            values: vec![DbValue::Integer(id), DbValue::String(name.to_string()), DbValue::Money(0.0)],
+            ..Default::default()
+        };
+        for (table, record) in [
+            ("left", row(1, "shared")),
+            ("left", row(2, "left only")),
+            ("right", row(1, "shared")),
+            ("right", row(3, "right only")),
+        ] {
+            client.post(format!("/api/tables/{}/records", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        for path in ["/api/symmetric_difference/left/right", "/api/symmetric-difference/left/right"] {
+            let response = client.get(path).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            let result: Vec<SourcedRecord> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            let tagged: Vec<_> = result.iter().map(|r| (r.source.as_str(), r.row.values[0].clone())).collect();
+            assert_eq!(tagged, vec![("left", DbValue::Integer(2)), ("right", DbValue::Integer(3))]);
+        }
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_failure() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/accounts")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        client.post("/api/tables/accounts/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let operations = r#"[
+            {"op": "insert", "table": "accounts", "values": [{"Integer": 2}, {"String": "Jane"}, {"Money": 5.0}]},
+            {"op": "update", "table": "accounts", "id": 0, "values": [{"Integer": 1}, {"String": "John"}, {"Money": 0.0}]},
+            {"op": "insert", "table": "accounts", "values": [{"String": "wrong type"}]},
+            {"op": "delete", "table": "accounts", "id": 0}
+        ]"#;
+        let response = client.post("/api/transaction")
+            .header(ContentType::JSON)
+            .body(operations)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: TransactionError = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.index, Some(2));
+
+        let response = client.get("/api/tables/accounts/records").dispatch();
+        let records = page_records(response);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].values, create_test_record().values);
+    }
+
+    #[test]
+    fn test_transaction_commits() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/ledger")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+
+        let operations = r#"[
+            {"op": "insert", "table": "ledger", "values": [{"Integer": 1}, {"String": "a"}, {"Money": 1.0}]},
+            {"op": "insert", "table": "ledger", "values": [{"Integer": 2}, {"String": "b"}, {"Money": 2.0}]},
+            {"op": "delete", "table": "ledger", "id": 0}
+        ]"#;
+        let response = client.post("/api/transaction")
+            .header(ContentType::JSON)
+            .body(operations)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/api/tables/ledger/records").dispatch();
+        let records = page_records(response);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "1");
+    }
+
+    #[test]
+    fn test_tables_with_column() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        for table in ["customers", "suppliers"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&schema).unwrap())
+                .dispatch();
+        }
+        let mut other = create_test_schema();
+        other.columns[2].name = "total".to_string();
+        client.post("/api/tables/orders")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&other).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/columns/balance/tables").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let list: TableList = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(list.tables, vec!["customers", "suppliers"]);
+    }
+
+    #[test]
+    fn test_intersection_epsilon() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        for table in ["prices_a", "prices_b"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&schema).unwrap())
+                .dispatch();
+        }
+        for (table, balance) in [("prices_a", 100.0), ("prices_a", 200.0), ("prices_b", 100.04), ("prices_b", 200.0)] {
+            let record = Record {
+                id: "0".to_string(),
+                values: vec![DbValue::Integer(1), DbValue::String("item".to_string()), DbValue::Money(balance)],
+                ..Default::default()
+            };
+            client.post(format!("/api/tables/{}/records", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let count = |uri: &str| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            serde_json::from_str::<Vec<Record>>(&response.into_string().unwrap()).unwrap().len()
+        };
+        assert_eq!(count("/api/intersection/prices_a/prices_b?epsilon=0.01"), 1);
+        assert_eq!(count("/api/intersection/prices_a/prices_b?epsilon=0.1"), 2);
+    }
+
+    #[test]
+    fn test_lock_blocks_writes() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/locked_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        let record = serde_json::to_string(&create_test_record()).unwrap();
+
+        assert_eq!(client.post("/api/admin/lock").dispatch().status(), Status::Ok);
+
+        let response = client.post("/api/tables/locked_table/records")
+            .header(ContentType::JSON)
+            .body(&record)
+            .dispatch();
+        assert_eq!(response.status(), Status::Locked);
+        assert_eq!(client.delete("/api/tables/locked_table").dispatch().status(), Status::Locked);
+        assert_eq!(client.get("/api/tables/locked_table/records").dispatch().status(), Status::Ok);
+
+        assert_eq!(client.post("/api/admin/unlock").dispatch().status(), Status::Ok);
+
+        let response = client.post("/api/tables/locked_table/records")
+            .header(ContentType::JSON)
+            .body(&record)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let client = create_test_client();
+
+        let schema = create_test_schema();
+        client.post("/api/tables/metered")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&schema).unwrap())
+            .dispatch();
+        for _ in 0..2 {
+            client.post("/api/tables/metered/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+        client.delete("/api/tables/metered/records/0").dispatch();
+
+        let response = client.get("/api/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+
+        for line in body.lines().filter(|l| !l.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(!name.is_empty());
+            assert!(value.parse::<f64>().is_ok(), "bad metric line: {}", line);
+        }
+        assert!(body.contains("api_requests_total{method=\"POST\"} 3"));
+        assert!(body.contains("db_table_rows{table=\"metered\"} 1"));
+        assert!(body.contains("db_row_operations_total{op=\"insert\"} 2"));
+        assert!(body.contains("db_row_operations_total{op=\"delete\"} 1"));
+        assert!(body.contains("db_save_duration_seconds_count 4"));
+    }
+
+    #[test]
+    fn test_metrics_skip_failed_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = create_test_client_at(dir.path().join("missing").join("db.json").to_str().unwrap());
+
+        client.post("/api/tables/metered")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        let response = client.post("/api/tables/metered/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+
+        let body = client.get("/api/metrics").dispatch().into_string().unwrap();
+        assert!(!body.contains("db_row_operations_total{op=\"insert\"} 1"), "{}", body);
+    }
+
+    #[test]
+    fn test_schema_graph() {
+        let client = create_test_client();
+
+        client.post("/api/tables/customers")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        let mut orders = create_test_schema();
+        orders.columns[0].name = "customer_id".to_string();
+        orders.columns[0].references = Some("customers".to_string());
+        client.post("/api/tables/orders")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&orders).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/schema/graph").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let graph: RelationshipGraph = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].from_table, "orders");
+        assert_eq!(graph.edges[0].from_column, "customer_id");
+        assert_eq!(graph.edges[0].to_table, "customers");
+    }
+
+    #[test]
+    fn test_export() {
+        let client = create_test_client();
+        client.post("/api/tables/test_table")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        client.post("/api/tables/test_table/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let response = client.get("/api/export").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"database.json\""),
+        );
+        let exported: Database = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        let state = client.rocket().state::<ApiState>().unwrap();
+        assert_eq!(&exported, &*state.db.lock().unwrap());
+        assert_eq!(exported.get_table("test_table").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_import() {
+        let client = create_test_client();
+        client.post("/api/tables/current")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+
+        let mut backup = Database::new("backup");
+        let mut table = db_core::types::table::Table::new("restored".to_string(), create_test_schema());
+        table.insert(create_test_record().values).unwrap();
+        backup.add_table(table).unwrap();
+
+        let response = client.post("/api/import")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&backup).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/current/schema").dispatch().status(), Status::NotFound);
+        assert_eq!(client.get("/api/tables/restored/count").dispatch().into_string().unwrap(), r#"{"count":1}"#);
+
+        backup.get_table_mut("restored").unwrap().get_row_mut(0).unwrap().values[2] = DbValue::String("lots".to_string());
+        backup.tables[0].name = "corrupt".to_string();
+        let response = client.post("/api/import")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&backup).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(client.get("/api/tables/restored/schema").dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/api/tables/corrupt/schema").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_import_options() {
+        let client = create_test_client();
+
+        let mut schema = create_test_schema();
+        schema.columns[1].nullable = true;
+        schema.columns[2].default = Some(DbValue::Money(5.0));
+        let mut backup = Database::new("backup");
+        let mut table = db_core::types::table::Table::new("messy".to_string(), schema);
+        table.insert(vec![DbValue::Integer(1), DbValue::String(String::new())]).unwrap();
+        table.get_row_mut(0).unwrap().values.pop();
+        backup.add_table(table).unwrap();
+        let body = serde_json::to_string(&backup).unwrap();
+
+        let import = |query: &str| client.post(format!("/api/import{}", query))
+            .header(ContentType::JSON)
+            .body(&body)
+            .dispatch()
+            .status();
+        let stored = || {
+            let response = client.get("/api/tables/messy/records/0").dispatch();
+            serde_json::from_str::<Record>(&response.into_string().unwrap()).unwrap().values
+        };
+
+        assert_eq!(import(""), Status::BadRequest);
+        assert_eq!(import("?fill_defaults=true"), Status::Ok);
+        assert_eq!(stored(), vec![DbValue::Integer(1), DbValue::String(String::new()), DbValue::Money(5.0)]);
+        assert_eq!(import("?fill_defaults=true&treat_empty_as_null=true"), Status::Ok);
+        assert_eq!(stored(), vec![DbValue::Integer(1), DbValue::Null, DbValue::Money(5.0)]);
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_databases() {
+        let client = create_test_client();
+
+        let backup = || {
+            let mut backup = Database::new("backup");
+            let mut schema = create_test_schema();
+            schema.columns[0].unique = true;
+            let mut table = db_core::types::table::Table::new("restored".to_string(), schema);
+            table.insert(create_test_record().values).unwrap();
+            backup.add_table(table).unwrap();
+            backup
+        };
+        let import = |database: &Database| {
+            client.post("/api/import")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(database).unwrap())
+                .dispatch()
+                .status()
+        };
+
+        let mut duplicated = backup();
+        duplicated.tables.push(duplicated.tables[0].clone());
+        assert_eq!(import(&duplicated), Status::BadRequest);
+
+        let mut bad_schema = backup();
+        bad_schema.tables[0].schema.columns[1].name = "a/b".to_string();
+        assert_eq!(import(&bad_schema), Status::BadRequest);
+
+        let mut repeated = backup();
+        let table = repeated.get_table_mut("restored").unwrap();
+        let values = table.get_row(0).unwrap().values.clone();
+        table.rows.insert(1, db_core::types::table::Row::new(1, values));
+        assert_eq!(import(&repeated), Status::BadRequest);
+
+        assert_eq!(client.get("/api/tables/restored/schema").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_import_publishes_changes() {
+        let client = create_test_client();
+        client.post("/api/tables/current")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        client.post("/api/tables/current/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let mut backup = Database::new("backup");
+        let mut table = db_core::types::table::Table::new("restored".to_string(), create_test_schema());
+        for _ in 0..2 {
+            table.insert(create_test_record().values).unwrap();
+        }
+        backup.add_table(table).unwrap();
+
+        let state = client.rocket().state::<ApiState>().unwrap();
+        let mut changes = state.changes.subscribe();
+        let response = client.post("/api/import")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&backup).unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        assert_eq!(changes.try_recv().unwrap(), ChangeEvent { table: "current".to_string(), op: RowOp::Delete, id: None });
+        assert_eq!(changes.try_recv().unwrap(), ChangeEvent { table: "restored".to_string(), op: RowOp::Insert, id: None });
+        let body = client.get("/api/metrics").dispatch().into_string().unwrap();
+        assert!(body.contains("db_row_operations_total{op=\"insert\"} 3"), "{}", body);
+        assert!(body.contains("db_row_operations_total{op=\"delete\"} 1"), "{}", body);
+    }
+
+    #[test]
+    fn test_export_archive() {
+        let client = create_test_client();
+
+        for table in ["first", "second", "third"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+        }
+        client.post("/api/tables/first/records")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch();
+
+        let entries = |uri: &str| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.content_type(), Some(ContentType::ZIP));
+            let archive = zip::ZipArchive::new(Cursor::new(response.into_bytes().unwrap())).unwrap();
+            let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+            names.sort();
+            (names, archive)
+        };
+
+        let (names, mut archive) = entries("/api/export/archive?tables=first,second&format=csv");
+        assert_eq!(names, vec!["first.csv", "second.csv"]);
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("first.csv").unwrap(), &mut contents).unwrap();
+        assert_eq!(contents, "id,name,balance\n1,John Doe,1000\n");
+
+        let (names, _) = entries("/api/export/archive?format=json");
+        assert_eq!(names, vec!["first.json", "second.json", "third.json"]);
+
+        let response = client.get("/api/export/archive?tables=missing").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_get_all_multi_sort() {
+        let client = create_test_client();
+
+        client.post("/api/tables/sorted")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for (id, name, balance) in [(1, "b", 10.0), (2, "a", 5.0), (3, "b", 30.0), (4, "a", 5.0)] {
+            let record = Record {
+                id: "0".to_string(),
+                values: vec![DbValue::Integer(id), DbValue::String(name.to_string()), DbValue::Money(balance)],
+                ..Default::default()
+            };
+            client.post("/api/tables/sorted/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/tables/sorted/records?sort=name:asc,balance:desc,id:desc").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let records = page_records(response);
+        let ids: Vec<_> = records.iter().map(|r| r.values[0].clone()).collect();
+        assert_eq!(ids, [4, 2, 3, 1].map(DbValue::Integer));
+
+        let response = client.get("/api/tables/sorted/records?sort=name:up").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(error.error.contains("Invalid sort direction"));
+
+        let response = client.get("/api/tables/sorted/records?sort=missing").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client.get("/api/tables/sorted/records?sort=balance&order=desc").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let records = page_records(response);
+        let ids: Vec<_> = records.iter().map(|r| r.values[0].clone()).collect();
+        assert_eq!(ids, [3, 1, 2, 4].map(DbValue::Integer));
+
+        let response = client.get("/api/tables/sorted/records?sort=balance&order=sideways").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_get_all_filter() {
+        let client = create_test_client();
+
+        client.post("/api/tables/filtered")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch();
+        for (id, name, balance) in [(1, "John", 500.0), (2, "Jane", 1500.0), (3, "Bob", 2500.0)] {
+            let record = Record {
+                id: "0".to_string(),
+                values: vec![DbValue::Integer(id), DbValue::String(name.to_string()), DbValue::Money(balance)],
+                ..Default::default()
+            };
+            client.post("/api/tables/filtered/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let ids = |uri: &str| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let records = page_records(response);
+            records.into_iter().map(|r| r.values[0].clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(ids("/api/tables/filtered/records?filter=balance:gt:1000"), [2, 3].map(DbValue::Integer));
+        assert_eq!(ids("/api/tables/filtered/records?filter=name:contains:J"), [1, 2].map(DbValue::Integer));
+        assert_eq!(ids("/api/tables/filtered/records?filter=balance:gt:1000&filter=name:contains:J"), [2].map(DbValue::Integer));
+        assert_eq!(ids("/api/tables/filtered/records?filter=balance:gt:0&sort=id:desc"), [3, 2, 1].map(DbValue::Integer));
+
+        let response = client.get("/api/tables/filtered/records?filter=age:gt:1").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.error, "Column 'age' not found");
+    }
 
     #[test]
-    fn test_create_table() {
+    fn test_delete_where() {
         let client = create_test_client();
-        let schema = create_test_schema();
-        
-        let response = client.post("/api/tables/test_table")
+
+        client.post("/api/tables/filtered")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
+        for (id, name, balance) in [(1, "John", -50.0), (2, "Jane", 1500.0), (3, "Bob", -0.5)] {
+            let record = Record {
+                id: "0".to_string(),
+                values: vec![DbValue::Integer(id), DbValue::String(name.to_string()), DbValue::Money(balance)],
+                ..Default::default()
+            };
+            client.post("/api/tables/filtered/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.delete("/api/tables/filtered/records?filter=balance:lt:0").dispatch();
         assert_eq!(response.status(), Status::Ok);
+        let count: DeletedCount = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(count.deleted, 2);
+
+        let response = client.get("/api/tables/filtered/records").dispatch();
+        let records = page_records(response);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].values[1], DbValue::String("Jane".to_string()));
+
+        let response = client.delete("/api/tables/filtered/records?filter=age:lt:0").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: ErrorBody = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(error.error, "Column 'age' not found");
+        assert_eq!(client.get("/api/tables/filtered/count").dispatch().into_string().unwrap(), r#"{"count":1}"#);
     }
 
     #[test]
-    fn test_create_and_get_record() {
+    fn test_count() {
         let client = create_test_client();
-        
-        // First create a table
-        let schema = create_test_schema();
-        client.post("/api/tables/test_table")
-            .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
-            .dispatch();
-            
-        // Create a record
-        let record = create_test_record();
-        let response = client.post("/api/tables/test_table/records")
+
+        client.post("/api/tables/counted")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&record).unwrap())
-            .dispatch();
-            
-        assert_eq!(response.status(), Status::Ok);
-        
-        // Get the record back
-        let response = client.get("/api/tables/test_table/records/0")
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        assert_eq!(response.status(), Status::Ok);
-        
-        let retrieved_record: Record = serde_json::from_str(
-            &response.into_string().unwrap()
-        ).unwrap();
-        
-        assert_eq!(retrieved_record.values, record.values);
+        let count = || {
+            let response = client.get("/api/tables/counted/count").dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            serde_json::from_str::<RowCount>(&response.into_string().unwrap()).unwrap().count
+        };
+        assert_eq!(count(), 0);
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let response = client.post("/api/tables/counted/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+            let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            ids.push(record.id);
+        }
+        assert_eq!(count(), 3);
+
+        client.delete(format!("/api/tables/counted/records/{}", ids[1])).dispatch();
+        assert_eq!(count(), 2);
+
+        let response = client.get("/api/tables/missing/count").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
     }
 
     #[test]
-    fn test_update_record() {
+    fn test_describe_stats() {
         let client = create_test_client();
-        
-        // Create table and initial record
-        let schema = create_test_schema();
-        client.post("/api/tables/test_table")
+
+        client.post("/api/tables/described")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        let record = create_test_record();
-        client.post("/api/tables/test_table/records")
+        for (id, balance) in [(1, 10.0), (2, 20.0), (3, 60.0)] {
+            let record = Record {
+                id: "0".to_string(),
+                values: vec![DbValue::Integer(id), DbValue::String("same".to_string()), DbValue::Money(balance)],
+                ..Default::default()
+            };
+            client.post("/api/tables/described/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/tables/described/describe-stats").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let stats: Vec<ColumnStats> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+        assert_eq!(stats.len(), 3);
+        assert_eq!((stats[0].min, stats[0].max, stats[0].mean), (Some(1.0), Some(3.0), Some(2.0)));
+        assert_eq!((stats[1].count, stats[1].distinct, stats[1].mean), (3, 1, None));
+        assert_eq!((stats[2].min, stats[2].max, stats[2].mean), (Some(10.0), Some(60.0), Some(30.0)));
+    }
+
+    #[rocket::async_test]
+    async fn test_table_events() {
+        use rocket::local::asynchronous::Client;
+        use rocket::tokio::io::AsyncReadExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let client = Client::tracked(create_test_rocket(path.to_str().unwrap())).await.unwrap();
+        client.post("/api/tables/watched")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&record).unwrap())
-            .dispatch();
-            
-        // Update the record
-        let mut updated_record = record.clone();
-        updated_record.values = vec![
-            DbValue::Integer(1),
-            DbValue::String("Jane Doe".to_string()),
-            DbValue::Money(2000.0),
-        ];
-        
-        let response = client.put("/api/tables/test_table/records/0")
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
+            .dispatch()
+            .await;
+
+        let response = client.get("/api/tables/watched/events").dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+        let mut stream = response;
+
+        client.post("/api/tables/watched/records")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&updated_record).unwrap())
+            .body(serde_json::to_string(&create_test_record()).unwrap())
+            .dispatch()
+            .await;
+
+        let mut buf = vec![0; 256];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await
+            .expect("an event within the timeout")
+            .unwrap();
+        let event = String::from_utf8_lossy(&buf[..read]);
+        assert!(event.starts_with("data:"), "unexpected event: {}", event);
+
+        let payload: ChangeEvent = serde_json::from_str(event.trim_start_matches("data:").trim()).unwrap();
+        assert_eq!(payload, ChangeEvent { table: "watched".to_string(), op: RowOp::Insert, id: Some(0) });
+    }
+
+    #[test]
+    fn test_get_all_ids_only() {
+        let client = create_test_client();
+
+        client.post("/api/tables/identified")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
+        for _ in 0..3 {
+            client.post("/api/tables/identified/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+        client.delete("/api/tables/identified/records/1").dispatch();
+
+        let response = client.get("/api/tables/identified/records").dispatch();
+        let records = page_records(response);
+        let mut expected: Vec<String> = records.into_iter().map(|r| r.id).collect();
+        expected.sort();
+
+        let response = client.get("/api/tables/identified/records?ids_only=true").dispatch();
         assert_eq!(response.status(), Status::Ok);
-        
-        // Verify the update
-        let response = client.get("/api/tables/test_table/records/0")
+        let body: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let mut ids: Vec<String> = serde_json::from_value(body["ids"].clone()).unwrap();
+        ids.sort();
+
+        assert_eq!(ids, expected);
+        assert_eq!(ids, vec!["0", "2"]);
+    }
+
+    #[test]
+    fn test_move_record() {
+        let client = create_test_client();
+
+        client.post("/api/tables/arranged")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
+        for _ in 0..4 {
+            client.post("/api/tables/arranged/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let response = client.put("/api/tables/arranged/records/3/position?position=0").dispatch();
         assert_eq!(response.status(), Status::Ok);
-        
-        let retrieved_record: Record = serde_json::from_str(
-            &response.into_string().unwrap()
-        ).unwrap();
-        
-        assert_eq!(retrieved_record.values, updated_record.values);
+        let response = client.put("/api/tables/arranged/records/9/position?position=0").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let ids = |uri: &str| -> Vec<String> {
+            let response = client.get(uri).dispatch();
+            match serde_json::from_str(&response.into_string().unwrap()).unwrap() {
+                RecordList::Ids { ids, .. } => ids,
+                other => panic!("expected ids, got {:?}", other),
+            }
+        };
+        assert_eq!(ids("/api/tables/arranged/records?ids_only=true"), ["3", "0", "1", "2"]);
+        let records = page_records(client.get("/api/tables/arranged/records").dispatch());
+        assert_eq!(records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["3", "0", "1", "2"]);
+
+        // Cursors follow the display order too
+        assert_eq!(ids("/api/tables/arranged/records?ids_only=true&limit=2"), ["3", "0"]);
+        assert_eq!(ids("/api/tables/arranged/records?ids_only=true&after=0"), ["1", "2"]);
     }
 
     #[test]
-    fn test_delete_record() {
+    fn test_get_all_cursor_paging() {
         let client = create_test_client();
-        
-        // Create table and record
-        let schema = create_test_schema();
-        client.post("/api/tables/test_table")
+
+        client.post("/api/tables/paged")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        let record = create_test_record();
-        client.post("/api/tables/test_table/records")
+        let insert = || {
+            client.post("/api/tables/paged/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        };
+        for _ in 0..5 {
+            insert();
+        }
+
+        let page = |uri: String| -> (Vec<String>, Option<String>) {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            match serde_json::from_str(&response.into_string().unwrap()).unwrap() {
+                RecordList::Page { records, next, .. } => (records.into_iter().map(|r| r.id).collect(), next),
+                other => panic!("expected a page, got {:?}", other),
+            }
+        };
+
+        let mut seen = Vec::new();
+        let (ids, mut next) = page("/api/tables/paged/records?limit=2".to_string());
+        seen.extend(ids);
+        insert();
+        while let Some(cursor) = next {
+            let (ids, cursor) = page(format!("/api/tables/paged/records?after={}&limit=2", cursor));
+            seen.extend(ids);
+            next = cursor;
+        }
+        assert_eq!(seen, ["0", "1", "2", "3", "4", "5"]);
+
+        let response = client.get("/api/tables/paged/records?after=abc").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_get_all_offset_paging() {
+        let client = create_test_client();
+
+        client.post("/api/tables/offset_paged")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&record).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        // Delete the record
-        let response = client.delete("/api/tables/test_table/records/0")
+        let inserts: Vec<_> = (0..250)
+            .map(|n| Operation::Insert {
+                table: "offset_paged".to_string(),
+                values: vec![DbValue::Integer(n), DbValue::String(format!("row {}", n)), DbValue::Money(0.0)],
+            })
+            .collect();
+        let response = client.post("/api/transaction")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&inserts).unwrap())
             .dispatch();
-            
         assert_eq!(response.status(), Status::Ok);
-        
-        // Verify deletion
-        let response = client.get("/api/tables/test_table/records/0")
-            .dispatch();
-            
-        assert_eq!(response.status(), Status::InternalServerError); // Should fail to find record
+
+        let mut seen = Vec::new();
+        for offset in (0..250).step_by(DEFAULT_PAGE_LIMIT) {
+            let response = client.get(format!("/api/tables/offset_paged/records?offset={}", offset)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            match serde_json::from_str(&response.into_string().unwrap()).unwrap() {
+                RecordList::Page { records, total, .. } => {
+                    assert_eq!(total, 250);
+                    assert_eq!(records.len(), DEFAULT_PAGE_LIMIT.min(250 - offset));
+                    seen.extend(records.into_iter().map(|r| r.values[0].clone()));
+                }
+                other => panic!("expected a page, got {:?}", other),
+            }
+        }
+
+        assert_eq!(seen, (0..250).map(DbValue::Integer).collect::<Vec<_>>());
     }
 
     #[test]
-    fn test_intersection() {
+    fn test_get_all_pages_sorted_and_filtered() {
         let client = create_test_client();
-        
-        // Create two tables with the same schema
-        let schema = create_test_schema();
-        client.post("/api/tables/table1")
+
+        client.post("/api/tables/sorted_paged")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        client.post("/api/tables/table2")
+        let inserts: Vec<_> = (0..250)
+            .map(|n| Operation::Insert {
+                table: "sorted_paged".to_string(),
+                values: vec![DbValue::Integer(n), DbValue::String(format!("row {}", n)), DbValue::Money(0.0)],
+            })
+            .collect();
+        client.post("/api/transaction")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&inserts).unwrap())
             .dispatch();
-            
-        // Add same record to both tables
-        let record = create_test_record();
-        client.post("/api/tables/table1/records")
+
+        let page = |uri: String| -> (Vec<DbValue>, Option<String>, usize) {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            match serde_json::from_str(&response.into_string().unwrap()).unwrap() {
+                RecordList::Page { records, next, total } => (records.into_iter().map(|r| r.values[0].clone()).collect(), next, total),
+                other => panic!("expected a page, got {:?}", other),
+            }
+        };
+
+        let (values, next, total) = page("/api/tables/sorted_paged/records?sort=id:desc".to_string());
+        assert_eq!(total, 250);
+        assert_eq!(values.len(), DEFAULT_PAGE_LIMIT);
+        assert_eq!(values[0], DbValue::Integer(249));
+        assert!(next.is_some());
+
+        let mut seen = Vec::new();
+        let mut next = None;
+        loop {
+            let uri = match &next {
+                Some(cursor) => format!("/api/tables/sorted_paged/records?sort=id:desc&filter=id:gt:99&limit=60&after={}", cursor),
+                None => "/api/tables/sorted_paged/records?sort=id:desc&filter=id:gt:99&limit=60".to_string(),
+            };
+            let (values, cursor, total) = page(uri);
+            assert_eq!(total, 150);
+            seen.extend(values);
+            next = cursor;
+            if next.is_none() {
+                break;
+            }
+        }
+        assert_eq!(seen, (100..250).rev().map(DbValue::Integer).collect::<Vec<_>>());
+
+        let (values, _, _) = page("/api/tables/sorted_paged/records?sort=id:desc&filter=id:gt:99&offset=140".to_string());
+        assert_eq!(values, (100..110).rev().map(DbValue::Integer).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_create_from_form() {
+        let client = create_test_client();
+
+        client.post("/api/tables/form_table")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&record).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        client.post("/api/tables/table2/records")
+
+        let response = client.post("/api/tables/form_table/records/form")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&record).unwrap())
+            .body(r#"{"values": ["1", "John Doe", "1000"]}"#)
             .dispatch();
-            
-        // Add different record to table2
-        let mut different_record = record.clone();
-        different_record.values = vec![
-            DbValue::Integer(2),
-            DbValue::String("Jane Doe".to_string()),
-            DbValue::Money(2000.0),
-        ];
-        
-        client.post("/api/tables/table2/records")
+        assert_eq!(response.status(), Status::Ok);
+        let record: Record = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(record.values, create_test_record().values);
+
+        let response = client.post("/api/tables/form_table/records/form")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&different_record).unwrap())
+            .body(r#"{"values": ["one", "John Doe", "1000"]}"#)
             .dispatch();
-            
-        // Get intersection
-        let response = client.get("/api/intersection/table1/table2")
-            .dispatch();
-            
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_union() {
+        let client = create_test_client();
+
+        for table in ["union_a", "union_b"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+        }
+        let mut other = create_test_record();
+        other.values[0] = DbValue::Integer(2);
+        for (table, record) in [("union_a", create_test_record()), ("union_b", create_test_record()), ("union_b", other)] {
+            client.post(format!("/api/tables/{}/records", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/union/union_a/union_b").dispatch();
         assert_eq!(response.status(), Status::Ok);
-        
-        let intersection: Vec<Record> = serde_json::from_str(
-            &response.into_string().unwrap()
-        ).unwrap();
-        
-        assert_eq!(intersection.len(), 1);
-        assert_eq!(intersection[0].values, record.values);
+        let records: Vec<Record> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(records.len(), 2);
     }
 
     #[test]
-    fn test_validation_errors() {
+    fn test_join() {
         let client = create_test_client();
-        
-        // Create table
-        let schema = create_test_schema();
-        client.post("/api/schema/test_table")
+        client.post("/api/tables/users")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&schema).unwrap())
+            .body(serde_json::to_string(&create_test_schema()).unwrap())
             .dispatch();
-            
-        // Test wrong number of values
-        let mut invalid_record = create_test_record();
-        invalid_record.values.pop();
-        
-        let response = client.post("/api/tables/test_table/records")
+        let orders = DbSchema::builder()
+            .column("user_id", db_core::types::schema::DbColumnType::Integer)
+            .column("item", db_core::types::schema::DbColumnType::String)
+            .build()
+            .unwrap();
+        client.post("/api/tables/orders")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&invalid_record).unwrap())
+            .body(serde_json::to_string(&orders).unwrap())
             .dispatch();
-            
-        assert_eq!(response.status(), Status::InternalServerError);
-        
-        // Test wrong value type
-        let mut invalid_record = create_test_record();
-        invalid_record.values[0] = DbValue::String("not an integer".to_string());
-        
-        let response = client.post("/api/tables/test_table/records")
+
+        client.post("/api/tables/users/records")
             .header(ContentType::JSON)
-            .body(serde_json::to_string(&invalid_record).unwrap())
+            .body(serde_json::to_string(&create_test_record()).unwrap())
             .dispatch();
-            
-        assert_eq!(response.status(), Status::InternalServerError);
+        for (user, item) in [(1, "pen"), (1, "ink"), (5, "cup")] {
+            let record = NewRecord { values: vec![DbValue::Integer(user), DbValue::String(item.to_string())] };
+            client.post("/api/tables/orders/records")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let response = client.get("/api/join/users/orders?left=id&right=user_id").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let joined: TableDetails = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(joined.schema.columns.len(), 5);
+        assert_eq!(joined.rows.len(), 2);
+        assert_eq!(joined.rows[1].values[4], DbValue::String("ink".to_string()));
+
+        let response = client.get("/api/join/users/orders?left=id&right=missing").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get("/api/join/users/missing?left=id&right=user_id").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_search() {
+        let client = create_test_client();
+        for table in ["accounts", "archive"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+            client.post(format!("/api/tables/{}/records", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_record()).unwrap())
+                .dispatch();
+        }
+
+        let search = |value: DbValue| {
+            let response = client.post("/api/search")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&value).unwrap())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            serde_json::from_str::<Vec<SourcedRecord>>(&response.into_string().unwrap()).unwrap()
+        };
+        let hits = search(DbValue::String("John Doe".to_string()));
+        let sources: Vec<&str> = hits.iter().map(|h| h.source.as_str()).collect();
+        assert_eq!(sources, vec!["accounts", "archive"]);
+        assert_eq!(hits[0].row.values, create_test_record().values);
+
+        assert!(search(DbValue::String("Nobody".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_difference() {
+        let client = create_test_client();
+
+        for table in ["diff_a", "diff_b"] {
+            client.post(format!("/api/tables/{}", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&create_test_schema()).unwrap())
+                .dispatch();
+        }
+        let mut other = create_test_record();
+        other.values[0] = DbValue::Integer(2);
+        for (table, record) in [("diff_a", create_test_record()), ("diff_a", other), ("diff_b", create_test_record())] {
+            client.post(format!("/api/tables/{}/records", table))
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&record).unwrap())
+                .dispatch();
+        }
+
+        let count = |uri: &str| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            serde_json::from_str::<Vec<Record>>(&response.into_string().unwrap()).unwrap().len()
+        };
+        assert_eq!(count("/api/difference/diff_a/diff_b"), 1);
+        assert_eq!(count("/api/difference/diff_b/diff_a"), 0);
+    }
+}
@@ -0,0 +1,53 @@
+//! Times `Table::intersection` against `Table::intersection_sorted`.
+//! Run with `cargo bench -p core --bench intersection`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use core::types::schema::{DbColumnType, DbSchema, DbValue};
+use core::types::table::Table;
+
+const ROUNDS: u32 = 10;
+
+fn table(name: &str, rows: i32, step: i32) -> Table {
+    let schema = DbSchema::builder()
+        .column("id", DbColumnType::Integer)
+        .column("name", DbColumnType::String)
+        .column("price", DbColumnType::Money)
+        .build()
+        .unwrap();
+    let mut table = Table::new(name.to_string(), schema);
+    for i in 0..rows {
+        let n = i * step;
+        table.insert(vec![
+            DbValue::Integer(n),
+            DbValue::String(format!("item{}", n % 1000)),
+            DbValue::Money(f64::from(n % 500)),
+        ]).unwrap();
+    }
+    table
+}
+
+fn time(f: impl Fn() -> usize) -> (Duration, usize) {
+    let matches = f();
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    (started.elapsed() / ROUNDS, matches)
+}
+
+fn main() {
+    for rows in [1_000, 10_000, 100_000] {
+        let left = table("left", rows, 2);
+        let right = table("right", rows, 3);
+
+        let (hashed, hashed_matches) = time(|| left.intersection(&right).unwrap().len());
+        let (sorted, sorted_matches) = time(|| left.intersection_sorted(&right).unwrap().len());
+        assert_eq!(hashed_matches, sorted_matches);
+
+        println!(
+            "{:>7} rows: intersection {:>10.2?}, intersection_sorted {:>10.2?} ({} matches)",
+            rows, hashed, sorted, hashed_matches,
+        );
+    }
+}
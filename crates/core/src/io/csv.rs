@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use anyhow::{anyhow, bail};
+use crate::io::ImportOptions;
+use crate::types::schema::{DbSchema, DbValue};
+use crate::types::table::Table;
+
+/// Writes `table` to `path` with `write_table`.
+pub fn export_table_csv(table: &Table, path: &str) -> anyhow::Result<()> {
+    write_table(table, BufWriter::new(File::create(path)?))
+}
+
+/// Reads a CSV file, such as one written by `export_table_csv`, into a new
+/// table named after the file. Cells are parsed as their column's type.
+pub fn import_table_csv(path: &str, schema: &DbSchema, options: ImportOptions) -> anyhow::Result<Table> {
+    let name = Path::new(path).file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("Cannot name a table after '{}'", path))?;
+    let mut table = Table::try_new(name.to_string(), schema.clone())?;
+    for values in read_rows(BufReader::new(File::open(path)?), schema, options)? {
+        table.insert(values)?;
+    }
+    Ok(table)
+}
+
+/// Parses CSV into rows of values for `schema`. The header row, unless
+/// `options` says there is none, must name `schema`'s columns in order; with
+/// `fill_defaults` it may list them in any order and leave out columns that
+/// have a default. Empty cells are resolved by `ImportOptions::empty_cell`
+/// and otherwise parsed like any other. Records with the wrong number of
+/// cells are rejected.
+pub fn read_rows<R: Read>(reader: R, schema: &DbSchema, options: ImportOptions) -> anyhow::Result<Vec<Vec<DbValue>>> {
+    let mut csv = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .delimiter(options.delimiter)
+        .has_headers(options.has_header)
+        .from_reader(reader);
+
+    // Where each schema column sits in a record, if it is there at all.
+    let positions: Vec<Option<usize>> = if options.has_header {
+        let header: Vec<&str> = csv.headers()?.iter().collect();
+        let expected: Vec<&str> = schema.columns.iter().map(|c| c.name.as_str()).collect();
+        if header != expected && !options.fill_defaults {
+            bail!("Header {:?} does not match the schema columns {:?}", header, expected);
+        }
+        if let Some(name) = header.iter().find(|name| !expected.contains(name)) {
+            bail!("Header column '{}' is not in the schema", name);
+        }
+        if let Some((i, name)) = header.iter().enumerate().find(|(i, name)| header[..*i].contains(name)) {
+            bail!("Header column '{}' is repeated at position {}", name, i + 1);
+        }
+        let positions: Vec<_> = expected.iter().map(|name| header.iter().position(|h| h == name)).collect();
+        if let Some(column) = schema.columns.iter().zip(&positions).find(|(c, p)| p.is_none() && c.default.is_none()).map(|(c, _)| c) {
+            bail!("Header is missing column '{}', which has no default", column.name);
+        }
+        positions
+    } else {
+        (0..schema.columns.len()).map(Some).collect()
+    };
+    let width = positions.iter().flatten().count();
+
+    let mut rows = Vec::new();
+    for (line, record) in csv.records().enumerate() {
+        let record = record?;
+        // Lines count from 1, and the header takes the first.
+        let line = line + if options.has_header { 2 } else { 1 };
+        if record.len() != width {
+            bail!("Line {} has {} fields, expected {}", line, record.len(), width);
+        }
+        let values = schema.columns.iter().zip(&positions)
+            .map(|(column, position)| {
+                let Some(cell) = position.map(|p| &record[p]) else {
+                    return Ok(column.default.clone().unwrap_or(DbValue::Null));
+                };
+                if cell.is_empty() {
+                    if let Some(value) = options.empty_cell(column) {
+                        return Ok(value);
+                    }
+                }
+                DbValue::parse(cell, &column.column_type)
+                    .map_err(|e| anyhow!("Line {}, column '{}': {}", line, column.name, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        rows.push(values);
+    }
+    Ok(rows)
+}
+
+/// Writes `table` as CSV: a header row of column names, then one record per
+/// row in display order. Money ranges are written as `min..max`.
+pub fn write_table<W: Write>(table: &Table, writer: W) -> anyhow::Result<()> {
+    let mut csv = ::csv::Writer::from_writer(writer);
+
+    csv.write_record(table.schema.columns.iter().map(|c| c.name.as_str()))?;
+    for row in table.get_rows_ordered() {
+        csv.write_record(row.values.iter().map(DbValue::to_text))?;
+    }
+
+    csv.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::schema::DbColumnType;
+    use crate::types::table::create_test_table;
+
+    fn every_type_schema() -> DbSchema {
+        DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("ratio", DbColumnType::Real)
+            .column("grade", DbColumnType::Char)
+            .column("name", DbColumnType::String)
+            .column("balance", DbColumnType::Money)
+            .column("budget", DbColumnType::MoneyRange)
+            .column("active", DbColumnType::Boolean)
+            .column("opened", DbColumnType::Date)
+            .column("share", DbColumnType::Percentage)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_table() {
+        let mut table = create_test_table("people");
+        table.insert(vec![DbValue::Integer(1), DbValue::String("Doe, John".to_string())]).unwrap();
+        table.insert(vec![DbValue::Integer(2), DbValue::String("Jane".to_string())]).unwrap();
+
+        let mut out = Vec::new();
+        write_table(&table, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "id,name\n1,\"Doe, John\"\n2,Jane\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let schema = every_type_schema();
+        let mut table = Table::new("accounts".to_string(), schema.clone());
+        for (id, name) in [(1, "Doe, John"), (2, "Jane \"JJ\"")] {
+            table.insert(vec![
+                DbValue::Integer(id),
+                DbValue::Real(0.25 * id as f32),
+                DbValue::Char('A'),
+                DbValue::String(name.to_string()),
+                DbValue::Money(1000.5),
+                DbValue::MoneyRange(10.0, 20.5),
+                DbValue::Boolean(id == 1),
+                DbValue::Date(crate::types::schema::parse_date("2024-02-29").unwrap()),
+                DbValue::Percentage(12.5 * id as f64),
+            ]).unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.csv");
+        let path = path.to_str().unwrap();
+        export_table_csv(&table, path).unwrap();
+        let imported = import_table_csv(path, &schema, ImportOptions::default()).unwrap();
+
+        assert_eq!(imported.name(), "accounts");
+        let values = |t: &Table| t.get_rows_ordered().into_iter().map(|r| r.values.clone()).collect::<Vec<_>>();
+        assert_eq!(values(&imported), values(&table));
+    }
+
+    #[test]
+    fn test_read_rows_rejects_bad_input() {
+        let schema = create_test_table("people").schema;
+
+        let options = ImportOptions::default();
+
+        let err = read_rows("id,name\n1,John\n2\n".as_bytes(), &schema, options).unwrap_err();
+        assert_eq!(err.to_string(), "Line 3 has 1 fields, expected 2");
+
+        let err = read_rows("id,name\nseven,John\n".as_bytes(), &schema, options).unwrap_err();
+        assert!(err.to_string().starts_with("Line 2, column 'id'"));
+
+        assert!(read_rows("name,id\n".as_bytes(), &schema, options).is_err());
+    }
+
+    fn messy_schema() -> DbSchema {
+        DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("note", DbColumnType::String)
+            .nullable("note")
+            .column("score", DbColumnType::Integer)
+            .default_value("score", DbValue::Integer(10))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_rows_empty_cells() {
+        let schema = messy_schema();
+        let input = "id,note,score\n1,,\n2,hi,5\n";
+        let read = |treat_empty_as_null, fill_defaults| {
+            let options = ImportOptions { treat_empty_as_null, fill_defaults, ..ImportOptions::default() };
+            read_rows(input.as_bytes(), &schema, options)
+        };
+        let second = vec![DbValue::Integer(2), DbValue::String("hi".to_string()), DbValue::Integer(5)];
+
+        // An empty Integer cell cannot be parsed without a default
+        assert!(read(true, false).unwrap_err().to_string().starts_with("Line 2, column 'score'"));
+        assert!(read(false, false).is_err());
+
+        assert_eq!(read(true, true).unwrap(), vec![
+            vec![DbValue::Integer(1), DbValue::Null, DbValue::Integer(10)],
+            second.clone(),
+        ]);
+        // Without nulls, the empty note stays an empty string
+        assert_eq!(read(false, true).unwrap(), vec![
+            vec![DbValue::Integer(1), DbValue::String(String::new()), DbValue::Integer(10)],
+            second,
+        ]);
+    }
+
+    #[test]
+    fn test_read_rows_missing_columns() {
+        let schema = messy_schema();
+        let fill = ImportOptions { fill_defaults: true, ..ImportOptions::default() };
+
+        assert!(read_rows("note,id\nhi,1\n".as_bytes(), &schema, ImportOptions::default()).is_err());
+        assert_eq!(read_rows("note,id\nhi,1\n".as_bytes(), &schema, fill).unwrap(), vec![
+            vec![DbValue::Integer(1), DbValue::String("hi".to_string()), DbValue::Integer(10)],
+        ]);
+
+        let err = read_rows("note,score\nhi,1\n".as_bytes(), &schema, fill).unwrap_err();
+        assert_eq!(err.to_string(), "Header is missing column 'id', which has no default");
+        assert!(read_rows("id,other\n1,2\n".as_bytes(), &schema, fill).is_err());
+        assert!(read_rows("id,id\n1,2\n".as_bytes(), &schema, fill).is_err());
+    }
+
+    #[test]
+    fn test_read_rows_delimiter_and_no_header() {
+        let schema = messy_schema();
+        let options = ImportOptions { delimiter: b';', has_header: false, ..ImportOptions::default() };
+
+        let rows = read_rows("1;a, b;3\n2;;4\n".as_bytes(), &schema, options).unwrap();
+        assert_eq!(rows, vec![
+            vec![DbValue::Integer(1), DbValue::String("a, b".to_string()), DbValue::Integer(3)],
+            vec![DbValue::Integer(2), DbValue::Null, DbValue::Integer(4)],
+        ]);
+
+        let err = read_rows("1;a;3\nx;b;4\n".as_bytes(), &schema, options).unwrap_err();
+        assert!(err.to_string().starts_with("Line 2, column 'id'"));
+    }
+}
@@ -0,0 +1,461 @@
+pub mod csv;
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::types::database::Database;
+use crate::types::schema::{DbColumn, DbValue};
+
+/// First two bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Writes `data` to a temporary file next to `path` and renames it over
+/// `path` once fully written, so a crash mid-save never leaves a truncated
+/// file behind: readers see either the old contents or the new ones. The
+/// SHA-256 of the file goes to `<path>.sha256` for `load_from_file`.
+pub fn save_to_file<T>(data: &T, path: &str) -> Result<(), anyhow::Error>
+where
+    T: Serialize,
+{
+    write_atomically(path, |writer| save_to_writer(data, writer))
+}
+
+/// Like `save_to_file`, but gzip-compresses the JSON.
+pub fn save_to_file_compressed<T>(data: &T, path: &str) -> Result<(), anyhow::Error>
+where
+    T: Serialize,
+{
+    write_atomically(path, |writer| {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        save_to_writer(data, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    })
+}
+
+/// Replaces `path` via `replace_file` and then its checksum sidecar. A
+/// crash between the two leaves a stale sidecar, which loading reports as a
+/// mismatch rather than silently trusting the file.
+fn write_atomically<F>(path: &str, write: F) -> Result<(), anyhow::Error>
+where
+    F: FnOnce(&mut BufWriter<HashingWriter<&mut File>>) -> Result<(), anyhow::Error>,
+{
+    let target = Path::new(path);
+    let checksum = replace_file(target, |file| {
+        let mut writer = BufWriter::new(HashingWriter::new(file));
+        write(&mut writer)?;
+        Ok(writer.into_inner().map_err(|e| e.into_error())?.finish())
+    })?;
+    replace_file(&checksum_path(target), |file| {
+        writeln!(file, "{}", checksum)?;
+        Ok(())
+    })
+}
+
+/// Runs `write` on a fresh temporary file next to `target`, syncs it and
+/// renames it over `target`, removing the temporary file on failure.
+fn replace_file<T, F>(target: &Path, write: F) -> Result<T, anyhow::Error>
+where
+    F: FnOnce(&mut File) -> Result<T, anyhow::Error>,
+{
+    let tmp = temp_path(target);
+    let mut file = File::create(&tmp)?;
+
+    let result = (|| {
+        let value = write(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, target)?;
+        Ok(value)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Passes writes through to `inner` while hashing them.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        HashingWriter { inner, hasher: Sha256::new() }
+    }
+
+    /// Hex-encoded SHA-256 of everything written.
+    fn finish(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `<path>.sha256`, where saves record the checksum of `path`.
+pub fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Compares `path` against its checksum sidecar. Files saved before
+/// checksums existed have no sidecar and pass unchecked.
+fn verify_checksum(path: &str) -> Result<(), anyhow::Error> {
+    let expected = match fs::read_to_string(checksum_path(Path::new(path))) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut hasher = HashingWriter::new(io::sink());
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    if hasher.finish() != expected.trim() {
+        bail!(
+            "Database file checksum mismatch for '{}': it may be corrupted. If it was edited on purpose, delete or regenerate '{}'",
+            path,
+            checksum_path(Path::new(path)).display(),
+        );
+    }
+    Ok(())
+}
+
+/// A hidden sibling of `target`, unique per process and call, so that
+/// concurrent saves never share a temporary file.
+fn temp_path(target: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let name = target.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+    target.with_file_name(format!(".{}.{}.{}.tmp", name, process::id(), unique))
+}
+
+pub fn save_to_writer<T, W>(data: &T, mut writer: W) -> Result<(), anyhow::Error>
+where
+    T: Serialize,
+    W: Write,
+{
+    let mut ser = serde_json::Serializer::new(&mut writer);
+    data.serialize(&mut ser)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// How often and how patiently `with_retry` retries a failing operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of tries, including the first.
+    pub attempts: u32,
+    /// Delay before the first retry; doubled after each further failure.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Runs `op` until it succeeds or `policy.attempts` tries have failed,
+/// sleeping with exponential backoff in between. The final error names the
+/// number of attempts and wraps the last failure. The sleep blocks the
+/// calling thread, so this suits synchronous callers only.
+pub fn with_retry<T, F>(policy: &RetryPolicy, mut op: F) -> Result<T, anyhow::Error>
+where
+    F: FnMut() -> Result<T, anyhow::Error>,
+{
+    let mut attempt = 1;
+    let mut delay = policy.base_delay;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= policy.attempts => {
+                return Err(anyhow!("Giving up after {} attempts: {}", attempt, e));
+            }
+            Err(_) => {
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// `save_to_file` retried according to `policy`.
+pub fn save_with_retry<T>(data: &T, path: &str, policy: &RetryPolicy) -> Result<(), anyhow::Error>
+where
+    T: Serialize,
+{
+    with_retry(policy, || save_to_file(data, path))
+}
+
+/// Fails with a checksum mismatch, before parsing, if `path` no longer
+/// matches the sidecar written by `save_to_file`.
+pub fn load_from_file<T>(path: &str) -> Result<T, anyhow::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    verify_checksum(path)?;
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let data = serde_json::from_reader(reader)?;
+    Ok(data)
+}
+
+/// Loads a file written by either `save_to_file_compressed` or
+/// `save_to_file`. A file is read as gzip when its name ends in `.gz` or it
+/// starts with the gzip magic bytes, and as plain JSON otherwise. The
+/// checksum is verified as in `load_from_file`.
+pub fn load_from_file_compressed<T>(path: &str) -> Result<T, anyhow::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    verify_checksum(path)?;
+    let mut reader = BufReader::new(File::open(path)?);
+    let compressed = path.ends_with(".gz") || reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    let data = if compressed {
+        serde_json::from_reader(BufReader::new(GzDecoder::new(reader)))?
+    } else {
+        serde_json::from_reader(reader)?
+    };
+    Ok(data)
+}
+
+/// How imports treat cells and columns left empty. The defaults accept
+/// exactly what `csv::write_table` and `save_to_file` produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// Empty cells in `nullable` columns become `Null`.
+    pub treat_empty_as_null: bool,
+    /// Columns missing from the input, and empty cells not turned into
+    /// `Null`, take the column's `default`.
+    pub fill_defaults: bool,
+    /// Field separator for CSV.
+    pub delimiter: u8,
+    /// Whether CSV starts with a header row naming the columns. Without one,
+    /// every record holds all columns in schema order.
+    pub has_header: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            treat_empty_as_null: true,
+            fill_defaults: false,
+            delimiter: b',',
+            has_header: true,
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Value for an empty cell of `column`, if these options supply one.
+    pub fn empty_cell(&self, column: &DbColumn) -> Option<DbValue> {
+        if self.treat_empty_as_null && column.nullable {
+            Some(DbValue::Null)
+        } else if self.fill_defaults {
+            column.default.clone()
+        } else {
+            None
+        }
+    }
+
+    /// Applies the options to rows loaded from JSON, before validation:
+    /// empty strings count as empty cells, and rows shorter than their
+    /// schema are completed from the defaults when `fill_defaults` is set.
+    pub fn apply(&self, database: &mut Database) -> Result<(), anyhow::Error> {
+        for table in &mut database.tables {
+            let columns = table.schema.columns.clone();
+            for row in table.rows.values_mut() {
+                for (value, column) in row.values.iter_mut().zip(&columns) {
+                    if matches!(value, DbValue::String(s) if s.is_empty()) {
+                        if let Some(filled) = self.empty_cell(column) {
+                            *value = filled;
+                        }
+                    }
+                }
+                if self.fill_defaults {
+                    for column in columns.iter().skip(row.values.len()) {
+                        let default = column.default.clone().ok_or_else(|| anyhow!(
+                            "Table '{}', row {}: missing value for column '{}', which has no default",
+                            table.name, row.id, column.name,
+                        ))?;
+                        row.values.push(default);
+                    }
+                }
+            }
+            table.rebuild_indexes();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails the first `failures` writes, then accepts everything.
+    struct FlakyWriter<'a> {
+        failures: &'a mut u32,
+        out: Vec<u8>,
+    }
+
+    impl Write for FlakyWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if *self.failures > 0 {
+                *self.failures -= 1;
+                return Err(io::Error::other("transient failure"));
+            }
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_with_retry_recovers() {
+        let policy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(1) };
+        let mut failures = 2;
+        let mut tries = 0;
+
+        with_retry(&policy, || {
+            tries += 1;
+            save_to_writer(&vec![1, 2, 3], FlakyWriter { failures: &mut failures, out: Vec::new() })
+        }).unwrap();
+
+        assert_eq!(tries, 3);
+    }
+
+    #[test]
+    fn test_with_retry_gives_up() {
+        let policy = RetryPolicy { attempts: 2, base_delay: Duration::from_millis(1) };
+        let mut failures = 5;
+
+        let err = with_retry(&policy, || {
+            save_to_writer(&"data", FlakyWriter { failures: &mut failures, out: Vec::new() })
+        }).unwrap_err();
+
+        assert!(err.to_string().starts_with("Giving up after 2 attempts"));
+        assert_eq!(failures, 3);
+    }
+
+    /// Serializes a few entries, then fails, like a save cut short.
+    struct FailsMidway;
+
+    impl Serialize for FailsMidway {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::{Error, SerializeSeq};
+            let mut seq = serializer.serialize_seq(None)?;
+            seq.serialize_element(&1)?;
+            seq.serialize_element(&2)?;
+            Err(S::Error::custom("interrupted"))
+        }
+    }
+
+    #[test]
+    fn test_failed_save_keeps_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let path = path.to_str().unwrap();
+
+        save_to_file(&vec!["original"], path).unwrap();
+        assert!(save_to_file(&FailsMidway, path).is_err());
+
+        let loaded: Vec<String> = load_from_file(path).unwrap();
+        assert_eq!(loaded, vec!["original"]);
+        // Only the file and its checksum; no temporary files left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        save_to_file(&vec!["updated"], path).unwrap();
+        let loaded: Vec<String> = load_from_file(path).unwrap();
+        assert_eq!(loaded, vec!["updated"]);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let path = path.to_str().unwrap();
+
+        save_to_file(&vec!["original"], path).unwrap();
+        let mut bytes = fs::read(path).unwrap();
+        bytes[3] ^= 0x01;
+        fs::write(path, &bytes).unwrap();
+
+        let err = load_from_file::<Vec<String>>(path).unwrap_err();
+        assert!(err.to_string().starts_with("Database file checksum mismatch"));
+        let err = load_from_file_compressed::<Vec<String>>(path).unwrap_err();
+        assert!(err.to_string().starts_with("Database file checksum mismatch"));
+
+        // Files without a sidecar load unchecked.
+        fs::remove_file(checksum_path(Path::new(path))).unwrap();
+        let loaded: Vec<String> = load_from_file(path).unwrap();
+        assert_eq!(loaded, vec!["osiginal"]);
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        use crate::types::database::Database;
+        use crate::types::schema::DbValue;
+        use crate::types::table::create_test_table;
+
+        let mut db = Database::new("test_db");
+        for name in ["people", "staff"] {
+            let mut table = create_test_table(name);
+            for n in 0..50 {
+                table.insert(vec![DbValue::Integer(n), DbValue::String(format!("{} {}", name, n))]).unwrap();
+            }
+            db.add_table(table).unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let gz = dir.path().join("db.json.gz");
+        let gz = gz.to_str().unwrap();
+        save_to_file_compressed(&db, gz).unwrap();
+        assert_eq!(&fs::read(gz).unwrap()[..2], &GZIP_MAGIC);
+
+        let loaded: Database = load_from_file_compressed(gz).unwrap();
+        assert_eq!(loaded.tables, db.tables);
+
+        // Detected by magic bytes without the extension; plain files still load.
+        let renamed = dir.path().join("db.bin");
+        fs::rename(gz, &renamed).unwrap();
+        let loaded: Database = load_from_file_compressed(renamed.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.tables, db.tables);
+
+        let plain = dir.path().join("db.json");
+        let plain = plain.to_str().unwrap();
+        save_to_file(&db, plain).unwrap();
+        let loaded: Database = load_from_file_compressed(plain).unwrap();
+        assert_eq!(loaded.tables, db.tables);
+    }
+}
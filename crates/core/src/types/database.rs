@@ -1,12 +1,49 @@
+use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
-use crate::types::table::Table;
+use std::collections::{HashMap, HashSet};
+use crate::types::schema::{validate_identifier, DbColumn, DbValue};
+use crate::types::table::{Row, Table};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Database {
     pub name: String,
     pub tables: Vec<Table>,
 }
 
+/// What `Database::merge` does with an incoming table whose name is taken.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// Keep the existing table and drop the incoming one.
+    Skip,
+    /// Add the incoming table as `<name>_1`, `<name>_2`, ... whichever is free.
+    Rename,
+    /// Reject the merge.
+    Fail,
+}
+
+/// Tables as nodes and column references as edges, for rendering the schema
+/// as a graph.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RelationshipGraph {
+    pub nodes: Vec<TableNode>,
+    pub edges: Vec<Relationship>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableNode {
+    pub name: String,
+    pub columns: Vec<DbColumn>,
+}
+
+/// `from_table.from_column` references rows of `to_table`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Relationship {
+    pub from_table: String,
+    pub from_column: String,
+    pub to_table: String,
+}
+
 impl Database {
     pub fn new(name: &str) -> Self {
         Database {
@@ -15,8 +52,13 @@ impl Database {
         }
     }
 
-    pub fn add_table(&mut self, table: Table) {
+    /// Adds `table`, failing if a table with the same name already exists.
+    pub fn add_table(&mut self, table: Table) -> anyhow::Result<()> {
+        if self.get_table(table.name()).is_some() {
+            bail!("Table '{}' already exists", table.name());
+        }
         self.tables.push(table);
+        Ok(())
     }
 
     pub fn get_table(&self, name: &str) -> Option<&Table> {
@@ -31,11 +73,174 @@ impl Database {
         let index = self.tables.iter().position(|t| t.name() == name);
         index.map(|i| self.tables.remove(i))
     }
+
+    /// Renames table `old` to `new`, keeping its rows, and points columns
+    /// that referenced `old` at `new`. Fails if `old` is missing, `new` is not
+    /// a valid name, or a table called `new` already exists.
+    pub fn rename_table(&mut self, old: &str, new: &str) -> anyhow::Result<()> {
+        validate_identifier(new)?;
+        if self.get_table(old).is_none() {
+            bail!("Table '{}' not found", old);
+        }
+        if self.get_table(new).is_some() {
+            bail!("Table '{}' already exists", new);
+        }
+
+        for table in &mut self.tables {
+            if table.name == old {
+                table.name = new.to_string();
+            }
+            for column in &mut table.schema.columns {
+                if column.references.as_deref() == Some(old) {
+                    column.references = Some(new.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a copy of table `source` called `new_name`, with the same schema,
+    /// rows, ids and id counter. Fails if `source` is missing, `new_name` is
+    /// not a valid name, or a table called `new_name` already exists.
+    pub fn duplicate_table(&mut self, source: &str, new_name: &str) -> anyhow::Result<()> {
+        validate_identifier(new_name)?;
+        let mut copy = self.get_table(source)
+            .ok_or_else(|| anyhow!("Table '{}' not found", source))?
+            .clone();
+        copy.name = new_name.to_string();
+        self.add_table(copy)
+    }
+
+    /// Adds the tables of `other` with their rows, ids and schemas unchanged,
+    /// resolving name clashes with `on_conflict`. Columns of merged tables
+    /// that referenced a renamed table are pointed at its new name. Nothing
+    /// is added if the merge fails.
+    pub fn merge(&mut self, other: Database, on_conflict: MergeStrategy) -> anyhow::Result<()> {
+        let mut taken: HashSet<String> = self.tables.iter()
+            .chain(&other.tables)
+            .map(|t| t.name().to_string())
+            .collect();
+        let mut renamed = HashMap::new();
+        let mut incoming = Vec::new();
+
+        for mut table in other.tables {
+            if self.get_table(table.name()).is_some() {
+                match on_conflict {
+                    MergeStrategy::Skip => continue,
+                    MergeStrategy::Fail => bail!("Table '{}' already exists", table.name()),
+                    MergeStrategy::Rename => {
+                        let new_name = (1..)
+                            .map(|n| format!("{}_{}", table.name(), n))
+                            .find(|name| !taken.contains(name))
+                            .expect("some numeric suffix is free");
+                        taken.insert(new_name.clone());
+                        renamed.insert(table.name().to_string(), new_name.clone());
+                        table.name = new_name;
+                    }
+                }
+            }
+            incoming.push(table);
+        }
+
+        for table in &mut incoming {
+            for column in &mut table.schema.columns {
+                if let Some(new) = column.references.as_ref().and_then(|r| renamed.get(r)) {
+                    column.references = Some(new.clone());
+                }
+            }
+        }
+        self.tables.extend(incoming);
+        Ok(())
+    }
+
+    /// Checks a database read from outside, reporting the first failure:
+    /// table names must be valid and distinct, schemas must pass
+    /// `DbSchema::validate`, every row must pass `Table::validate`, and
+    /// `unique` columns must not repeat a value.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for table in &self.tables {
+            validate_identifier(table.name())
+                .map_err(|e| anyhow!("Invalid table name '{}': {}", table.name(), e))?;
+            if !names.insert(table.name()) {
+                bail!("Table '{}' appears more than once", table.name());
+            }
+            table.schema.validate().map_err(|e| anyhow!("Table '{}': {}", table.name(), e))?;
+            for row in table.get_rows_ordered() {
+                table.validate(&row.values)
+                    .map_err(|e| anyhow!("Table '{}', row {}: {}", table.name(), row.id, e))?;
+            }
+            table.validate_unique().map_err(|e| anyhow!("Table '{}': {}", table.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Names of the tables whose schema has a column called `column`.
+    pub fn tables_with_column(&self, column: &str) -> Vec<String> {
+        self.tables.iter()
+            .filter(|t| t.schema.column_index(column).is_some())
+            .map(|t| t.name().to_string())
+            .collect()
+    }
+
+    /// Every row holding `value` in any column, paired with its table's name,
+    /// in table order and then id order. Tables without a column of the
+    /// value's type are skipped without scanning their rows.
+    pub fn search(&self, value: &DbValue) -> Vec<(String, Row)> {
+        let value_type = value.value_type();
+        self.tables.iter()
+            .filter(|t| match &value_type {
+                Some(value_type) => t.schema.columns.iter().any(|c| &c.column_type == value_type),
+                None => t.schema.columns.iter().any(|c| c.nullable),
+            })
+            .flat_map(|t| {
+                t.get_rows_ordered().into_iter()
+                    .filter(|r| r.values.contains(value))
+                    .map(|r| (t.name().to_string(), r.clone()))
+            })
+            .collect()
+    }
+
+    /// Builds the graph of tables and the references between them. References
+    /// to tables that do not exist are left out.
+    pub fn relationship_graph(&self) -> RelationshipGraph {
+        let nodes = self.tables.iter()
+            .map(|t| TableNode {
+                name: t.name().to_string(),
+                columns: t.schema.columns.clone(),
+            })
+            .collect();
+
+        let edges = self.tables.iter()
+            .flat_map(|t| t.schema.columns.iter().map(move |c| (t, c)))
+            .filter_map(|(t, c)| {
+                let target = c.references.as_ref()?;
+                self.get_table(target)?;
+                Some(Relationship {
+                    from_table: t.name().to_string(),
+                    from_column: c.name.clone(),
+                    to_table: target.clone(),
+                })
+            })
+            .collect();
+
+        RelationshipGraph { nodes, edges }
+    }
+
+    /// Compacts every table whose fragmentation exceeds `threshold`, returning
+    /// the names of the tables that released storage. Row ids are unchanged.
+    pub fn compact(&mut self, threshold: f64) -> Vec<String> {
+        self.tables.iter_mut()
+            .filter(|t| t.fragmentation() > threshold)
+            .filter_map(|t| t.compact().then(|| t.name().to_string()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::types::table::create_test_table;
+    use crate::io::{load_from_file, save_to_file};
     use super::*;
 
     #[test]
@@ -45,23 +250,318 @@ mod tests {
         let table1 = create_test_table("table1");
         let table2 = create_test_table("table2");
 
-        db.add_table(table1.clone());
-        db.add_table(table2.clone());
+        db.add_table(table1.clone()).unwrap();
+        db.add_table(table2.clone()).unwrap();
 
         assert_eq!(db.get_table("table1"), Some(&table1));
         assert_eq!(db.get_table("table2"), Some(&table2));
 
         let table3 = create_test_table("table3");
-        db.add_table(table3.clone());
+        db.add_table(table3.clone()).unwrap();
 
         assert_eq!(db.get_table("table3"), Some(&table3));
 
         let table4 = create_test_table("table4");
-        db.add_table(table4.clone());
+        db.add_table(table4.clone()).unwrap();
 
         assert_eq!(db.get_table("table4"), Some(&table4));
 
         assert_eq!(db.delete_table("table4"), Some(table4));
         assert_eq!(db.get_table("table4"), None);
     }
+
+    #[test]
+    fn test_add_table_rejects_duplicate_name() {
+        let mut db = Database::new("test_db");
+        let mut original = create_test_table("people");
+        original.insert(vec![DbValue::Integer(1), DbValue::String("ann".to_string())]).unwrap();
+        db.add_table(original.clone()).unwrap();
+
+        let err = db.add_table(create_test_table("people")).unwrap_err();
+        assert_eq!(err.to_string(), "Table 'people' already exists");
+        assert_eq!(db.tables.len(), 1);
+        assert_eq!(db.get_table("people"), Some(&original));
+    }
+
+    #[test]
+    fn test_duplicate_table() {
+        let mut db = Database::new("test_db");
+        let mut people = create_test_table("people");
+        for (id, name) in [(1, "ann"), (2, "bob")] {
+            people.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+        people.delete(0).unwrap();
+        db.add_table(people.clone()).unwrap();
+
+        db.duplicate_table("people", "people_copy").unwrap();
+        let copy = db.get_table_mut("people_copy").unwrap();
+        assert_eq!(copy.rows, people.rows);
+        assert_eq!(copy.index, people.index);
+
+        copy.update(1, vec![DbValue::Integer(9), DbValue::String("zed".to_string())]).unwrap();
+        assert_eq!(copy.insert(vec![DbValue::Integer(3), DbValue::String("cid".to_string())]).unwrap(), 2);
+        assert_eq!(db.get_table("people"), Some(&people));
+
+        assert_eq!(db.duplicate_table("missing", "x").unwrap_err().to_string(), "Table 'missing' not found");
+        assert_eq!(db.duplicate_table("people", "people_copy").unwrap_err().to_string(), "Table 'people_copy' already exists");
+        assert!(db.duplicate_table("people", "").is_err());
+    }
+
+    fn create_merge_source(names: &[&str]) -> Database {
+        let mut db = Database::new("other");
+        for (i, name) in names.iter().enumerate() {
+            let mut table = create_test_table(name);
+            for _ in 0..2 {
+                table.insert(vec![DbValue::Integer(i as i32), DbValue::String(format!("from other {}", i))]).unwrap();
+            }
+            table.delete(0).unwrap();
+            db.add_table(table).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("people")).unwrap();
+        let other = create_merge_source(&["orders", "items"]);
+
+        for strategy in [MergeStrategy::Skip, MergeStrategy::Rename, MergeStrategy::Fail] {
+            let mut merged = db.clone();
+            merged.merge(other.clone(), strategy).unwrap();
+            assert_eq!(merged.name, "test_db");
+            assert_eq!(merged.tables.len(), 3);
+            assert_eq!(merged.get_table("orders"), other.get_table("orders"));
+            assert_eq!(merged.get_table("items"), other.get_table("items"));
+        }
+    }
+
+    #[test]
+    fn test_merge_skip() {
+        let mut db = Database::new("test_db");
+        let people = create_test_table("people");
+        db.add_table(people.clone()).unwrap();
+        let other = create_merge_source(&["people", "orders"]);
+
+        db.merge(other.clone(), MergeStrategy::Skip).unwrap();
+        assert_eq!(db.tables.len(), 2);
+        assert_eq!(db.get_table("people"), Some(&people));
+        assert_eq!(db.get_table("orders"), other.get_table("orders"));
+    }
+
+    #[test]
+    fn test_merge_rename() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("people")).unwrap();
+        db.add_table(create_test_table("orders")).unwrap();
+        let mut other = create_merge_source(&["people", "orders", "orders_1"]);
+        other.get_table_mut("orders_1").unwrap().schema.columns[0].references = Some("people".to_string());
+
+        db.merge(other.clone(), MergeStrategy::Rename).unwrap();
+        assert_eq!(db.tables.len(), 5);
+
+        let renamed = db.get_table("people_1").unwrap();
+        let original = other.get_table("people").unwrap();
+        assert_eq!(renamed.rows, original.rows);
+        assert_eq!(renamed.index, original.index);
+        assert_eq!(renamed.schema, original.schema);
+
+        assert_eq!(db.get_table("orders_2").unwrap().rows, other.get_table("orders").unwrap().rows);
+        let kept = db.get_table("orders_1").unwrap();
+        assert_eq!(kept.rows, other.get_table("orders_1").unwrap().rows);
+        assert_eq!(kept.schema.columns[0].references.as_deref(), Some("people_1"));
+    }
+
+    #[test]
+    fn test_merge_fail() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("people")).unwrap();
+        let before = db.clone();
+        let other = create_merge_source(&["orders", "people"]);
+
+        let err = db.merge(other, MergeStrategy::Fail).unwrap_err();
+        assert_eq!(err.to_string(), "Table 'people' already exists");
+        assert_eq!(db, before);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut db = Database::new("test_db");
+        let mut table = create_test_table("people");
+        table.insert(vec![DbValue::Integer(1), DbValue::String("ann".to_string())]).unwrap();
+        db.add_table(table).unwrap();
+        assert!(db.validate().is_ok());
+
+        db.get_table_mut("people").unwrap().get_row_mut(0).unwrap().values[0] = DbValue::String("one".to_string());
+        let err = db.validate().unwrap_err().to_string();
+        assert!(err.starts_with("Table 'people', row 0: "), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_tables() {
+        let valid = || {
+            let mut db = Database::new("test_db");
+            let mut table = create_test_table("people");
+            table.schema.columns[0].unique = true;
+            table.insert(vec![DbValue::Integer(1), DbValue::String("ann".to_string())]).unwrap();
+            table.insert(vec![DbValue::Integer(2), DbValue::String("bob".to_string())]).unwrap();
+            db.add_table(table).unwrap();
+            db
+        };
+        assert!(valid().validate().is_ok());
+
+        let mut db = valid();
+        db.tables.push(db.tables[0].clone());
+        assert_eq!(db.validate().unwrap_err().to_string(), "Table 'people' appears more than once");
+
+        let mut db = valid();
+        db.tables[0].name = "a/b".to_string();
+        assert!(db.validate().unwrap_err().to_string().starts_with("Invalid table name 'a/b'"));
+
+        let mut db = valid();
+        db.tables[0].schema.columns[1].name = "id".to_string();
+        assert!(db.validate().is_err());
+
+        let mut db = valid();
+        db.get_table_mut("people").unwrap().get_row_mut(1).unwrap().values[0] = DbValue::Integer(1);
+        assert_eq!(
+            db.validate().unwrap_err().to_string(),
+            "Table 'people': Rows 0 and 1 both hold 1 in unique column 'id'",
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut db = Database::new("test_db");
+        for (name, rows) in [("staff", [(1, "ann"), (2, "bob")]), ("clients", [(7, "cid"), (8, "ann")])] {
+            let mut table = create_test_table(name);
+            for (id, person) in rows {
+                table.insert(vec![DbValue::Integer(id), DbValue::String(person.to_string())]).unwrap();
+            }
+            db.add_table(table).unwrap();
+        }
+
+        let hits: Vec<(String, u32)> = db.search(&DbValue::String("ann".to_string())).into_iter()
+            .map(|(table, row)| (table, row.id))
+            .collect();
+        assert_eq!(hits, vec![("staff".to_string(), 0), ("clients".to_string(), 1)]);
+
+        assert!(db.search(&DbValue::String("zed".to_string())).is_empty());
+        assert!(db.search(&DbValue::Money(1.0)).is_empty());
+        assert_eq!(db.search(&DbValue::Integer(7)).len(), 1);
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let mut db = Database::new("test_db");
+        let mut people = create_test_table("people");
+        people.insert(vec![DbValue::Integer(1), DbValue::String("ann".to_string())]).unwrap();
+        db.add_table(people).unwrap();
+        let mut orders = create_test_table("orders");
+        orders.schema.columns[0].references = Some("people".to_string());
+        db.add_table(orders).unwrap();
+
+        db.rename_table("people", "customers").unwrap();
+
+        assert!(db.get_table("people").is_none());
+        let customers = db.get_table("customers").unwrap();
+        assert_eq!(customers.name(), "customers");
+        assert_eq!(customers.len(), 1);
+        assert_eq!(db.get_table("orders").unwrap().schema.columns[0].references.as_deref(), Some("customers"));
+    }
+
+    #[test]
+    fn test_rename_table_errors() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("people")).unwrap();
+        db.add_table(create_test_table("staff")).unwrap();
+
+        let err = db.rename_table("people", "staff").unwrap_err();
+        assert_eq!(err.to_string(), "Table 'staff' already exists");
+        assert!(db.get_table("people").is_some());
+
+        assert!(db.rename_table("missing", "other").is_err());
+        assert!(db.rename_table("people", "a/b").is_err());
+    }
+
+    #[test]
+    fn test_indexed_column_after_load() {
+        let mut table = create_test_table("people");
+        table.schema.columns[1].indexed = true;
+        table.insert(vec![DbValue::Integer(1), DbValue::String("ann".to_string())]).unwrap();
+        table.insert(vec![DbValue::Integer(2), DbValue::String("bob".to_string())]).unwrap();
+        table.insert(vec![DbValue::Integer(3), DbValue::String("ann".to_string())]).unwrap();
+
+        let mut db = Database::new("test_db");
+        db.add_table(table).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        save_to_file(&db, path.to_str().unwrap()).unwrap();
+        let loaded: Database = load_from_file(path.to_str().unwrap()).unwrap();
+
+        let table = loaded.get_table("people").unwrap();
+        assert!(table.is_indexed("name"));
+        let ids: Vec<u32> = table.find_by("name", &DbValue::String("ann".to_string()))
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_compact_threshold() {
+        let mut db = Database::new("test_db");
+        let mut sparse = create_test_table("sparse");
+        let mut dense = create_test_table("dense");
+        for i in 0..100 {
+            sparse.insert(vec![DbValue::Integer(i), DbValue::String("x".to_string())]).unwrap();
+            dense.insert(vec![DbValue::Integer(i), DbValue::String("x".to_string())]).unwrap();
+        }
+        for id in 0..90 {
+            sparse.delete(id).unwrap();
+        }
+        dense.delete(0).unwrap();
+        db.add_table(sparse).unwrap();
+        db.add_table(dense).unwrap();
+
+        assert_eq!(db.compact(0.5), vec!["sparse".to_string()]);
+        let sparse = db.get_table("sparse").unwrap();
+        assert_eq!(sparse.index, 100);
+        assert!(sparse.contains(90));
+
+        assert!(db.compact(0.5).is_empty());
+    }
+
+    #[test]
+    fn test_tables_with_column() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("people")).unwrap();
+        db.add_table(create_test_table("staff")).unwrap();
+        let mut other = create_test_table("orders");
+        other.schema.columns[1].name = "total".to_string();
+        db.add_table(other).unwrap();
+
+        assert_eq!(db.tables_with_column("name"), vec!["people", "staff"]);
+        assert_eq!(db.tables_with_column("id").len(), 3);
+        assert!(db.tables_with_column("missing").is_empty());
+    }
+
+    #[test]
+    fn test_relationship_graph() {
+        let mut db = Database::new("test_db");
+        db.add_table(create_test_table("customers")).unwrap();
+        let mut orders = create_test_table("orders");
+        orders.schema.columns[0].references = Some("customers".to_string());
+        orders.schema.columns[1].references = Some("missing".to_string());
+        db.add_table(orders).unwrap();
+
+        let graph = db.relationship_graph();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges, vec![Relationship {
+            from_table: "orders".to_string(),
+            from_column: "id".to_string(),
+            to_table: "customers".to_string(),
+        }]);
+    }
 }
@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use crate::types::schema::{DbSchema, DbValue};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum FilterOp {
+    #[serde(rename = "eq")]
+    Eq,
+    #[serde(rename = "gt")]
+    Gt,
+    #[serde(rename = "lt")]
+    Lt,
+    #[serde(rename = "contains")]
+    Contains,
+}
+
+impl FromStr for FilterOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eq" => Ok(FilterOp::Eq),
+            "gt" => Ok(FilterOp::Gt),
+            "lt" => Ok(FilterOp::Lt),
+            "contains" => Ok(FilterOp::Contains),
+            _ => bail!("Unknown filter operator '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColumnPredicate {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: DbValue,
+}
+
+impl ColumnPredicate {
+    pub fn new(column: &str, op: FilterOp, value: DbValue) -> Self {
+        ColumnPredicate {
+            column: column.to_string(),
+            op,
+            value,
+        }
+    }
+
+    /// Parses a `column:op:value` query, reading the value as the column's
+    /// type (or as a string for `contains`). The value may itself contain ':'.
+    pub fn parse(spec: &str, schema: &DbSchema) -> anyhow::Result<Self> {
+        let mut parts = spec.splitn(3, ':');
+        let (Some(column), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Filter '{}' must look like column:op:value", spec);
+        };
+        let index = schema.column_index(column)
+            .ok_or_else(|| anyhow!("Column '{}' not found", column))?;
+        let op = op.parse::<FilterOp>()?;
+        let value = match op {
+            FilterOp::Contains => DbValue::String(value.to_string()),
+            _ => DbValue::parse(value, &schema.columns[index].column_type)?,
+        };
+
+        Ok(ColumnPredicate::new(column, op, value))
+    }
+
+    /// Values of a different type than the operand never match.
+    pub fn matches(&self, value: &DbValue) -> bool {
+        match self.op {
+            FilterOp::Eq => value == &self.value,
+            FilterOp::Gt => compare(value, &self.value) == Some(Ordering::Greater),
+            FilterOp::Lt => compare(value, &self.value) == Some(Ordering::Less),
+            FilterOp::Contains => match (value, &self.value) {
+                (DbValue::String(s), DbValue::String(needle)) => s.contains(needle.as_str()),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A conjunction of column predicates: a row matches when every predicate does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Filter {
+    pub predicates: Vec<ColumnPredicate>,
+}
+
+impl Filter {
+    pub fn new(predicates: Vec<ColumnPredicate>) -> Self {
+        Filter { predicates }
+    }
+
+    /// Resolves every predicate's column against the schema, failing on the
+    /// first unknown column name.
+    pub fn resolve(&self, schema: &DbSchema) -> anyhow::Result<Vec<(usize, &ColumnPredicate)>> {
+        resolve(&self.predicates, schema)
+    }
+}
+
+/// Pairs each predicate with the index of its column in `schema`.
+pub fn resolve<'a>(predicates: &'a [ColumnPredicate], schema: &DbSchema) -> anyhow::Result<Vec<(usize, &'a ColumnPredicate)>> {
+    predicates.iter()
+        .map(|p| {
+            schema.column_index(&p.column)
+                .map(|i| (i, p))
+                .ok_or_else(|| anyhow!("Column '{}' not found", p.column))
+        })
+        .collect()
+}
+
+fn compare(a: &DbValue, b: &DbValue) -> Option<Ordering> {
+    (a.value_type().is_some() && a.value_type() == b.value_type()).then(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predicate_operators() {
+        let gt = ColumnPredicate::new("n", FilterOp::Gt, DbValue::Integer(10));
+        assert!(gt.matches(&DbValue::Integer(11)));
+        assert!(!gt.matches(&DbValue::Integer(10)));
+        assert!(!gt.matches(&DbValue::Real(11.0)));
+
+        let contains = ColumnPredicate::new("s", FilterOp::Contains, DbValue::String("oh".to_string()));
+        assert!(contains.matches(&DbValue::String("John".to_string())));
+        assert!(!contains.matches(&DbValue::String("Jane".to_string())));
+    }
+
+    #[test]
+    fn test_filter_deser() {
+        let filter: Filter = serde_json::from_str(
+            r#"[{"column": "balance", "op": "gt", "value": {"Money": 1000.0}}]"#
+        ).unwrap();
+
+        assert_eq!(filter, Filter::new(vec![
+            ColumnPredicate::new("balance", FilterOp::Gt, DbValue::Money(1000.0)),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_predicate() {
+        let schema = DbSchema::builder()
+            .column("balance", crate::types::schema::DbColumnType::Money)
+            .column("name", crate::types::schema::DbColumnType::String)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            ColumnPredicate::parse("balance:gt:1000", &schema).unwrap(),
+            ColumnPredicate::new("balance", FilterOp::Gt, DbValue::Money(1000.0)),
+        );
+        assert_eq!(
+            ColumnPredicate::parse("name:contains:a:b", &schema).unwrap(),
+            ColumnPredicate::new("name", FilterOp::Contains, DbValue::String("a:b".to_string())),
+        );
+        assert_eq!(ColumnPredicate::parse("age:gt:1", &schema).unwrap_err().to_string(), "Column 'age' not found");
+        assert!(ColumnPredicate::parse("balance:gte:1", &schema).is_err());
+        assert!(ColumnPredicate::parse("balance:gt:lots", &schema).is_err());
+        assert!(ColumnPredicate::parse("balance", &schema).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 pub mod database;
 pub mod table;
 pub mod schema;
+pub mod filter;
 
+pub mod record;
@@ -0,0 +1,128 @@
+use anyhow::bail;
+use crate::types::schema::{DbSchema, DbValue};
+
+/// Builds a row for `schema` from values set by column name, so callers do
+/// not need to know the column order.
+#[derive(Debug, Clone)]
+pub struct RecordBuilder<'a> {
+    schema: &'a DbSchema,
+    values: Vec<(String, DbValue)>,
+}
+
+impl<'a> RecordBuilder<'a> {
+    pub fn new(schema: &'a DbSchema) -> Self {
+        RecordBuilder {
+            schema,
+            values: Vec::new(),
+        }
+    }
+
+    /// Sets `column`, replacing any value set for it before.
+    pub fn set(mut self, column: &str, value: DbValue) -> Self {
+        self.values.retain(|(name, _)| name != column);
+        self.values.push((column.to_string(), value));
+        self
+    }
+
+    /// Returns the values in schema order, taking each column left unset from
+    /// its `default` as `Table::insert` does. Fails on a column the schema
+    /// does not have, a value of the wrong type, or an unset column without a
+    /// default.
+    pub fn build(self) -> anyhow::Result<Vec<DbValue>> {
+        let mut row: Vec<Option<DbValue>> = vec![None; self.schema.columns.len()];
+
+        for (name, value) in self.values {
+            let Some(i) = self.schema.column_index(&name) else {
+                bail!("Column '{}' not found", name);
+            };
+            let column = &self.schema.columns[i];
+            match value.value_type() {
+                None if !column.nullable => bail!("Column '{}' is not nullable", name),
+                Some(value_type) if value_type != column.column_type => {
+                    bail!("Column '{}' expects {:?}, got {:?}", name, column.column_type, value_type);
+                }
+                _ => {}
+            }
+            row[i] = Some(value);
+        }
+
+        row.into_iter().zip(&self.schema.columns)
+            .map(|(value, column)| match value.or_else(|| column.default.clone()) {
+                Some(value) => Ok(value),
+                None => bail!("Missing value for column '{}'", column.name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::schema::DbColumnType;
+    use crate::types::table::create_test_schema;
+
+    #[test]
+    fn test_build_in_schema_order() {
+        let schema = create_test_schema();
+        let row = RecordBuilder::new(&schema)
+            .set("col2", DbValue::String("test".to_string()))
+            .set("col1", DbValue::Integer(42))
+            .build()
+            .unwrap();
+
+        assert_eq!(row, vec![DbValue::Integer(42), DbValue::String("test".to_string())]);
+    }
+
+    #[test]
+    fn test_build_missing_column() {
+        let schema = create_test_schema();
+        let err = RecordBuilder::new(&schema)
+            .set("col1", DbValue::Integer(42))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "Missing value for column 'col2'");
+    }
+
+    #[test]
+    fn test_build_fills_defaults() {
+        let schema = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .default_value("id", DbValue::Integer(0))
+            .column("name", DbColumnType::String)
+            .column("note", DbColumnType::String)
+            .default_value("note", DbValue::String("none".to_string()))
+            .build()
+            .unwrap();
+        let row = RecordBuilder::new(&schema)
+            .set("name", DbValue::String("test".to_string()))
+            .build()
+            .unwrap();
+
+        assert_eq!(row, vec![DbValue::Integer(0), DbValue::String("test".to_string()), DbValue::String("none".to_string())]);
+    }
+
+    #[test]
+    fn test_build_type_mismatch() {
+        let schema = create_test_schema();
+        let err = RecordBuilder::new(&schema)
+            .set("col1", DbValue::String("42".to_string()))
+            .set("col2", DbValue::String("test".to_string()))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "Column 'col1' expects Integer, got String");
+    }
+
+    #[test]
+    fn test_build_extra_column() {
+        let schema = create_test_schema();
+        let result = RecordBuilder::new(&schema)
+            .set("col1", DbValue::Integer(42))
+            .set("col2", DbValue::String("test".to_string()))
+            .set("col3", DbValue::Integer(1))
+            .build();
+
+        assert!(result.is_err());
+    }
+}
@@ -1,4 +1,7 @@
+use std::cmp::Ordering;
+use std::fmt;
 use std::hash::Hash;
+use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -9,6 +12,26 @@ pub enum DbValue {
     String(String),
     Money(f64),
     MoneyRange(f64, f64),
+    Boolean(bool),
+    /// Midnight UTC of a calendar day, as a Unix timestamp in seconds.
+    Date(i64),
+    /// A share from 0 to 100 inclusive.
+    Percentage(f64),
+    /// A missing value; accepted only by `nullable` columns.
+    Null,
+}
+
+/// `amount` rounded to two decimal places, with ties to even. A fraction
+/// within `TIE_EPSILON` of half a cent counts as a tie, absorbing the error
+/// from representing the amount in binary.
+fn round_cents(amount: f64) -> f64 {
+    const TIE_EPSILON: f64 = 1e-6;
+    let cents = amount * 100.0;
+    if (cents.abs().fract() - 0.5).abs() < TIE_EPSILON {
+        (cents.trunc() + 0.5f64.copysign(cents)).round_ties_even() / 100.0
+    } else {
+        cents.round() / 100.0
+    }
 }
 
 impl Eq for DbValue {}
@@ -31,11 +54,124 @@ impl PartialEq for DbValue {
                 const EPSILON: f64 = 1e-10;
                 (a1 - b1).abs() < EPSILON && (a2 - b2).abs() < EPSILON
             },
+            (DbValue::Boolean(a), DbValue::Boolean(b)) => a == b,
+            (DbValue::Date(a), DbValue::Date(b)) => a == b,
+            (DbValue::Percentage(a), DbValue::Percentage(b)) => {
+                const EPSILON: f64 = 1e-10;
+                (a - b).abs() < EPSILON
+            },
+            (DbValue::Null, DbValue::Null) => true,
             _ => false,
         }
     }
 }
 
+impl DbValue {
+    /// Parses user-entered text as a value of `column_type`. Money ranges are
+    /// written `min..max`; a Char must be exactly one character; a Boolean is
+    /// `true` or `false`; a Date is `YYYY-MM-DD`; a Percentage may end in `%`.
+    pub fn parse(text: &str, column_type: &DbColumnType) -> anyhow::Result<DbValue> {
+        let number = |text: &str| text.trim().parse::<f64>()
+            .map_err(|_| anyhow!("'{}' is not a number", text));
+
+        Ok(match column_type {
+            DbColumnType::Integer => DbValue::Integer(text.trim().parse()
+                .map_err(|_| anyhow!("'{}' is not an integer", text))?),
+            DbColumnType::Real => DbValue::Real(text.trim().parse()
+                .map_err(|_| anyhow!("'{}' is not a number", text))?),
+            DbColumnType::Char => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => DbValue::Char(c),
+                    _ => bail!("'{}' is not a single character", text),
+                }
+            }
+            DbColumnType::String => DbValue::String(text.to_string()),
+            DbColumnType::Money => DbValue::Money(number(text)?),
+            DbColumnType::MoneyRange => {
+                let (min, max) = text.split_once("..")
+                    .ok_or_else(|| anyhow!("'{}' is not a range, expected min..max", text))?;
+                let (min, max) = (number(min)?, number(max)?);
+                if min > max {
+                    bail!("Range start {} exceeds its end {}", min, max);
+                }
+                DbValue::MoneyRange(min, max)
+            }
+            DbColumnType::Boolean => DbValue::Boolean(text.trim().parse()
+                .map_err(|_| anyhow!("'{}' is not true or false", text))?),
+            DbColumnType::Date => DbValue::Date(parse_date(text)?),
+            DbColumnType::Percentage => {
+                let trimmed = text.trim();
+                DbValue::Percentage(number(trimmed.strip_suffix('%').unwrap_or(trimmed))?)
+            }
+        })
+    }
+
+    /// Like `==`, but numeric values (including both range bounds) are equal
+    /// when they differ by less than `epsilon` instead of the fixed per-type
+    /// tolerance.
+    pub fn approx_eq(&self, other: &DbValue, epsilon: f64) -> bool {
+        match (self, other) {
+            (DbValue::Real(a), DbValue::Real(b)) => ((*a as f64) - (*b as f64)).abs() < epsilon,
+            (DbValue::Money(a), DbValue::Money(b)) => (a - b).abs() < epsilon,
+            (DbValue::MoneyRange(a1, a2), DbValue::MoneyRange(b1, b2)) => {
+                (a1 - b1).abs() < epsilon && (a2 - b2).abs() < epsilon
+            }
+            (DbValue::Percentage(a), DbValue::Percentage(b)) => (a - b).abs() < epsilon,
+            _ => self == other,
+        }
+    }
+}
+
+/// Renders a value for display: money with a `$` and two decimals, ranges
+/// as `$min-$max`, dates as `YYYY-MM-DD`, percentages with a trailing `%`,
+/// and everything else bare.
+impl fmt::Display for DbValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbValue::Integer(n) => write!(f, "{}", n),
+            DbValue::Real(n) => write!(f, "{}", n),
+            DbValue::Char(c) => write!(f, "{}", c),
+            DbValue::String(s) => f.write_str(s),
+            DbValue::Money(m) => write!(f, "${:.2}", m),
+            DbValue::MoneyRange(start, end) => write!(f, "${:.2}-${:.2}", start, end),
+            DbValue::Boolean(b) => write!(f, "{}", b),
+            DbValue::Date(d) => f.write_str(&format_date(*d)),
+            DbValue::Percentage(p) => write!(f, "{}%", p),
+            DbValue::Null => f.write_str("null"),
+        }
+    }
+}
+
+/// Values of the same type order naturally (floats via `total_cmp`, ranges by
+/// lower then upper bound); values of different types order by variant.
+/// Floats are compared exactly, as `Hash` sees them: the epsilon `PartialEq`
+/// is not transitive, so an order built on it would not be total.
+impl Ord for DbValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (DbValue::Integer(a), DbValue::Integer(b)) => a.cmp(b),
+            (DbValue::Real(a), DbValue::Real(b)) => a.total_cmp(b),
+            (DbValue::Char(a), DbValue::Char(b)) => a.cmp(b),
+            (DbValue::String(a), DbValue::String(b)) => a.cmp(b),
+            (DbValue::Money(a), DbValue::Money(b)) => a.total_cmp(b),
+            (DbValue::MoneyRange(a1, a2), DbValue::MoneyRange(b1, b2)) => {
+                a1.total_cmp(b1).then(a2.total_cmp(b2))
+            },
+            (DbValue::Boolean(a), DbValue::Boolean(b)) => a.cmp(b),
+            (DbValue::Date(a), DbValue::Date(b)) => a.cmp(b),
+            (DbValue::Percentage(a), DbValue::Percentage(b)) => a.total_cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for DbValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Hash for DbValue {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
@@ -48,20 +184,166 @@ impl Hash for DbValue {
                 m1.to_bits().hash(state);
                 m2.to_bits().hash(state);
             }
+            DbValue::Boolean(b) => b.hash(state),
+            DbValue::Date(d) => d.hash(state),
+            DbValue::Percentage(p) => p.to_bits().hash(state),
+            DbValue::Null => {}
         }
     }
 }
 
 impl DbValue {
-    pub fn value_type(&self) -> DbColumnType {
+    fn rank(&self) -> u8 {
+        match self {
+            DbValue::Integer(_) => 0,
+            DbValue::Real(_) => 1,
+            DbValue::Char(_) => 2,
+            DbValue::String(_) => 3,
+            DbValue::Money(_) => 4,
+            DbValue::MoneyRange(_, _) => 5,
+            DbValue::Boolean(_) => 6,
+            DbValue::Date(_) => 7,
+            DbValue::Percentage(_) => 8,
+            DbValue::Null => 9,
+        }
+    }
+
+    /// The numeric value of `Integer`, `Real`, `Money` and `Percentage`
+    /// values.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DbValue::Integer(n) => Some(*n as f64),
+            DbValue::Real(n) => Some(*n as f64),
+            DbValue::Money(m) => Some(*m),
+            DbValue::Percentage(p) => Some(*p),
+            _ => None,
+        }
+    }
+
+    /// Rounds `Money` and both ends of `MoneyRange` to whole cents, so stored
+    /// amounts do not drift through float arithmetic. Ties go to the even
+    /// cent (banker's rounding), judged on the decimal the float stands for:
+    /// `10.005` becomes `10.00` even though its nearest `f64` lies just above
+    /// the tie. `Table` applies this on insert and update, so stored money
+    /// values are changed accordingly. Other values are left as they are.
+    pub fn normalize_money(&mut self) {
+        match self {
+            DbValue::Money(m) => *m = round_cents(*m),
+            DbValue::MoneyRange(min, max) => {
+                *min = round_cents(*min);
+                *max = round_cents(*max);
+            }
+            _ => {}
+        }
+    }
+
+    /// Builds a value of `col_type` from plain JSON: numbers for numeric and
+    /// money types and percentages, a one-character string for `Char`, a two-element
+    /// `[min, max]` array for `MoneyRange`, `true`/`false` for `Boolean`, and a
+    /// `YYYY-MM-DD` string for `Date`. JSON `null` becomes `Null` for any type.
+    pub fn from_json(value: &serde_json::Value, col_type: &DbColumnType) -> anyhow::Result<DbValue> {
+        let mismatch = || anyhow!("Expected {:?} value, got {}", col_type, value);
+        if value.is_null() {
+            return Ok(DbValue::Null);
+        }
+
+        Ok(match col_type {
+            DbColumnType::Integer => {
+                let n = value.as_i64().ok_or_else(mismatch)?;
+                DbValue::Integer(i32::try_from(n).map_err(|_| anyhow!("Integer {} is out of range", n))?)
+            }
+            DbColumnType::Real => {
+                let n = value.as_f64().ok_or_else(mismatch)?;
+                if n.abs() > f32::MAX as f64 {
+                    bail!("Real {} is out of range", n);
+                }
+                DbValue::Real(n as f32)
+            }
+            DbColumnType::Char => {
+                let s = value.as_str().ok_or_else(mismatch)?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => DbValue::Char(c),
+                    _ => bail!("Expected a single character, got {:?}", s),
+                }
+            }
+            DbColumnType::String => DbValue::String(value.as_str().ok_or_else(mismatch)?.to_string()),
+            DbColumnType::Money => DbValue::Money(value.as_f64().ok_or_else(mismatch)?),
+            DbColumnType::MoneyRange => match value.as_array().map(Vec::as_slice) {
+                Some([min, max]) => DbValue::MoneyRange(
+                    min.as_f64().ok_or_else(mismatch)?,
+                    max.as_f64().ok_or_else(mismatch)?,
+                ),
+                _ => return Err(mismatch()),
+            },
+            DbColumnType::Boolean => DbValue::Boolean(value.as_bool().ok_or_else(mismatch)?),
+            DbColumnType::Date => DbValue::Date(parse_date(value.as_str().ok_or_else(mismatch)?)?),
+            DbColumnType::Percentage => DbValue::Percentage(value.as_f64().ok_or_else(mismatch)?),
+        })
+    }
+
+    /// The plain JSON form understood by `from_json`.
+    pub fn to_json(&self) -> serde_json::Value {
         match self {
+            DbValue::Integer(n) => serde_json::json!(n),
+            DbValue::Real(n) => serde_json::json!(n),
+            DbValue::Char(c) => serde_json::json!(c.to_string()),
+            DbValue::String(s) => serde_json::json!(s),
+            DbValue::Money(m) => serde_json::json!(m),
+            DbValue::MoneyRange(min, max) => serde_json::json!([min, max]),
+            DbValue::Boolean(b) => serde_json::json!(b),
+            DbValue::Date(d) => serde_json::json!(format_date(*d)),
+            DbValue::Percentage(p) => serde_json::json!(p),
+            DbValue::Null => serde_json::Value::Null,
+        }
+    }
+
+    /// The type of the value; `None` for `Null`, which fits any column that
+    /// is `nullable`.
+    pub fn value_type(&self) -> Option<DbColumnType> {
+        Some(match self {
             DbValue::Integer(_) => DbColumnType::Integer,
             DbValue::Real(_) => DbColumnType::Real,
             DbValue::Char(_) => DbColumnType::Char,
             DbValue::String(_) => DbColumnType::String,
             DbValue::Money(_) => DbColumnType::Money,
             DbValue::MoneyRange(_, _) => DbColumnType::MoneyRange,
+            DbValue::Boolean(_) => DbColumnType::Boolean,
+            DbValue::Date(_) => DbColumnType::Date,
+            DbValue::Percentage(_) => DbColumnType::Percentage,
+            DbValue::Null => return None,
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, DbValue::Null)
+    }
+
+    /// The plain text form that `parse` reads back: numbers without
+    /// formatting, ranges as `min..max`, dates as `YYYY-MM-DD`, and `Null` as
+    /// an empty string.
+    pub fn to_text(&self) -> String {
+        match self {
+            DbValue::Integer(n) => n.to_string(),
+            DbValue::Real(n) => n.to_string(),
+            DbValue::Char(c) => c.to_string(),
+            DbValue::String(s) => s.clone(),
+            DbValue::Money(m) => m.to_string(),
+            DbValue::MoneyRange(min, max) => format!("{}..{}", min, max),
+            DbValue::Boolean(b) => b.to_string(),
+            DbValue::Date(d) => format_date(*d),
+            DbValue::Percentage(p) => p.to_string(),
+            DbValue::Null => String::new(),
+        }
+    }
+
+    /// The same value as type `to`, going through its text form. `Null`
+    /// stays `Null`.
+    pub fn convert(&self, to: &DbColumnType) -> anyhow::Result<DbValue> {
+        if self.is_null() || self.value_type().as_ref() == Some(to) {
+            return Ok(self.clone());
         }
+        DbValue::parse(&self.to_text(), to)
     }
 }
 
@@ -80,19 +362,325 @@ pub enum DbColumnType {
     Money,
     #[serde(rename = "money_range")]
     MoneyRange,
+    #[serde(rename = "boolean")]
+    Boolean,
+    #[serde(rename = "date")]
+    Date,
+    #[serde(rename = "percentage")]
+    Percentage,
+}
+
+impl DbColumnType {
+    /// The value a new cell of this type starts from: zero, empty or false.
+    pub fn default_value(&self) -> DbValue {
+        match self {
+            DbColumnType::Integer => DbValue::Integer(0),
+            DbColumnType::Real => DbValue::Real(0.0),
+            DbColumnType::Char => DbValue::Char(' '),
+            DbColumnType::String => DbValue::String(String::new()),
+            DbColumnType::Money => DbValue::Money(0.0),
+            DbColumnType::MoneyRange => DbValue::MoneyRange(0.0, 0.0),
+            DbColumnType::Boolean => DbValue::Boolean(false),
+            DbColumnType::Date => DbValue::Date(0),
+            DbColumnType::Percentage => DbValue::Percentage(0.0),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Parses a `YYYY-MM-DD` date into the timestamp stored by `DbValue::Date`.
+pub fn parse_date(text: &str) -> anyhow::Result<i64> {
+    let invalid = || anyhow!("'{}' is not a date, expected YYYY-MM-DD", text);
+    let parts: Vec<&str> = text.trim().split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit())
+        .then(|| part.parse::<u32>().ok())
+        .flatten()
+        .ok_or_else(invalid);
+    let (year, month, day) = (digits(year)? as i64, digits(month)?, digits(day)?);
+
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day) * SECONDS_PER_DAY)
+}
+
+/// Formats a `DbValue::Date` timestamp as `YYYY-MM-DD`.
+pub fn format_date(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+/// Whether `timestamp` falls exactly on midnight UTC, as parsed dates do.
+pub fn is_date(timestamp: i64) -> bool {
+    timestamp.rem_euclid(SECONDS_PER_DAY) == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar, and back; see
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct DbColumn {
     pub name: String,
     pub column_type: DbColumnType,
+    /// Maintain a secondary index for this column; rebuilt from rows on load.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub indexed: bool,
+    /// Reject rows that repeat an existing value in this column.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unique: bool,
+    /// Inclusive lower bound for numeric columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Inclusive upper bound for numeric columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Longest accepted value, in characters, for String columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<usize>,
+    /// Name of the table whose rows this column refers to. Informational
+    /// only; it is not enforced on insert.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<String>,
+    /// Accept `DbValue::Null` in place of a value of `column_type`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub nullable: bool,
+    /// Value `Table::insert` fills in when a row stops short of this column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<DbValue>,
+    /// Return this column from list endpoints; single-record fetches always
+    /// include it.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub include_in_list: bool,
+}
+
+impl Default for DbColumn {
+    fn default() -> Self {
+        DbColumn {
+            name: String::new(),
+            column_type: DbColumnType::default(),
+            indexed: false,
+            unique: false,
+            min: None,
+            max: None,
+            max_len: None,
+            references: None,
+            nullable: false,
+            default: None,
+            include_in_list: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct DbSchema {
     pub columns: Vec<DbColumn>,
 }
 
+impl DbSchema {
+    pub fn builder() -> SchemaBuilder {
+        SchemaBuilder::default()
+    }
+
+    /// Rejects invalid (e.g. empty) column names, names used by more than
+    /// one column, and defaults of another type than their column (a `Null`
+    /// default needs a `nullable` column).
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (i, column) in self.columns.iter().enumerate() {
+            validate_identifier(&column.name)
+                .map_err(|e| anyhow!("Invalid column name: {}", e))?;
+            if self.columns[..i].iter().any(|c| c.name == column.name) {
+                bail!("Duplicate column '{}'", column.name);
+            }
+            if let Some(default) = &column.default {
+                match default.value_type() {
+                    None if column.nullable => {}
+                    Some(value_type) if value_type == column.column_type => {}
+                    _ => bail!("Default {} does not fit {:?} column '{}'", default, column.column_type, column.name),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+
+    /// Two schemas are compatible for set operations when their column names
+    /// and types line up, regardless of per-column options such as indexing.
+    pub fn is_compatible(&self, other: &DbSchema) -> bool {
+        self.columns.len() == other.columns.len()
+            && self.columns.iter().zip(&other.columns)
+                .all(|(a, b)| a.name == b.name && a.column_type == b.column_type)
+    }
+
+    /// Positions of the columns returned by list endpoints.
+    pub fn list_columns(&self) -> Vec<usize> {
+        self.columns.iter().enumerate()
+            .filter(|(_, c)| c.include_in_list)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+
+/// Chainable construction of a `DbSchema`. Column options such as `unique`
+/// refer to a column added earlier by name; all problems are reported by
+/// `build`.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaBuilder {
+    columns: Vec<DbColumn>,
+    unknown: Vec<String>,
+}
+
+impl SchemaBuilder {
+    pub fn column(mut self, name: &str, column_type: DbColumnType) -> Self {
+        self.columns.push(DbColumn {
+            name: name.to_string(),
+            column_type,
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn indexed(self, name: &str) -> Self {
+        self.modify(name, |c| c.indexed = true)
+    }
+
+    pub fn unique(self, name: &str) -> Self {
+        self.modify(name, |c| c.unique = true)
+    }
+
+    /// Inclusive numeric bounds for `name`.
+    pub fn range(self, name: &str, min: f64, max: f64) -> Self {
+        self.modify(name, |c| {
+            c.min = Some(min);
+            c.max = Some(max);
+        })
+    }
+
+    pub fn max_len(self, name: &str, max_len: usize) -> Self {
+        self.modify(name, |c| c.max_len = Some(max_len))
+    }
+
+    pub fn references(self, name: &str, table: &str) -> Self {
+        self.modify(name, |c| c.references = Some(table.to_string()))
+    }
+
+    pub fn nullable(self, name: &str) -> Self {
+        self.modify(name, |c| c.nullable = true)
+    }
+
+    pub fn default_value(self, name: &str, value: DbValue) -> Self {
+        self.modify(name, |c| c.default = Some(value))
+    }
+
+    fn modify(mut self, name: &str, f: impl FnOnce(&mut DbColumn)) -> Self {
+        match self.columns.iter_mut().find(|c| c.name == name) {
+            Some(column) => f(column),
+            None => self.unknown.push(name.to_string()),
+        }
+        self
+    }
+
+    /// Fails if there are no columns, a name is invalid or repeated, or an
+    /// option names a column that was never added.
+    pub fn build(self) -> anyhow::Result<DbSchema> {
+        if let Some(name) = self.unknown.first() {
+            bail!("Column '{}' not found", name);
+        }
+        if self.columns.is_empty() {
+            bail!("Schema must have at least one column");
+        }
+        let schema = DbSchema { columns: self.columns };
+        schema.validate()?;
+        Ok(schema)
+    }
+}
+
+/// Longest table or column name accepted by `validate_identifier`, in characters.
+pub const MAX_IDENTIFIER_LEN: usize = 64;
+
+/// Checks a table or column name. A valid identifier:
+/// - is not empty or made up only of whitespace,
+/// - contains no `/` (names are used as REST path segments),
+/// - contains no control characters,
+/// - is at most `MAX_IDENTIFIER_LEN` characters long.
+pub fn validate_identifier(name: &str) -> anyhow::Result<()> {
+    if name.trim().is_empty() {
+        bail!("Name must not be empty");
+    }
+    if name.contains('/') {
+        bail!("Name '{}' must not contain '/'", name);
+    }
+    if name.chars().any(char::is_control) {
+        bail!("Name {:?} must not contain control characters", name);
+    }
+    if name.chars().count() > MAX_IDENTIFIER_LEN {
+        bail!("Name must be at most {} characters long", MAX_IDENTIFIER_LEN);
+    }
+    Ok(())
+}
+
+impl DbColumn {
+    /// Human-readable `[min..max]` bounds, or `None` when unbounded.
+    pub fn range_label(&self) -> Option<String> {
+        match (self.min, self.max) {
+            (None, None) => None,
+            (min, max) => Some(format!(
+                "[{}..{}]",
+                min.map(|m| m.to_string()).unwrap_or_default(),
+                max.map(|m| m.to_string()).unwrap_or_default(),
+            )),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -123,10 +711,12 @@ mod tests {
                 DbColumn {
                     name: "id".to_string(),
                     column_type: DbColumnType::Integer,
+                    ..Default::default()
                 },
                 DbColumn {
                     name: "name".to_string(),
                     column_type: DbColumnType::String,
+                    ..Default::default()
                 }
             ]
         });
@@ -139,14 +729,17 @@ mod tests {
                 DbColumn {
                     name: "id".to_string(),
                     column_type: DbColumnType::Integer,
+                    ..Default::default()
                 },
                 DbColumn {
                     name: "name".to_string(),
                     column_type: DbColumnType::String,
+                    ..Default::default()
                 },
                 DbColumn {
                     name: "surname".to_string(),
                     column_type: DbColumnType::String,
+                    ..Default::default()
                 }
             ]
         };
@@ -177,4 +770,267 @@ mod tests {
 
         assert_eq!(value_json, r#"{"Money":42.0}"#);
     }
+
+    #[test]
+    fn test_boolean_serde() {
+        assert_eq!(serde_json::to_string(&DbValue::Boolean(true)).unwrap(), r#"{"Boolean":true}"#);
+        assert_eq!(serde_json::from_str::<DbValue>(r#"{"Boolean":false}"#).unwrap(), DbValue::Boolean(false));
+        assert_eq!(serde_json::to_string(&DbColumnType::Boolean).unwrap(), r#""boolean""#);
+        assert_eq!(serde_json::from_str::<DbColumnType>(r#""boolean""#).unwrap(), DbColumnType::Boolean);
+
+        assert_eq!(DbValue::parse(" true ", &DbColumnType::Boolean).unwrap(), DbValue::Boolean(true));
+        assert!(DbValue::parse("yes", &DbColumnType::Boolean).is_err());
+        assert_eq!(DbValue::from_json(&serde_json::json!(false), &DbColumnType::Boolean).unwrap(), DbValue::Boolean(false));
+        assert_ne!(DbValue::Boolean(true), DbValue::Integer(1));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_date("2000-03-01").unwrap(), 951_868_800);
+        assert_eq!(parse_date("2024-02-29").unwrap(), 1_709_164_800);
+        assert_eq!(parse_date("1969-12-31").unwrap(), -86_400);
+
+        for date in ["1970-01-01", "1999-12-31", "2024-02-29", "1900-03-01"] {
+            assert_eq!(format_date(parse_date(date).unwrap()), date);
+        }
+
+        for bad in ["", "2024-1-05", "2024/01/05", "2023-02-29", "2024-13-01", "2024-04-31", "20240105", "2024-01-05T00:00", "+024-01-05"] {
+            assert!(parse_date(bad).is_err(), "{:?} should be rejected", bad);
+        }
+        assert_eq!(DbValue::parse("2024-01-05", &DbColumnType::Date).unwrap(), DbValue::Date(1_704_412_800));
+        assert!(DbValue::parse("yesterday", &DbColumnType::Date).is_err());
+    }
+
+    #[test]
+    fn test_null() {
+        assert_eq!(DbValue::Null, DbValue::Null);
+        assert_ne!(DbValue::Null, DbValue::Integer(0));
+        assert_eq!(DbValue::Null.value_type(), None);
+        assert_eq!(serde_json::to_string(&DbValue::Null).unwrap(), r#""Null""#);
+        assert_eq!(DbValue::from_json(&serde_json::Value::Null, &DbColumnType::Integer).unwrap(), DbValue::Null);
+        assert_eq!(DbValue::Null.to_json(), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(DbValue::Integer(42).convert(&DbColumnType::String).unwrap(), DbValue::String("42".to_string()));
+        assert_eq!(DbValue::String("7".to_string()).convert(&DbColumnType::Money).unwrap(), DbValue::Money(7.0));
+        assert_eq!(DbValue::Integer(3).convert(&DbColumnType::Real).unwrap(), DbValue::Real(3.0));
+        assert_eq!(DbValue::Null.convert(&DbColumnType::Date).unwrap(), DbValue::Null);
+        assert!(DbValue::String("abc".to_string()).convert(&DbColumnType::Integer).is_err());
+        assert!(DbValue::Real(1.5).convert(&DbColumnType::Integer).is_err());
+
+        for value in [DbValue::MoneyRange(1.0, 2.5), DbValue::Date(parse_date("2024-01-05").unwrap()), DbValue::Char('x')] {
+            let column_type = value.value_type().unwrap();
+            assert_eq!(DbValue::parse(&value.to_text(), &column_type).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            (DbValue::Integer(-7), "-7"),
+            (DbValue::Real(2.5), "2.5"),
+            (DbValue::Char('x'), "x"),
+            (DbValue::String("Ann Lee".to_string()), "Ann Lee"),
+            (DbValue::Money(1234.5), "$1234.50"),
+            (DbValue::Money(0.005), "$0.01"),
+            (DbValue::MoneyRange(10.0, 20.25), "$10.00-$20.25"),
+            (DbValue::Boolean(true), "true"),
+            (DbValue::Date(parse_date("2024-02-29").unwrap()), "2024-02-29"),
+            (DbValue::Percentage(42.5), "42.5%"),
+            (DbValue::Null, "null"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_db_value_ord() {
+        assert!(DbValue::Integer(1) < DbValue::Integer(2));
+        assert!(DbValue::Money(-1.0) < DbValue::Money(0.5));
+        assert!(DbValue::MoneyRange(1.0, 5.0) < DbValue::MoneyRange(1.0, 6.0));
+        assert!(DbValue::String("a".to_string()) < DbValue::String("b".to_string()));
+        assert!(DbValue::Integer(100) < DbValue::Real(0.0));
+
+        // Transitive even where the epsilon `PartialEq` is not.
+        let (a, b, c) = (DbValue::Real(1.0), DbValue::Real(1.000_000_6), DbValue::Real(1.000_001_2));
+        assert!(a == b && b == c && a != c);
+        assert!(a < b && b < c && a < c);
+        assert_eq!(DbValue::Real(1.0).cmp(&DbValue::Real(1.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_db_value_json_round_trip() {
+        let values = vec![
+            DbValue::Integer(-7),
+            DbValue::Real(1.5),
+            DbValue::Char('x'),
+            DbValue::String("hello".to_string()),
+            DbValue::Money(19.99),
+            DbValue::MoneyRange(10.0, 20.5),
+        ];
+
+        for value in values {
+            let json = value.to_json();
+            assert_eq!(DbValue::from_json(&json, &value.value_type().unwrap()).unwrap(), value);
+        }
+
+        assert_eq!(DbValue::MoneyRange(1.0, 2.0).to_json(), serde_json::json!([1.0, 2.0]));
+        assert_eq!(DbValue::Char('a').to_json(), serde_json::json!("a"));
+    }
+
+    #[test]
+    fn test_db_value_from_json_mismatch() {
+        use serde_json::json;
+
+        assert!(DbValue::from_json(&json!("42"), &DbColumnType::Integer).is_err());
+        assert!(DbValue::from_json(&json!(1.5), &DbColumnType::Integer).is_err());
+        assert!(DbValue::from_json(&json!(i64::MAX), &DbColumnType::Integer).is_err());
+        let err = DbValue::from_json(&json!(1e39), &DbColumnType::Real).unwrap_err();
+        assert!(err.to_string().ends_with("is out of range"), "{}", err);
+        assert!(DbValue::from_json(&json!(-1e39), &DbColumnType::Real).is_err());
+        assert_eq!(DbValue::from_json(&json!(f32::MAX), &DbColumnType::Real).unwrap(), DbValue::Real(f32::MAX));
+        assert!(DbValue::from_json(&json!("ab"), &DbColumnType::Char).is_err());
+        assert!(DbValue::from_json(&json!(5), &DbColumnType::String).is_err());
+        assert!(DbValue::from_json(&json!([1.0]), &DbColumnType::MoneyRange).is_err());
+        assert!(DbValue::from_json(&json!("10"), &DbColumnType::Money).is_err());
+    }
+
+    #[test]
+    fn test_validate_identifier() {
+        assert!(validate_identifier("customers").is_ok());
+        assert!(validate_identifier("order items").is_ok());
+
+        assert!(validate_identifier("").is_err());
+        assert!(validate_identifier("   ").is_err());
+        assert!(validate_identifier("a/b").is_err());
+        assert!(validate_identifier("tab\tname").is_err());
+        assert!(validate_identifier(&"x".repeat(MAX_IDENTIFIER_LEN + 1)).is_err());
+        assert!(validate_identifier(&"x".repeat(MAX_IDENTIFIER_LEN)).is_ok());
+    }
+
+    #[test]
+    fn test_schema_builder() {
+        let schema = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("email", DbColumnType::String)
+            .unique("email")
+            .build()
+            .unwrap();
+
+        assert_eq!(schema.columns.len(), 2);
+        assert_eq!(schema.columns[0].column_type, DbColumnType::Integer);
+        assert!(schema.columns[1].unique);
+        assert!(!schema.columns[0].unique);
+    }
+
+    #[test]
+    fn test_schema_builder_rejects_bad_schemas() {
+        let duplicate = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("id", DbColumnType::String)
+            .build();
+        assert_eq!(duplicate.unwrap_err().to_string(), "Duplicate column 'id'");
+
+        assert!(DbSchema::builder().build().is_err());
+        assert!(DbSchema::builder().column(" ", DbColumnType::Integer).build().is_err());
+        assert!(DbSchema::builder().column("id", DbColumnType::Integer).unique("missing").build().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let column = |name: &str| DbColumn { name: name.to_string(), ..Default::default() };
+        assert!(DbSchema { columns: vec![column("id"), column("name")] }.validate().is_ok());
+
+        let duplicate = DbSchema { columns: vec![column("id"), column("name"), column("id")] };
+        assert_eq!(duplicate.validate().unwrap_err().to_string(), "Duplicate column 'id'");
+
+        let empty = DbSchema { columns: vec![column("id"), column("")] };
+        assert_eq!(empty.validate().unwrap_err().to_string(), "Invalid column name: Name must not be empty");
+
+        let schema = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("note", DbColumnType::String)
+            .default_value("note", DbValue::String("none".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(schema.columns[1].default, Some(DbValue::String("none".to_string())));
+
+        let err = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .default_value("id", DbValue::String("1".to_string()))
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Default 1 does not fit Integer column 'id'");
+        assert!(DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .default_value("id", DbValue::Null)
+            .build()
+            .is_err());
+        assert!(DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .nullable("id")
+            .default_value("id", DbValue::Null)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(DbValue::parse(" 42 ", &DbColumnType::Integer).unwrap(), DbValue::Integer(42));
+        assert_eq!(DbValue::parse("x", &DbColumnType::Char).unwrap(), DbValue::Char('x'));
+        assert_eq!(DbValue::parse("1.5..3", &DbColumnType::MoneyRange).unwrap(), DbValue::MoneyRange(1.5, 3.0));
+        assert!(DbValue::parse("4.2", &DbColumnType::Integer).is_err());
+        assert!(DbValue::parse("xy", &DbColumnType::Char).is_err());
+        assert!(DbValue::parse("3..1", &DbColumnType::MoneyRange).is_err());
+
+        assert_eq!(DbValue::parse("2.5", &DbColumnType::Real).unwrap(), DbValue::Real(2.5));
+        assert_eq!(DbValue::parse("Ann", &DbColumnType::String).unwrap(), DbValue::String("Ann".to_string()));
+        assert_eq!(DbValue::parse("19.99", &DbColumnType::Money).unwrap(), DbValue::Money(19.99));
+        assert_eq!(DbValue::parse("false", &DbColumnType::Boolean).unwrap(), DbValue::Boolean(false));
+        assert_eq!(DbValue::parse("12.5%", &DbColumnType::Percentage).unwrap(), DbValue::Percentage(12.5));
+        assert_eq!(DbValue::parse(" 80 ", &DbColumnType::Percentage).unwrap(), DbValue::Percentage(80.0));
+        assert_eq!(
+            DbValue::parse("abc", &DbColumnType::Integer).unwrap_err().to_string(),
+            "'abc' is not an integer",
+        );
+        assert!(DbValue::parse("abc", &DbColumnType::Real).is_err());
+        assert!(DbValue::parse("", &DbColumnType::Char).is_err());
+        assert!(DbValue::parse("$5", &DbColumnType::Money).is_err());
+        assert!(DbValue::parse("5", &DbColumnType::MoneyRange).is_err());
+        assert!(DbValue::parse("%", &DbColumnType::Percentage).is_err());
+    }
+
+    #[test]
+    fn test_normalize_money() {
+        let normalized = |mut value: DbValue| {
+            value.normalize_money();
+            value
+        };
+        let cents = |value: DbValue| match normalized(value) {
+            DbValue::Money(m) => m,
+            other => panic!("expected money, got {:?}", other),
+        };
+
+        assert_eq!(cents(DbValue::Money(10.005)), 10.0);
+        assert_eq!(cents(DbValue::Money(10.015)), 10.02);
+        assert_eq!(cents(DbValue::Money(2.675)), 2.68);
+        assert_eq!(cents(DbValue::Money(-10.005)), -10.0);
+        assert_eq!(cents(DbValue::Money(1.006)), 1.01);
+        assert_eq!(cents(DbValue::Money(19.99)), 19.99);
+
+        let sum = 10.1 + 20.2;
+        assert_ne!(sum, 30.3);
+        assert_eq!(cents(DbValue::Money(sum)), cents(DbValue::Money(30.3)));
+        assert_eq!(cents(DbValue::Money(0.1 + 0.2)), cents(DbValue::Money(0.3)));
+
+        match normalized(DbValue::MoneyRange(0.125, 10.1 + 20.2)) {
+            DbValue::MoneyRange(min, max) => assert_eq!((min, max), (0.12, 30.3)),
+            other => panic!("expected a money range, got {:?}", other),
+        }
+        assert_eq!(normalized(DbValue::Real(0.125)), DbValue::Real(0.125));
+    }
 }
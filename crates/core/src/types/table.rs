@@ -1,22 +1,217 @@
 use std::collections::{HashMap, HashSet};
-use anyhow::bail;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
-use crate::types::schema::{DbSchema, DbValue};
-#[cfg(test)]
-use crate::types::schema::{DbColumn, DbColumnType};
+use crate::types::filter::{self, ColumnPredicate, Filter};
+use crate::types::schema::{is_date, validate_identifier, DbColumn, DbColumnType, DbSchema, DbValue};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Row {
     pub id: u32,
     pub values: Vec<DbValue>,
+    /// Unix time in seconds when the row was inserted. Rows saved before
+    /// timestamps were tracked read as 0.
+    #[serde(default)]
+    pub created_at: i64,
+    /// Unix time in seconds of the last change to the row's values.
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+impl Row {
+    /// A row stamped with the current time as both created and updated.
+    pub fn new(id: u32, values: Vec<DbValue>) -> Row {
+        let now = unix_now();
+        Row { id, values, created_at: now, updated_at: now }
+    }
+
+    /// Copy of the row keeping only the values at `columns`, in that order.
+    pub fn project(&self, columns: &[usize]) -> Row {
+        Row {
+            values: columns.iter().filter_map(|&i| self.values.get(i).cloned()).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// What `Table::insert_with` does when a row repeats a value in a `unique`
+/// column.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum OnConflict {
+    /// Reject the insert.
+    #[default]
+    #[serde(rename = "error")]
+    Error,
+    /// Skip the insert and return the id of the existing row.
+    #[serde(rename = "ignore")]
+    Ignore,
+    /// Overwrite the existing row with the new values and return its id.
+    #[serde(rename = "replace")]
+    Replace,
+}
+
+impl FromStr for OnConflict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(OnConflict::Error),
+            "ignore" => Ok(OnConflict::Ignore),
+            "replace" => Ok(OnConflict::Replace),
+            _ => bail!("Invalid on_conflict value '{}', expected error, ignore or replace", s),
+        }
+    }
+}
+
+/// What `Table::insert_with` did with a row, with the id of the row it ended
+/// up in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InsertOutcome {
+    /// The row was added under a new id.
+    Inserted(u32),
+    /// The row clashed with an existing one and was skipped.
+    Ignored(u32),
+    /// The row clashed with an existing one and overwrote it.
+    Replaced(u32),
+}
+
+impl InsertOutcome {
+    pub fn id(self) -> u32 {
+        match self {
+            InsertOutcome::Inserted(id) | InsertOutcome::Ignored(id) | InsertOutcome::Replaced(id) => id,
+        }
+    }
+}
+
+/// One key of a multi-column sort, parsed from `column` or
+/// `column:asc|desc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Parses a comma-separated list such as `status:asc,amount:desc`.
+    pub fn parse_list(spec: &str) -> anyhow::Result<Vec<SortKey>> {
+        Self::parse_list_with_default(spec, false)
+    }
+
+    /// Like `parse_list`, but keys without a direction sort descending when
+    /// `descending` is set.
+    pub fn parse_list_with_default(spec: &str, descending: bool) -> anyhow::Result<Vec<SortKey>> {
+        spec.split(',').map(|key| Self::parse_with_default(key, descending)).collect()
+    }
+
+    fn parse_with_default(s: &str, descending: bool) -> anyhow::Result<SortKey> {
+        let (column, descending) = match s.split_once(':') {
+            Some((column, direction)) => match parse_direction(direction) {
+                Ok(descending) => (column, descending),
+                Err(_) => bail!("Invalid sort direction '{}' for column '{}', expected asc or desc", direction, column),
+            },
+            None => (s, descending),
+        };
+        if column.is_empty() {
+            bail!("Empty column name in sort '{}'", s);
+        }
+        Ok(SortKey { column: column.to_string(), descending })
+    }
+}
+
+/// Parses `asc` or `desc`, returning whether the order is descending.
+pub fn parse_direction(direction: &str) -> anyhow::Result<bool> {
+    match direction {
+        "asc" => Ok(false),
+        "desc" => Ok(true),
+        _ => bail!("Invalid sort direction '{}', expected asc or desc", direction),
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with_default(s, false)
+    }
+}
+
+/// Aggregate computed by `Table::aggregate`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+
+impl FromStr for AggregateOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(AggregateOp::Sum),
+            "avg" => Ok(AggregateOp::Avg),
+            "min" => Ok(AggregateOp::Min),
+            "max" => Ok(AggregateOp::Max),
+            "count" => Ok(AggregateOp::Count),
+            _ => bail!("Invalid aggregate '{}', expected sum, avg, min, max or count", s),
+        }
+    }
 }
 
+/// Summary of one column's values, as returned by `Table::describe`. The
+/// numeric fields are only set for Integer, Real, Money and Percentage
+/// columns that hold at least one row.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColumnStats {
+    pub column: String,
+    pub column_type: DbColumnType,
+    pub count: usize,
+    pub distinct: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+}
+
+/// Secondary indexes keyed by column position, mapping a value to the ids of
+/// the rows holding it (kept sorted).
+type ColumnIndexes = HashMap<usize, HashMap<ExactValue, Vec<u32>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Table {
     pub schema: DbSchema,
     pub rows: HashMap<u32, Row>,
     pub index: u32,
     pub name: String,
+    /// Explicit display order of row ids, set once rows are reordered by hand;
+    /// `None` means id order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<u32>>,
+    /// Built lazily from `rows` for every `indexed` or `unique` column, so it never needs
+    /// to be persisted.
+    #[serde(skip)]
+    indexes: OnceLock<ColumnIndexes>,
+}
+
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        self.schema == other.schema
+            && self.rows == other.rows
+            && self.index == other.index
+            && self.name == other.name
+            && self.order == other.order
+    }
 }
 
 impl Table {
@@ -26,271 +221,2440 @@ impl Table {
             rows: HashMap::new(),
             index: 0,
             name,
+            order: None,
+            indexes: OnceLock::new(),
         }
     }
 
+    /// Like `new`, but rejects an invalid table name or a schema that fails
+    /// `DbSchema::validate`.
+    pub fn try_new(name: String, schema: DbSchema) -> anyhow::Result<Self> {
+        validate_identifier(&name).map_err(|e| anyhow!("Invalid table name: {}", e))?;
+        schema.validate()?;
+        Ok(Table::new(name, schema))
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// Inserts a row. A row shorter than the schema has its missing trailing
+    /// columns filled from their `default`s, and money is rounded to the
+    /// cent; see `DbValue::normalize_money`.
     pub fn insert(&mut self, row: Vec<DbValue>) -> anyhow::Result<u32> {
+        self.insert_with(row, OnConflict::Error).map(InsertOutcome::id)
+    }
+
+    /// Inserts a row, resolving clashes on `unique` columns according to
+    /// `on_conflict`.
+    pub fn insert_with(&mut self, row: Vec<DbValue>, on_conflict: OnConflict) -> anyhow::Result<InsertOutcome> {
+        let mut row = self.fill_defaults(row)?;
+        row.iter_mut().for_each(DbValue::normalize_money);
         self.validate(&row)?;
 
+        if let Some(existing) = self.find_conflict(&row, None) {
+            return match on_conflict {
+                OnConflict::Error => bail!("Unique constraint violated by row {}", existing),
+                OnConflict::Ignore => Ok(InsertOutcome::Ignored(existing)),
+                OnConflict::Replace => {
+                    self.update(existing, row)?;
+                    Ok(InsertOutcome::Replaced(existing))
+                }
+            };
+        }
+
         let id = self.index;
+        self.insert_at(id, row);
+        Ok(InsertOutcome::Inserted(id))
+    }
 
-        self.rows.insert(id, Row {
-            id,
-            values: row,
-        });
+    /// Like `insert`, but takes the lowest id not held by a row, so ids freed
+    /// by deletes are filled again before `index` grows. Finding the id is
+    /// O(n) per insert, and because ids no longer only grow, cursors from
+    /// `rows_after` may skip rows inserted into earlier gaps.
+    pub fn insert_reuse_ids(&mut self, row: Vec<DbValue>) -> anyhow::Result<u32> {
+        let mut row = self.fill_defaults(row)?;
+        row.iter_mut().for_each(DbValue::normalize_money);
+        self.validate(&row)?;
+        if let Some(existing) = self.find_conflict(&row, None) {
+            bail!("Unique constraint violated by row {}", existing);
+        }
 
-        self.index += 1;
-        Ok(self.index - 1)
+        let id = (0..self.index)
+            .find(|id| !self.rows.contains_key(id))
+            .unwrap_or(self.index);
+        self.insert_at(id, row);
+        Ok(id)
+    }
+
+    /// Appends the defaults of the columns past the end of `row`, failing on
+    /// the first one without a default. Rows of full length or longer are
+    /// returned as they are.
+    fn fill_defaults(&self, mut row: Vec<DbValue>) -> anyhow::Result<Vec<DbValue>> {
+        for column in self.schema.columns.iter().skip(row.len()) {
+            let default = column.default.clone()
+                .ok_or_else(|| anyhow!("Missing value for column '{}', which has no default", column.name))?;
+            row.push(default);
+        }
+        Ok(row)
+    }
+
+    /// Stores an already validated row under `id`, which must be free.
+    fn insert_at(&mut self, id: u32, row: Vec<DbValue>) {
+        self.index_row(id, &row);
+        self.rows.insert(id, Row::new(id, row));
+        if let Some(order) = &mut self.order {
+            order.push(id);
+        }
+        self.index = self.index.max(id + 1);
+    }
+
+    /// Removes every row, keeping the schema, and restarts ids at 0.
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.index = 0;
+        self.order = None;
+        self.rebuild_indexes();
+    }
+
+    /// Inserts every row or none of them, returning the assigned ids in
+    /// order. Rows are inserted into a copy that replaces the table only once
+    /// all of them pass validation and the unique checks, including clashes
+    /// between rows of the same batch.
+    pub fn insert_many(&mut self, rows: Vec<Vec<DbValue>>) -> anyhow::Result<Vec<u32>> {
+        let mut staged = self.clone();
+        let ids = rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| staged.insert(row).map_err(|e| anyhow!("Row {} of the batch: {}", i, e)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        *self = staged;
+        Ok(ids)
     }
 
     pub fn delete(&mut self, id: u32) -> anyhow::Result<()> {
-        self.rows.remove(&id).ok_or_else(|| anyhow::anyhow!("Row not found"))?;
+        let row = self.rows.remove(&id).ok_or_else(|| anyhow::anyhow!("Row not found"))?;
+        self.unindex_row(id, &row.values);
+        if let Some(order) = &mut self.order {
+            order.retain(|&o| o != id);
+        }
         Ok(())
     }
 
-    pub fn update(&mut self, id: u32, new_row: Vec<DbValue>) -> anyhow::Result<()> {
+    /// Replaces every value of row `id`. Money is rounded to the cent first,
+    /// as on insert; see `DbValue::normalize_money`.
+    pub fn update(&mut self, id: u32, mut new_row: Vec<DbValue>) -> anyhow::Result<()> {
+        new_row.iter_mut().for_each(DbValue::normalize_money);
         self.validate(&new_row)?;
+        if let Some(existing) = self.find_conflict(&new_row, Some(id)) {
+            bail!("Unique constraint violated by row {}", existing);
+        }
 
-        let row = self.get_row_mut(id);
+        let old_values = self.get_row(id)?.values.clone();
+        self.unindex_row(id, &old_values);
+        self.index_row(id, &new_row);
+
+        let row = self.get_row_mut(id)?;
         row.values = new_row;
+        row.updated_at = unix_now();
         Ok(())
     }
 
-    pub fn intersection(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
-        if self.schema != other.schema {
-            bail!("Schemas do not match");
+    /// Replaces only the named columns of row `id`, keeping the others. The
+    /// resulting row is validated as a whole, as `update` does.
+    pub fn update_partial(&mut self, id: u32, changes: HashMap<String, DbValue>) -> anyhow::Result<()> {
+        let mut values = self.get_row(id)?.values.clone();
+        for (column, value) in changes {
+            let col = self.schema.column_index(&column)
+                .ok_or_else(|| anyhow!("Column '{}' not found", column))?;
+            values[col] = value;
         }
+        self.update(id, values)
+    }
 
-        let mut result = Vec::new();
+    /// Returns the id of a row, other than `exclude`, that already holds one of
+    /// `values`' entries in a `unique` column.
+    fn find_conflict(&self, values: &[DbValue], exclude: Option<u32>) -> Option<u32> {
+        self.schema.columns.iter()
+            .zip(values)
+            .filter(|(col, value)| col.unique && !value.is_null())
+            .flat_map(|(col, value)| self.find_by(&col.name, value))
+            .map(|r| r.id)
+            .find(|id| Some(*id) != exclude)
+    }
 
-        let mut unique_rows = HashSet::new();
+    /// Replaces a single cell, validating only that value and leaving the rest
+    /// of the row untouched.
+    pub fn set_cell(&mut self, id: u32, column: &str, mut value: DbValue) -> anyhow::Result<()> {
+        let col = self.schema.column_index(column)
+            .ok_or_else(|| anyhow::anyhow!("Column '{}' not found", column))?;
+        value.normalize_money();
+        self.validate_cell(col, &value)?;
 
-        for row in self.rows.values() {
-            unique_rows.insert(row.clone().values);
+        let old_values = self.get_row(id)?.values.clone();
+        let mut new_values = old_values.clone();
+        new_values[col] = value;
+        if let Some(existing) = self.find_conflict(&new_values, Some(id)) {
+            bail!("Unique constraint violated by row {}", existing);
         }
 
-        for row in other.rows.values() {
-            if unique_rows.contains(&row.values) {
-                result.push(row.clone());
-            }
+        self.unindex_row(id, &old_values);
+        self.index_row(id, &new_values);
+        if let Some(row) = self.rows.get_mut(&id) {
+            row.values = new_values;
+            row.updated_at = unix_now();
         }
+        Ok(())
+    }
 
-        Ok(result)
+    /// Rows matching every predicate, in id order. Rows whose value has a
+    /// different type than a predicate's operand do not match it.
+    pub fn filter(&self, predicates: &[ColumnPredicate]) -> anyhow::Result<Vec<Row>> {
+        let predicates = filter::resolve(predicates, &self.schema)?;
+        let mut rows: Vec<Row> = self.rows.values()
+            .filter(|r| predicates.iter().all(|(i, p)| p.matches(&r.values[*i])))
+            .cloned()
+            .collect();
+        rows.sort_by_key(|r| r.id);
+        Ok(rows)
     }
 
-    pub fn validate(&self, row: &[DbValue]) -> anyhow::Result<()> {
-        if row.len() != self.schema.columns.len() {
-            bail!("Row length does not match schema length");
+    /// Deletes the rows matching every predicate, returning how many were
+    /// removed. The matching ids are collected before any row is removed, and
+    /// an unknown column fails before anything changes. Unlike `clear`, ids
+    /// are not restarted.
+    pub fn delete_where(&mut self, predicates: &[ColumnPredicate]) -> anyhow::Result<usize> {
+        let predicates = filter::resolve(predicates, &self.schema)?;
+        let ids: Vec<u32> = self.rows.values()
+            .filter(|r| predicates.iter().all(|(i, p)| p.matches(&r.values[*i])))
+            .map(|r| r.id)
+            .collect();
+
+        for id in &ids {
+            self.delete(*id)?;
         }
+        Ok(ids.len())
+    }
 
-        for (i, value) in row.iter().enumerate() {
-            if value.value_type() != self.schema.columns[i].column_type {
-                bail!("Value type does not match schema type");
+    /// Replaces the schema, migrating every row. Columns are matched by name:
+    /// added columns are filled with their `default` if they have one, else
+    /// with `Null` when nullable and with the type's default value otherwise;
+    /// removed columns are dropped, and a column whose type changed has each
+    /// value converted, money being rounded to cents. Nothing changes unless every row converts and passes
+    /// validation under the new schema.
+    pub fn alter_schema(&mut self, new_schema: DbSchema) -> anyhow::Result<()> {
+        if new_schema.columns.is_empty() {
+            bail!("Schema must have at least one column");
+        }
+        new_schema.validate()?;
+
+        let sources: Vec<Option<usize>> = new_schema.columns.iter()
+            .map(|c| self.schema.column_index(&c.name))
+            .collect();
+        let mut altered = Table::new(self.name.clone(), new_schema);
+        altered.index = self.index;
+        altered.order = self.order.clone();
+
+        let mut ids: Vec<u32> = self.rows.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let row = &self.rows[&id];
+            let mut values = altered.schema.columns.iter().zip(&sources)
+                .map(|(column, source)| match source {
+                    Some(i) => row.values[*i].convert(&column.column_type)
+                        .map_err(|e| anyhow!("Row {}, column '{}': {}", id, column.name, e)),
+                    None => Ok(match &column.default {
+                        Some(default) => default.clone(),
+                        None if column.nullable => DbValue::Null,
+                        None => column.column_type.default_value(),
+                    }),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            values.iter_mut().for_each(DbValue::normalize_money);
+
+            altered.validate(&values).map_err(|e| anyhow!("Row {}: {}", id, e))?;
+            if let Some(existing) = altered.find_conflict(&values, None) {
+                bail!("Row {}: unique constraint violated by row {}", id, existing);
             }
+            altered.index_row(id, &values);
+            altered.rows.insert(id, Row { values, ..row.clone() });
         }
 
+        *self = altered;
         Ok(())
     }
 
-    pub fn get_row(&self, id: u32) -> anyhow::Result<&Row> {
-        self.rows.get(&id).ok_or_else(|| anyhow::anyhow!("Row not found"))
-    }
+    /// Sets `set_column` to `set_value` on every row matching `filter`,
+    /// returning the number of rows updated.
+    pub fn update_where(&mut self, filter: &Filter, set_column: &str, set_value: DbValue) -> anyhow::Result<usize> {
+        let col = self.schema.column_index(set_column)
+            .ok_or_else(|| anyhow::anyhow!("Column '{}' not found", set_column))?;
+        self.validate_cell(col, &set_value)?;
 
-    pub fn get_row_mut(&mut self, id: u32) -> &mut Row {
-        self.rows.get_mut(&id).ok_or_else(|| anyhow::anyhow!("Row not found")).unwrap()
-    }
+        let predicates = filter.resolve(&self.schema)?;
+        let ids: Vec<u32> = self.rows.values()
+            .filter(|r| predicates.iter().all(|(i, p)| p.matches(&r.values[*i])))
+            .map(|r| r.id)
+            .collect();
 
-    pub fn get_rows(&self) -> Vec<Row> {
-        self.rows.values().cloned().collect()
-    }
-}
+        if self.schema.columns[col].unique && !set_value.is_null() && ids.len() > 1 {
+            bail!("Unique constraint on '{}' would be violated", set_column);
+        }
 
-#[cfg(test)]
-pub fn create_test_schema() -> DbSchema {
-    DbSchema {
-        columns: vec![
-            DbColumn {
-                name: "col1".to_string(),
-                column_type: DbColumnType::Integer,
-            },
-            DbColumn {
-                name: "col2".to_string(),
-                column_type: DbColumnType::String,
-            },
-        ],
-    }
-}
+        for id in &ids {
+            let mut values = self.get_row(*id)?.values.clone();
+            values[col] = set_value.clone();
+            self.update(*id, values)?;
+        }
 
-#[cfg(test)]
-pub fn create_test_row() -> Vec<DbValue> {
-    vec![
-        DbValue::Integer(42),
-        DbValue::String("test".to_string()),
-    ]
-}
+        Ok(ids.len())
+    }
 
-#[cfg(test)]
-pub fn create_test_table(name: &str) -> Table {
-    Table::new(
-        name.to_string(),
-        DbSchema {
-            columns: vec![
-                DbColumn {
-                name: "id".to_string(),
-                column_type: DbColumnType::Integer,
-            },
-            DbColumn {
-                name: "name".to_string(),
-                column_type: DbColumnType::String,
-            },
-        ],
-    })
-}
+    /// Returns the rows whose `column` holds exactly `value`, ordered by id.
+    /// Uses the column's secondary index when it is declared `indexed`, and
+    /// falls back to a full scan otherwise; both compare by `Ord`, so floats
+    /// within `DbValue`'s epsilon do not match.
+    pub fn find_by(&self, column: &str, value: &DbValue) -> Vec<&Row> {
+        let Some(col) = self.schema.column_index(column) else {
+            return Vec::new();
+        };
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
+        if let Some(index) = self.indexes().get(&col) {
+            return index.get(&ExactValue(value.clone()))
+                .map(|ids| ids.iter().filter_map(|id| self.rows.get(id)).collect())
+                .unwrap_or_default();
+        }
 
-    #[test]
-    fn test_table_creation() {
-        let schema = create_test_schema();
-        let table = Table::new("test_table".to_string(), schema.clone());
-        assert_eq!(table.name(), "test_table");
-        assert_eq!(table.schema, schema);
-        assert!(table.rows.is_empty());
-        assert_eq!(table.index, 0);
+        let mut rows: Vec<&Row> = self.rows.values()
+            .filter(|r| r.values[col].cmp(value).is_eq())
+            .collect();
+        rows.sort_by_key(|r| r.id);
+        rows
     }
 
-    #[test]
-    fn test_insert_valid_row() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let row = create_test_row();
-        let id = table.insert(row.clone()).unwrap();
+    pub fn is_indexed(&self, column: &str) -> bool {
+        self.schema.column_index(column)
+            .is_some_and(|col| self.indexes().contains_key(&col))
+    }
 
-        assert_eq!(id, 0);
-        assert_eq!(table.rows.len(), 1);
-        assert_eq!(table.get_row(0).unwrap().values, row);
+    /// Marks `column` as `indexed` so `find_by` looks it up by hash instead of
+    /// scanning. The index is built on next use and kept in sync from then on.
+    pub fn create_index(&mut self, column: &str) -> anyhow::Result<()> {
+        let col = self.schema.column_index(column)
+            .ok_or_else(|| anyhow!("Column '{}' not found", column))?;
+        if !self.schema.columns[col].indexed {
+            self.schema.columns[col].indexed = true;
+            self.rebuild_indexes();
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_insert_invalid_row_length() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let result = table.insert(vec![DbValue::Integer(42)]);
-        assert!(result.is_err());
+    /// Drops the in-memory indexes so they are rebuilt from the current
+    /// schema and rows on next use.
+    pub fn rebuild_indexes(&mut self) {
+        self.indexes = OnceLock::new();
     }
 
-    #[test]
-    fn test_insert_invalid_type() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let result = table.insert(vec![
-            DbValue::String("wrong".to_string()),
-            DbValue::String("test".to_string()),
-        ]);
-        assert!(result.is_err());
+    fn indexes(&self) -> &ColumnIndexes {
+        self.indexes.get_or_init(|| {
+            let mut ids: Vec<u32> = self.rows.keys().copied().collect();
+            ids.sort_unstable();
+
+            let mut indexes = ColumnIndexes::new();
+            for (col, _) in self.schema.columns.iter().enumerate().filter(|(_, c)| c.indexed || c.unique) {
+                let index: &mut HashMap<ExactValue, Vec<u32>> = indexes.entry(col).or_default();
+                for id in &ids {
+                    index.entry(ExactValue(self.rows[id].values[col].clone())).or_default().push(*id);
+                }
+            }
+            indexes
+        })
     }
 
-    #[test]
-    fn test_delete_existing_row() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let id = table.insert(create_test_row()).unwrap();
-        assert!(table.delete(id).is_ok());
-        assert!(table.rows.is_empty());
+    fn index_row(&mut self, id: u32, values: &[DbValue]) {
+        if let Some(indexes) = self.indexes.get_mut() {
+            for (col, index) in indexes.iter_mut() {
+                let ids = index.entry(ExactValue(values[*col].clone())).or_default();
+                if let Err(pos) = ids.binary_search(&id) {
+                    ids.insert(pos, id);
+                }
+            }
+        }
     }
 
-    #[test]
-    fn test_delete_nonexistent_row() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        assert!(table.delete(0).is_err());
+    fn unindex_row(&mut self, id: u32, values: &[DbValue]) {
+        if let Some(indexes) = self.indexes.get_mut() {
+            for (col, index) in indexes.iter_mut() {
+                let key = ExactValue(values[*col].clone());
+                if let Some(ids) = index.get_mut(&key) {
+                    ids.retain(|i| *i != id);
+                    if ids.is_empty() {
+                        index.remove(&key);
+                    }
+                }
+            }
+        }
     }
 
-    #[test]
-    fn test_update_existing_row() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let id = table.insert(create_test_row()).unwrap();
+    /// Rows of `other` whose values also appear in `self`, ordered by values
+    /// and then id so the output is deterministic. Values match exactly; see
+    /// `intersection_with_epsilon` for the tolerant counterpart.
+    pub fn intersection(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
+        if !self.schema.is_compatible(&other.schema) {
+            bail!("Schemas do not match");
+        }
 
-        let new_row = vec![
-            DbValue::Integer(99),
-            DbValue::String("updated".to_string()),
-        ];
+        let mut result = Vec::new();
 
-        assert!(table.update(id, new_row.clone()).is_ok());
-        assert_eq!(table.get_row(id).unwrap().values, new_row);
+        let mut unique_rows = HashSet::new();
+
+        for row in self.rows.values() {
+            unique_rows.insert(ExactValues(&row.values));
+        }
+
+        for row in other.rows.values() {
+            if unique_rows.contains(&ExactValues(&row.values)) {
+                result.push(row.clone());
+            }
+        }
+
+        sort_rows(&mut result);
+        Ok(result)
     }
 
-    #[test]
-    fn test_intersection() {
-        let mut table1 = Table::new("test_table".to_string(), create_test_schema());
-        let mut table2 = Table::new("test_table".to_string(), create_test_schema());
+    /// One row per distinct value vector, keeping the lowest id of each, in
+    /// id order.
+    pub fn distinct(&self) -> Vec<Row> {
+        let mut rows: Vec<&Row> = self.rows.values().collect();
+        rows.sort_by_key(|r| r.id);
 
-        let row1 = create_test_row();
-        let row2 = vec![
-            DbValue::Integer(99),
-            DbValue::String("different".to_string()),
-        ];
+        let mut seen = HashSet::new();
+        rows.into_iter()
+            .filter(|row| seen.insert(ExactValues(&row.values)))
+            .cloned()
+            .collect()
+    }
 
-        table1.insert(row1.clone()).unwrap();
-        table1.insert(row2.clone()).unwrap();
-        table2.insert(row1.clone()).unwrap();
+    /// Inner equi-join on `self.on_self == other.on_other`. The result schema
+    /// holds `self`'s columns followed by `other`'s, the latter prefixed with
+    /// `<other table>.` where a name would clash; constraints are dropped.
+    /// Each output row concatenates a matching pair, numbered from 0 in
+    /// `self`'s id order, then `other`'s. Null never matches.
+    pub fn join(&self, other: &Table, on_self: &str, on_other: &str) -> anyhow::Result<(DbSchema, Vec<Row>)> {
+        let left = self.schema.column_index(on_self)
+            .ok_or_else(|| anyhow!("Column '{}' not found in table '{}'", on_self, self.name))?;
+        let right = other.schema.column_index(on_other)
+            .ok_or_else(|| anyhow!("Column '{}' not found in table '{}'", on_other, other.name))?;
+        let (left_type, right_type) = (&self.schema.columns[left].column_type, &other.schema.columns[right].column_type);
+        if left_type != right_type {
+            bail!("Cannot join {:?} column '{}' with {:?} column '{}'", left_type, on_self, right_type, on_other);
+        }
 
-        let intersection = table1.intersection(&table2).unwrap();
+        let mut columns: Vec<DbColumn> = self.schema.columns.iter()
+            .map(|c| DbColumn { name: c.name.clone(), column_type: c.column_type.clone(), nullable: c.nullable, ..Default::default() })
+            .collect();
+        for c in &other.schema.columns {
+            let name = if columns.iter().any(|existing| existing.name == c.name) {
+                format!("{}.{}", other.name, c.name)
+            } else {
+                c.name.clone()
+            };
+            columns.push(DbColumn { name, column_type: c.column_type.clone(), nullable: c.nullable, ..Default::default() });
+        }
 
-        assert_eq!(intersection.len(), 1);
-        assert_eq!(intersection[0].values, row1);
+        let mut matches: HashMap<&DbValue, Vec<&Row>> = HashMap::new();
+        for row in other.rows.values().filter(|r| !r.values[right].is_null()) {
+            matches.entry(&row.values[right]).or_default().push(row);
+        }
+        matches.values_mut().for_each(|rows| rows.sort_by_key(|r| r.id));
+
+        let mut rows: Vec<&Row> = self.rows.values().collect();
+        rows.sort_by_key(|r| r.id);
+        let joined = rows.into_iter()
+            .flat_map(|row| {
+                matches.get(&row.values[left]).into_iter().flatten()
+                    .map(move |matched| row.values.iter().chain(&matched.values).cloned().collect::<Vec<_>>())
+            })
+            .enumerate()
+            .map(|(id, values)| Row::new(id as u32, values))
+            .collect();
+
+        Ok((DbSchema { columns }, joined))
     }
 
-    #[test]
-    fn test_intersection_different_schemas() {
-        let schema1 = DbSchema {
-            columns: vec![
-                DbColumn {
-                    name: "id".to_string(),
-                    column_type: DbColumnType::Integer,
-                },
-                DbColumn {
-                    name: "name".to_string(),
-                    column_type: DbColumnType::String,
-                },
-            ],
-        };
+    /// Distinct rows present in either table, compared exactly by value. When
+    /// a value vector occurs more than once, the row from `self` with the
+    /// lowest id is kept. Rows are returned in value order.
+    pub fn union(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
+        if !self.schema.is_compatible(&other.schema) {
+            bail!("Schemas do not match");
+        }
 
-        let schema2 = DbSchema {
-            columns: vec![
-                DbColumn {
-                    name: "id".to_string(),
-                    column_type: DbColumnType::Integer,
-                },
-                DbColumn {
-                    name: "age".to_string(),
-                    column_type: DbColumnType::Integer,
-                },
-            ],
-        };
+        let mut candidates: Vec<&Row> = self.rows.values().collect();
+        candidates.sort_by_key(|r| r.id);
+        let mut from_other: Vec<&Row> = other.rows.values().collect();
+        from_other.sort_by_key(|r| r.id);
+        candidates.extend(from_other);
 
-        let table1 = Table::new("test_table".to_string(), schema1);
-        let table2 = Table::new("test_table".to_string(), schema2);
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+        for row in candidates {
+            if seen.insert(ExactValues(&row.values)) {
+                result.push(row.clone());
+            }
+        }
 
-        assert!(table1.intersection(&table2).is_err());
+        sort_rows(&mut result);
+        Ok(result)
     }
 
-    #[test]
-    fn test_get_rows() {
-        let mut table = Table::new("test_table".to_string(), create_test_schema());
-        let row1 = create_test_row();
-        let row2 = vec![
-            DbValue::Integer(99),
-            DbValue::String("different".to_string()),
-        ];
+    /// Like `intersection`, but floating-point values match when they differ
+    /// by less than `epsilon` (see `DbValue::approx_eq`). Tolerant equality
+    /// cannot be hashed, so this compares every pair of rows: O(n * m).
+    pub fn intersection_with_epsilon(&self, other: &Table, epsilon: f64) -> anyhow::Result<Vec<Row>> {
+        if !self.schema.is_compatible(&other.schema) {
+            bail!("Schemas do not match");
+        }
 
-        table.insert(row1.clone()).unwrap();
-        table.insert(row2.clone()).unwrap();
+        let matches = |a: &Row, b: &Row| {
+            a.values.iter().zip(&b.values).all(|(x, y)| x.approx_eq(y, epsilon))
+        };
+        let mut result: Vec<Row> = other.rows.values()
+            .filter(|row| self.rows.values().any(|r| matches(r, row)))
+            .cloned()
+            .collect();
 
-        let rows = table.get_rows();
-        assert_eq!(rows.len(), 2);
+        sort_rows(&mut result);
+        Ok(result)
+    }
+
+    /// Rows of `self` whose values appear nowhere in `other`, in value order.
+    /// Unlike `intersection` and `union`, which match values exactly, values
+    /// here match by `DbValue`'s `PartialEq`, so floats within its epsilon
+    /// count as present in `other`.
+    pub fn difference(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
+        if !self.schema.is_compatible(&other.schema) {
+            bail!("Schemas do not match");
+        }
+
+        let theirs: HashSet<TolerantValues> = other.rows.values().map(|r| TolerantValues(&r.values)).collect();
+        let mut result: Vec<Row> = self.rows.values()
+            .filter(|r| !theirs.contains(&TolerantValues(&r.values)))
+            .cloned()
+            .collect();
+
+        sort_rows(&mut result);
+        Ok(result)
+    }
+
+    /// Rows that are in exactly one of the two tables: those only in `self`
+    /// followed by those only in `other`. Rows repeating the same values
+    /// within one table collapse to the one with the lowest id.
+    pub fn symmetric_difference(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
+        let (mut result, theirs) = self.symmetric_difference_parts(other)?;
+        result.extend(theirs);
+        Ok(result)
+    }
+
+    /// `symmetric_difference` split by origin: the rows only in `self`, and
+    /// the rows only in `other`.
+    pub fn symmetric_difference_parts(&self, other: &Table) -> anyhow::Result<(Vec<Row>, Vec<Row>)> {
+        Ok((dedup_rows(self.difference(other)?), dedup_rows(other.difference(self)?)))
+    }
+
+    /// Same result as `intersection`, computed by sorting both tables' rows and
+    /// merging them instead of hashing: O(n log n + m log m) time against the
+    /// hash set's O(n + m), without the hash set's table of n entries. Rows
+    /// are returned in value order.
+    pub fn intersection_sorted(&self, other: &Table) -> anyhow::Result<Vec<Row>> {
+        if !self.schema.is_compatible(&other.schema) {
+            bail!("Schemas do not match");
+        }
+
+        let mut left: Vec<&Vec<DbValue>> = self.rows.values().map(|r| &r.values).collect();
+        let mut right: Vec<&Row> = other.rows.values().collect();
+        left.sort_unstable();
+        right.sort_by(|a, b| row_order(a, b));
+
+        let mut result = Vec::new();
+        let mut left = left.into_iter().peekable();
+        for row in right {
+            while left.next_if(|values| **values < row.values).is_some() {}
+            match left.peek() {
+                Some(values) if (**values).cmp(&row.values).is_eq() => result.push(row.clone()),
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn validate(&self, row: &[DbValue]) -> anyhow::Result<()> {
+        if row.len() != self.schema.columns.len() {
+            bail!("Row length does not match schema length");
+        }
+
+        for (i, value) in row.iter().enumerate() {
+            self.validate_cell(i, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `validate` over every stored row in display order, reporting the
+    /// first row that no longer fits the schema, e.g. after the file was
+    /// edited by hand.
+    pub fn validate_all(&self) -> anyhow::Result<()> {
+        for row in self.get_rows_ordered() {
+            self.validate(&row.values).map_err(|e| anyhow!("Row {}: {}", row.id, e))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that no two rows share a value in a `unique` column, nulls
+    /// aside, reporting the first clash in id order.
+    pub fn validate_unique(&self) -> anyhow::Result<()> {
+        let mut rows: Vec<&Row> = self.rows.values().collect();
+        rows.sort_by_key(|r| r.id);
+        for (col, column) in self.schema.columns.iter().enumerate().filter(|(_, c)| c.unique) {
+            let mut seen = HashMap::new();
+            for row in &rows {
+                let value = &row.values[col];
+                if value.is_null() {
+                    continue;
+                }
+                if let Some(first) = seen.insert(ExactValues(std::slice::from_ref(value)), row.id) {
+                    bail!("Rows {} and {} both hold {} in unique column '{}'", first, row.id, value, column.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses one string per column into a row of the declared types and
+    /// validates it, ready for `insert`.
+    pub fn coerce_row(&self, raw: &[String]) -> anyhow::Result<Vec<DbValue>> {
+        if raw.len() != self.schema.columns.len() {
+            bail!("Row length does not match schema length");
+        }
+
+        let row = raw.iter().zip(&self.schema.columns)
+            .map(|(text, col)| {
+                DbValue::parse(text, &col.column_type)
+                    .map_err(|e| anyhow::anyhow!("Column '{}': {}", col.name, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.validate(&row)?;
+        Ok(row)
+    }
+
+    /// Checks a single value against the column at position `col`.
+    pub fn validate_cell(&self, col: usize, value: &DbValue) -> anyhow::Result<()> {
+        let column = &self.schema.columns[col];
+        match value.value_type() {
+            None if column.nullable => return Ok(()),
+            None => bail!("Column '{}' does not accept null", column.name),
+            Some(value_type) if value_type != column.column_type => bail!("Value type does not match schema type"),
+            Some(_) => {}
+        }
+
+        if let Some(n) = value.as_f64() {
+            if let Some(min) = column.min.filter(|min| n < *min) {
+                bail!("Value {} for column '{}' is below the minimum of {}", n, column.name, min);
+            }
+            if let Some(max) = column.max.filter(|max| n > *max) {
+                bail!("Value {} for column '{}' is above the maximum of {}", n, column.name, max);
+            }
+        }
+
+        if let DbValue::MoneyRange(min, max) = value {
+            if min > max {
+                bail!("MoneyRange min must not exceed max");
+            }
+        }
+
+        if let DbValue::Percentage(p) = value {
+            if !(0.0..=100.0).contains(p) {
+                bail!("Value {} for column '{}' is not a percentage between 0 and 100", p, column.name);
+            }
+        }
+
+        if let DbValue::Date(timestamp) = value {
+            if !is_date(*timestamp) {
+                bail!("Value {} for column '{}' is not a whole day", timestamp, column.name);
+            }
+        }
+
+        if let (DbValue::String(text), Some(max_len)) = (value, column.max_len) {
+            let len = text.chars().count();
+            if len > max_len {
+                bail!("Value for column '{}' is {} characters long, above the limit of {}", column.name, len, max_len);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_row(&self, id: u32) -> anyhow::Result<&Row> {
+        self.rows.get(&id).ok_or_else(|| anyhow::anyhow!("Row not found"))
+    }
+
+    pub fn get_row_mut(&mut self, id: u32) -> anyhow::Result<&mut Row> {
+        self.rows.get_mut(&id).ok_or_else(|| anyhow::anyhow!("Row not found"))
+    }
+
+    /// Number of rows, without cloning any of them.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.rows.contains_key(&id)
+    }
+
+    /// All rows in display order; see `get_rows_ordered`.
+    pub fn get_rows(&self) -> Vec<Row> {
+        self.get_rows_ordered().into_iter().cloned().collect()
+    }
+
+    /// Up to `n` distinct rows chosen uniformly at random, for previewing a
+    /// large table. Returns every row when `n` exceeds the row count.
+    pub fn sample(&self, n: usize) -> Vec<Row> {
+        use rand::seq::IteratorRandom;
+        self.rows.values().cloned().choose_multiple(&mut rand::thread_rng(), n)
+    }
+
+    /// All rows in display order, with columns marked `include_in_list: false`
+    /// dropped.
+    pub fn list_rows(&self) -> Vec<Row> {
+        let columns = self.schema.list_columns();
+        self.get_rows_ordered().into_iter().map(|r| r.project(&columns)).collect()
+    }
+
+    /// Stats for every column, gathered in a single pass over the rows.
+    pub fn describe(&self) -> Vec<ColumnStats> {
+        struct Acc<'a> {
+            distinct: HashSet<&'a DbValue>,
+            min: f64,
+            max: f64,
+            sum: f64,
+            numeric: usize,
+        }
+
+        let mut accs: Vec<Acc> = self.schema.columns.iter()
+            .map(|_| Acc { distinct: HashSet::new(), min: f64::INFINITY, max: f64::NEG_INFINITY, sum: 0.0, numeric: 0 })
+            .collect();
+
+        for row in self.rows.values() {
+            for (acc, value) in accs.iter_mut().zip(&row.values) {
+                acc.distinct.insert(value);
+                if let Some(n) = value.as_f64() {
+                    acc.min = acc.min.min(n);
+                    acc.max = acc.max.max(n);
+                    acc.sum += n;
+                    acc.numeric += 1;
+                }
+            }
+        }
+
+        self.schema.columns.iter().zip(accs)
+            .map(|(col, acc)| {
+                let numeric = acc.numeric > 0;
+                ColumnStats {
+                    column: col.name.clone(),
+                    column_type: col.column_type.clone(),
+                    count: self.rows.len(),
+                    distinct: acc.distinct.len(),
+                    min: numeric.then_some(acc.min),
+                    max: numeric.then_some(acc.max),
+                    mean: numeric.then(|| acc.sum / acc.numeric as f64),
+                }
+            })
+            .collect()
+    }
+
+    /// Aggregates the non-null values of `column`. `Count` works on any column;
+    /// the others need an Integer, Real, Money or Percentage column. Over no
+    /// values `Sum` is 0, while `Avg`, `Min` and `Max` are undefined and
+    /// return an error.
+    pub fn aggregate(&self, column: &str, op: AggregateOp) -> anyhow::Result<f64> {
+        self.aggregate_with(column, op, true)
+    }
+
+    /// Like `aggregate`, but with `ignore_nulls` unset nulls count as rows:
+    /// `Count` counts every row and `Avg` divides by the number of rows.
+    /// `Sum`, `Min` and `Max` skip nulls either way.
+    pub fn aggregate_with(&self, column: &str, op: AggregateOp, ignore_nulls: bool) -> anyhow::Result<f64> {
+        let col = self.schema.column_index(column)
+            .ok_or_else(|| anyhow!("Column '{}' not found", column))?;
+        let values = self.rows.values()
+            .map(|r| &r.values[col])
+            .filter(|v| !v.is_null());
+        if op == AggregateOp::Count {
+            return Ok(if ignore_nulls { values.count() } else { self.rows.len() } as f64);
+        }
+
+        let column_type = &self.schema.columns[col].column_type;
+        if !matches!(column_type, DbColumnType::Integer | DbColumnType::Real | DbColumnType::Money | DbColumnType::Percentage) {
+            bail!("Cannot compute {:?} of {:?} column '{}'", op, column_type, column);
+        }
+        let numbers: Vec<f64> = values.filter_map(DbValue::as_f64).collect();
+        let averaged = if ignore_nulls { numbers.len() } else { self.rows.len() };
+        if (numbers.is_empty() && matches!(op, AggregateOp::Min | AggregateOp::Max)) || (averaged == 0 && op == AggregateOp::Avg) {
+            bail!("Cannot compute {:?} of column '{}' without values", op, column);
+        }
+
+        Ok(match op {
+            AggregateOp::Sum => numbers.iter().sum(),
+            AggregateOp::Avg => numbers.iter().sum::<f64>() / averaged as f64,
+            AggregateOp::Min => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+            AggregateOp::Max => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            AggregateOp::Count => unreachable!(),
+        })
+    }
+
+    /// All rows ordered by the typed values of `column`: numerically for
+    /// numbers and money (ranges by their lower bound first), lexicographically
+    /// for text. Ties stay in display order.
+    pub fn sorted_rows(&self, column: &str, descending: bool) -> anyhow::Result<Vec<Row>> {
+        self.sorted_by(&[SortKey { column: column.to_string(), descending }])
+    }
+
+    /// All rows ordered by `keys`, earlier keys taking precedence. Rows that
+    /// tie on every key stay in display order.
+    pub fn sorted_by(&self, keys: &[SortKey]) -> anyhow::Result<Vec<Row>> {
+        let columns = keys.iter()
+            .map(|k| {
+                self.schema.column_index(&k.column)
+                    .map(|i| (i, k.descending))
+                    .ok_or_else(|| anyhow::anyhow!("Cannot sort by unknown column '{}'", k.column))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut rows = self.get_rows();
+        rows.sort_by(|a, b| {
+            columns.iter()
+                .map(|&(i, descending)| {
+                    let ordering = a.values[i].cmp(&b.values[i]);
+                    if descending { ordering.reverse() } else { ordering }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(rows)
+    }
+
+    /// Up to `limit` rows with ids greater than `after` (all rows when
+    /// `None`), in ascending id order. Ids only grow (short of `renumber`), so
+    /// paging with the last returned id as the next `after` neither skips nor
+    /// repeats rows when others are inserted in between.
+    pub fn rows_after(&self, after: Option<u32>, limit: usize) -> Vec<&Row> {
+        let mut rows: Vec<&Row> = self.rows.values()
+            .filter(|r| after.is_none_or(|after| r.id > after))
+            .collect();
+        rows.sort_by_key(|r| r.id);
+        rows.truncate(limit);
+        rows
+    }
+
+    /// All rows in display order: the explicit `order` if rows have been
+    /// reordered, otherwise id order.
+    pub fn get_rows_ordered(&self) -> Vec<&Row> {
+        match &self.order {
+            Some(order) => order.iter().filter_map(|id| self.rows.get(id)).collect(),
+            None => {
+                let mut rows: Vec<&Row> = self.rows.values().collect();
+                rows.sort_by_key(|r| r.id);
+                rows
+            }
+        }
+    }
+
+    /// Moves row `id` to `position` in the display order (clamped to the
+    /// end). Ids are not changed.
+    pub fn move_row(&mut self, id: u32, position: usize) -> anyhow::Result<()> {
+        if !self.rows.contains_key(&id) {
+            bail!("Row not found");
+        }
+
+        let order = match self.order.take() {
+            Some(order) => order,
+            None => self.get_rows_ordered().iter().map(|r| r.id).collect(),
+        };
+        let mut order: Vec<u32> = order.into_iter().filter(|&o| o != id).collect();
+        order.insert(position.min(order.len()), id);
+        self.order = Some(order);
+        Ok(())
+    }
+
+    /// Share of the row storage's capacity not holding a row, from 0.0
+    /// towards 1.0. Deleted rows leave their capacity behind until `compact`.
+    pub fn fragmentation(&self) -> f64 {
+        let capacity = self.rows.capacity();
+        if capacity == 0 {
+            return 0.0;
+        }
+        1.0 - self.rows.len() as f64 / capacity as f64
+    }
+
+    /// Releases storage left behind by deleted rows: shrinks the row map,
+    /// drops stale ids from `order` and rebuilds the indexes. Row ids and
+    /// `index` are unchanged. Returns whether any capacity was released.
+    pub fn compact(&mut self) -> bool {
+        let capacity = self.rows.capacity();
+        self.rows.shrink_to_fit();
+        if let Some(order) = &mut self.order {
+            order.retain(|id| self.rows.contains_key(id));
+            order.shrink_to_fit();
+        }
+        self.rebuild_indexes();
+        self.rows.capacity() < capacity
+    }
+
+    /// Renumbers rows to consecutive ids starting at 0, preserving their
+    /// relative order, and resets `index` to the row count. Every id handed
+    /// out before, including `rows_after` cursors and references held by
+    /// other tables, then points at a different row or none, so this is only
+    /// done on explicit request.
+    pub fn renumber(&mut self) {
+        let mut rows: Vec<Row> = self.rows.drain().map(|(_, row)| row).collect();
+        rows.sort_by_key(|r| r.id);
+
+        let mut renumbered = HashMap::new();
+        for (id, mut row) in (0u32..).zip(rows) {
+            renumbered.insert(row.id, id);
+            row.id = id;
+            self.rows.insert(id, row);
+        }
+        if let Some(order) = &mut self.order {
+            for id in order.iter_mut() {
+                *id = renumbered[id];
+            }
+        }
+        self.index = self.rows.len() as u32;
+        self.rebuild_indexes();
+    }
+
+    /// Renders the table as an HTML `<table>`, one row per record in id order.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<table>\n<thead>\n<tr>");
+        for col in &self.schema.columns {
+            html.push_str(&format!("<th>{}</th>", escape_html(&col.name)));
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+
+        let mut rows: Vec<&Row> = self.rows.values().collect();
+        rows.sort_by_key(|r| r.id);
+        for row in rows {
+            html.push_str("<tr>");
+            for value in &row.values {
+                html.push_str(&format!("<td>{}</td>", escape_html(&value.to_string())));
+            }
+            html.push_str("</tr>\n");
+        }
+
+        html.push_str("</tbody>\n</table>\n");
+        html
+    }
+}
+
+/// Canonical ordering for set-operation output: by values, then by id.
+fn row_order(a: &Row, b: &Row) -> std::cmp::Ordering {
+    a.values.cmp(&b.values).then(a.id.cmp(&b.id))
+}
+
+fn sort_rows(rows: &mut [Row]) {
+    rows.sort_by(row_order);
+}
+
+/// A row's values as a hash set key. `DbValue`'s `PartialEq` lets floats
+/// within an epsilon match, which disagrees with its bitwise `Hash`; this
+/// compares exactly, by `Ord`, so set operations match values by hash alone.
+#[derive(Hash)]
+struct ExactValues<'a>(&'a [DbValue]);
+
+impl PartialEq for ExactValues<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.cmp(other.0).is_eq()
+    }
+}
+
+impl Eq for ExactValues<'_> {}
+
+/// A single value as an index key, compared exactly like `ExactValues`.
+#[derive(Debug, Clone)]
+struct ExactValue(DbValue);
+
+impl Hash for ExactValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialEq for ExactValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.cmp(&other.0).is_eq()
+    }
+}
+
+impl Eq for ExactValue {}
+
+/// A row's values as a hash set key matching by `DbValue`'s tolerant
+/// `PartialEq`. Floating-point values hash by variant only, so values within
+/// the epsilon always land in the same bucket and reach the comparison.
+struct TolerantValues<'a>(&'a [DbValue]);
+
+impl Hash for TolerantValues<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in self.0 {
+            match value {
+                DbValue::Real(_) | DbValue::Money(_) | DbValue::MoneyRange(_, _) | DbValue::Percentage(_) => {
+                    std::mem::discriminant(value).hash(state)
+                }
+                _ => value.hash(state),
+            }
+        }
+    }
+}
+
+impl PartialEq for TolerantValues<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for TolerantValues<'_> {}
+
+/// Drops rows repeating the values of the row before them; on rows in
+/// `row_order` this keeps the lowest id of each set of duplicates.
+fn dedup_rows(mut rows: Vec<Row>) -> Vec<Row> {
+    rows.dedup_by(|a, b| a.values.cmp(&b.values).is_eq());
+    rows
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+pub fn create_test_schema() -> DbSchema {
+    DbSchema::builder()
+        .column("col1", DbColumnType::Integer)
+        .column("col2", DbColumnType::String)
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+pub fn create_test_row() -> Vec<DbValue> {
+    vec![
+        DbValue::Integer(42),
+        DbValue::String("test".to_string()),
+    ]
+}
+
+#[cfg(test)]
+pub fn create_test_table(name: &str) -> Table {
+    let schema = DbSchema::builder()
+        .column("id", DbColumnType::Integer)
+        .column("name", DbColumnType::String)
+        .build()
+        .unwrap();
+    Table::new(name.to_string(), schema)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_creation() {
+        let schema = create_test_schema();
+        let table = Table::new("test_table".to_string(), schema.clone());
+        assert_eq!(table.name(), "test_table");
+        assert_eq!(table.schema, schema);
+        assert!(table.rows.is_empty());
+        assert_eq!(table.index, 0);
+    }
+
+    #[test]
+    fn test_insert_valid_row() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let row = create_test_row();
+        let id = table.insert(row.clone()).unwrap();
+
+        assert_eq!(id, 0);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.get_row(0).unwrap().values, row);
+    }
+
+    #[test]
+    fn test_insert_invalid_row_length() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let result = table.insert(vec![DbValue::Integer(42)]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_insert_invalid_type() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let result = table.insert(vec![
+            DbValue::String("wrong".to_string()),
+            DbValue::String("test".to_string()),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_delete_existing_row() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+        assert!(table.delete(id).is_ok());
+        assert!(table.rows.is_empty());
+    }
+
+    #[test]
+    fn test_delete_nonexistent_row() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        assert!(table.delete(0).is_err());
+    }
+
+    #[test]
+    fn test_update_existing_row() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+
+        let new_row = vec![
+            DbValue::Integer(99),
+            DbValue::String("updated".to_string()),
+        ];
+
+        assert!(table.update(id, new_row.clone()).is_ok());
+        assert_eq!(table.get_row(id).unwrap().values, new_row);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let mut table1 = Table::new("test_table".to_string(), create_test_schema());
+        let mut table2 = Table::new("test_table".to_string(), create_test_schema());
+
+        let row1 = create_test_row();
+        let row2 = vec![
+            DbValue::Integer(99),
+            DbValue::String("different".to_string()),
+        ];
+
+        table1.insert(row1.clone()).unwrap();
+        table1.insert(row2.clone()).unwrap();
+        table2.insert(row1.clone()).unwrap();
+
+        let intersection = table1.intersection(&table2).unwrap();
+
+        assert_eq!(intersection.len(), 1);
+        assert_eq!(intersection[0].values, row1);
+    }
+
+    #[test]
+    fn test_intersection_different_schemas() {
+        let schema1 = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("name", DbColumnType::String)
+            .build()
+            .unwrap();
+
+        let schema2 = DbSchema::builder()
+            .column("id", DbColumnType::Integer)
+            .column("age", DbColumnType::Integer)
+            .build()
+            .unwrap();
+
+        let table1 = Table::new("test_table".to_string(), schema1);
+        let table2 = Table::new("test_table".to_string(), schema2);
+
+        assert!(table1.intersection(&table2).is_err());
+    }
+
+    #[test]
+    fn test_get_rows() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let row1 = create_test_row();
+        let row2 = vec![
+            DbValue::Integer(99),
+            DbValue::String("different".to_string()),
+        ];
+
+        table.insert(row1.clone()).unwrap();
+        table.insert(row2.clone()).unwrap();
+
+        let rows = table.get_rows();
+        assert_eq!(rows.len(), 2);
         assert!(rows.iter().any(|r| r.values == row1));
         assert!(rows.iter().any(|r| r.values == row2));
     }
+
+    fn create_indexed_table() -> Table {
+        let mut schema = create_test_schema();
+        schema.columns[1].indexed = true;
+        Table::new("test_table".to_string(), schema)
+    }
+
+    #[test]
+    fn test_find_by_indexed_column_tracks_mutations() {
+        let mut table = create_indexed_table();
+        let a = table.insert(create_test_row()).unwrap();
+        let b = table.insert(vec![DbValue::Integer(7), DbValue::String("other".to_string())]).unwrap();
+        let c = table.insert(create_test_row()).unwrap();
+        assert!(table.is_indexed("col2"));
+
+        let test = DbValue::String("test".to_string());
+        let other = DbValue::String("other".to_string());
+        let ids = |rows: Vec<&Row>| rows.iter().map(|r| r.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(table.find_by("col2", &test)), vec![a, c]);
+
+        table.update(b, create_test_row()).unwrap();
+        assert_eq!(ids(table.find_by("col2", &test)), vec![a, b, c]);
+        assert!(table.find_by("col2", &other).is_empty());
+
+        table.delete(a).unwrap();
+        assert_eq!(ids(table.find_by("col2", &test)), vec![b, c]);
+    }
+
+    #[test]
+    fn test_find_by_unindexed_column_scans() {
+        let mut table = create_indexed_table();
+        table.insert(create_test_row()).unwrap();
+        table.insert(vec![DbValue::Integer(7), DbValue::String("other".to_string())]).unwrap();
+
+        assert!(!table.is_indexed("col1"));
+        let rows = table.find_by("col1", &DbValue::Integer(7));
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].values[1], DbValue::String("other".to_string()));
+        assert!(table.find_by("missing", &DbValue::Integer(7)).is_empty());
+    }
+
+    #[test]
+    fn test_find_by_near_equal_floats_indexed_and_scanned() {
+        let schema = DbSchema::builder()
+            .column("x", DbColumnType::Real)
+            .column("share", DbColumnType::Percentage)
+            .build().unwrap();
+        let mut scanned = Table::new("test_table".to_string(), schema);
+        for (x, share) in [(1.0, 0.5), (1.000_000_6, 0.500_000_000_01), (2.0, 0.7)] {
+            scanned.insert(vec![DbValue::Real(x), DbValue::Percentage(share)]).unwrap();
+        }
+        let mut indexed = scanned.clone();
+        indexed.create_index("x").unwrap();
+        indexed.create_index("share").unwrap();
+
+        let ids = |rows: Vec<&Row>| rows.iter().map(|r| r.id).collect::<Vec<_>>();
+        for (column, value, expected) in [
+            ("x", DbValue::Real(1.0), vec![0]),
+            ("x", DbValue::Real(1.000_000_6), vec![1]),
+            ("share", DbValue::Percentage(0.5), vec![0]),
+            ("share", DbValue::Percentage(0.500_000_000_01), vec![1]),
+        ] {
+            assert_eq!(ids(indexed.find_by(column, &value)), expected);
+            assert_eq!(ids(scanned.find_by(column, &value)), expected);
+        }
+    }
+
+    #[test]
+    fn test_create_index_matches_scan() {
+        let mut scanned = Table::new("test_table".to_string(), create_test_schema());
+        for i in 0..50 {
+            scanned.insert(vec![DbValue::Integer(i % 7), DbValue::String(format!("name{}", i % 5))]).unwrap();
+        }
+        scanned.delete(3).unwrap();
+        let mut indexed = scanned.clone();
+        indexed.create_index("col1").unwrap();
+        assert!(indexed.is_indexed("col1"));
+        assert!(!scanned.is_indexed("col1"));
+
+        for i in 0..8 {
+            let value = DbValue::Integer(i);
+            assert_eq!(indexed.find_by("col1", &value), scanned.find_by("col1", &value));
+        }
+
+        indexed.insert(vec![DbValue::Integer(2), DbValue::String("late".to_string())]).unwrap();
+        scanned.insert(vec![DbValue::Integer(2), DbValue::String("late".to_string())]).unwrap();
+        assert_eq!(indexed.find_by("col1", &DbValue::Integer(2)), scanned.find_by("col1", &DbValue::Integer(2)));
+
+        let reloaded: Table = serde_json::from_str(&serde_json::to_string(&indexed).unwrap()).unwrap();
+        assert!(reloaded.is_indexed("col1"));
+        assert!(indexed.create_index("missing").is_err());
+    }
+
+    #[test]
+    fn test_validate_boolean() {
+        let schema = DbSchema::builder()
+            .column("name", DbColumnType::String)
+            .column("active", DbColumnType::Boolean)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+
+        let id = table.insert(vec![DbValue::String("ann".to_string()), DbValue::Boolean(true)]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values[1], DbValue::Boolean(true));
+        assert!(table.insert(vec![DbValue::String("bob".to_string()), DbValue::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_validate_date() {
+        let schema = DbSchema::builder()
+            .column("name", DbColumnType::String)
+            .column("born", DbColumnType::Date)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        let date = |text: &str| DbValue::parse(text, &DbColumnType::Date).unwrap();
+
+        let id = table.insert(vec![DbValue::String("ann".to_string()), date("1990-05-17")]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values[1], date("1990-05-17"));
+        assert!(table.to_html().contains("<td>1990-05-17</td>"));
+        assert!(table.insert(vec![DbValue::String("bob".to_string()), DbValue::Date(12)]).is_err());
+        assert!(table.insert(vec![DbValue::String("bob".to_string()), DbValue::String("1990-05-17".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_validate_null() {
+        let schema = DbSchema::builder()
+            .column("name", DbColumnType::String)
+            .column("nickname", DbColumnType::String)
+            .nullable("nickname")
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+
+        let err = table.insert(vec![DbValue::Null, DbValue::Null]).unwrap_err();
+        assert_eq!(err.to_string(), "Column 'name' does not accept null");
+
+        let id = table.insert(vec![DbValue::String("ann".to_string()), DbValue::Null]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values[1], DbValue::Null);
+        table.update(id, vec![DbValue::String("ann".to_string()), DbValue::String("A".to_string())]).unwrap();
+    }
+
+    #[test]
+    fn test_insert_defaults() {
+        let schema = DbSchema::builder()
+            .column("name", DbColumnType::String)
+            .column("active", DbColumnType::Boolean)
+            .default_value("active", DbValue::Boolean(true))
+            .column("score", DbColumnType::Integer)
+            .default_value("score", DbValue::Integer(10))
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        let name = |s: &str| DbValue::String(s.to_string());
+
+        let id = table.insert(vec![name("ann"), DbValue::Boolean(false), DbValue::Integer(3)]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values, vec![name("ann"), DbValue::Boolean(false), DbValue::Integer(3)]);
+
+        let id = table.insert(vec![name("bob")]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values, vec![name("bob"), DbValue::Boolean(true), DbValue::Integer(10)]);
+        let id = table.insert_reuse_ids(vec![name("cid"), DbValue::Boolean(false)]).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values, vec![name("cid"), DbValue::Boolean(false), DbValue::Integer(10)]);
+
+        let err = table.insert(vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Missing value for column 'name', which has no default");
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let mut table = create_test_table("people");
+        for (id, name) in [(1, "ann"), (2, "bob"), (3, "cid")] {
+            table.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+        assert!(table.validate_all().is_ok());
+
+        table.get_row_mut(2).unwrap().values[1] = DbValue::Integer(3);
+        table.get_row_mut(1).unwrap().values.pop();
+        let err = table.validate_all().unwrap_err();
+        assert_eq!(err.to_string(), "Row 1: Row length does not match schema length");
+
+        table.delete(1).unwrap();
+        let err = table.validate_all().unwrap_err();
+        assert_eq!(err.to_string(), "Row 2: Value type does not match schema type");
+    }
+
+    #[test]
+    fn test_validate_percentage() {
+        let schema = DbSchema::builder()
+            .column("name", DbColumnType::String)
+            .column("progress", DbColumnType::Percentage)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        let row = |p: f64| vec![DbValue::String("task".to_string()), DbValue::Percentage(p)];
+
+        let id = table.insert(row(42.5)).unwrap();
+        table.insert(row(0.0)).unwrap();
+        table.insert(row(100.0)).unwrap();
+
+        for p in [-0.1, 100.5, f64::NAN] {
+            assert!(table.insert(row(p)).is_err());
+        }
+        let err = table.insert(row(101.0)).unwrap_err();
+        assert_eq!(err.to_string(), "Value 101 for column 'progress' is not a percentage between 0 and 100");
+        assert!(table.update(id, row(150.0)).is_err());
+        assert!(table.set_cell(id, "progress", DbValue::Percentage(-5.0)).is_err());
+        assert_eq!(table.get_row(id).unwrap().values[1], DbValue::Percentage(42.5));
+        assert_eq!(table.len(), 3);
+        assert!(table.insert(vec![DbValue::String("task".to_string()), DbValue::Real(50.0)]).is_err());
+    }
+
+    #[test]
+    fn test_validate_money_range_order() {
+        let schema = DbSchema::builder()
+            .column("budget", DbColumnType::MoneyRange)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+
+        let id = table.insert(vec![DbValue::MoneyRange(5.0, 5.0)]).unwrap();
+        let err = table.insert(vec![DbValue::MoneyRange(6.0, 5.0)]).unwrap_err();
+        assert_eq!(err.to_string(), "MoneyRange min must not exceed max");
+        assert!(table.update(id, vec![DbValue::MoneyRange(6.0, 5.0)]).is_err());
+        assert_eq!(table.get_row(id).unwrap().values[0], DbValue::MoneyRange(5.0, 5.0));
+    }
+
+    #[test]
+    fn test_alter_schema_adds_and_drops_columns() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for n in [1, 2, 3] {
+            table.insert(vec![DbValue::Integer(n), DbValue::String(format!("row {}", n))]).unwrap();
+        }
+
+        let schema = DbSchema::builder()
+            .column("col2", DbColumnType::String)
+            .column("balance", DbColumnType::Money)
+            .column("note", DbColumnType::String)
+            .nullable("note")
+            .build()
+            .unwrap();
+        table.alter_schema(schema).unwrap();
+
+        for row in table.get_rows() {
+            assert_eq!(row.values.len(), 3);
+            assert_eq!(row.values[1..], [DbValue::Money(0.0), DbValue::Null]);
+        }
+        assert_eq!(table.get_row(0).unwrap().values[0], DbValue::String("row 1".to_string()));
+
+        let id = table.insert(vec![DbValue::String("new".to_string()), DbValue::Money(5.0), DbValue::Null]).unwrap();
+        assert_eq!(id, 3);
+    }
+
+    #[test]
+    fn test_alter_schema_converts_types() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        table.insert(vec![DbValue::Integer(1), DbValue::String("7".to_string())]).unwrap();
+
+        let mut schema = create_test_schema();
+        schema.columns[0].column_type = DbColumnType::String;
+        schema.columns[1].column_type = DbColumnType::Integer;
+        table.alter_schema(schema).unwrap();
+        assert_eq!(table.get_row(0).unwrap().values, vec![DbValue::String("1".to_string()), DbValue::Integer(7)]);
+
+        table.insert(vec![DbValue::String("abc".to_string()), DbValue::Integer(8)]).unwrap();
+        let before = table.clone();
+        let mut schema = table.schema.clone();
+        schema.columns[0].column_type = DbColumnType::Integer;
+        let err = table.alter_schema(schema).unwrap_err();
+        assert_eq!(err.to_string(), "Row 1, column 'col1': 'abc' is not an integer");
+        assert_eq!(table, before);
+    }
+
+    #[test]
+    fn test_alter_schema_to_money_rounds_cents() {
+        let schema = DbSchema::builder().column("amount", DbColumnType::Real).build().unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        table.insert(vec![DbValue::Real(12.344)]).unwrap();
+        table.insert(vec![DbValue::Real(0.126)]).unwrap();
+
+        let schema = DbSchema::builder().column("amount", DbColumnType::Money).build().unwrap();
+        table.alter_schema(schema).unwrap();
+        assert_eq!(table.get_row(0).unwrap().values, vec![DbValue::Money(12.34)]);
+        assert_eq!(table.get_row(1).unwrap().values, vec![DbValue::Money(0.13)]);
+    }
+
+    #[test]
+    fn test_len() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        assert!(table.is_empty());
+
+        let id = table.insert(create_test_row()).unwrap();
+        table.insert(create_test_row()).unwrap();
+        assert_eq!(table.len(), 2);
+
+        table.delete(id).unwrap();
+        assert_eq!(table.len(), 1);
+        assert!(!table.is_empty());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for i in 0..3 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("name{}", i))]).unwrap();
+        }
+        let mut sample: Vec<u32> = table.sample(5).iter().map(|r| r.id).collect();
+        sample.sort();
+        assert_eq!(sample, vec![0, 1, 2]);
+        assert!(table.sample(0).is_empty());
+
+        for i in 3..100 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("name{}", i))]).unwrap();
+        }
+        let samples: HashSet<Vec<u32>> = (0..20)
+            .map(|_| {
+                let sample: Vec<u32> = table.sample(5).iter().map(|r| r.id).collect();
+                assert_eq!(sample.len(), 5);
+                assert_eq!(sample.iter().collect::<HashSet<_>>().len(), 5);
+                sample
+            })
+            .collect();
+        assert!(samples.len() > 1);
+    }
+
+    #[test]
+    fn test_money_normalized() {
+        let schema = DbSchema::builder()
+            .column("price", DbColumnType::Money)
+            .column("band", DbColumnType::MoneyRange)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        let money = |table: &Table, id: u32| match &table.get_row(id).unwrap().values[..] {
+            [DbValue::Money(m), DbValue::MoneyRange(min, max)] => (*m, *min, *max),
+            other => panic!("unexpected row {:?}", other),
+        };
+
+        let id = table.insert(vec![DbValue::Money(10.1 + 20.2), DbValue::MoneyRange(0.125, 10.005)]).unwrap();
+        assert_eq!(money(&table, id), (30.3, 0.12, 10.0));
+
+        table.update(id, vec![DbValue::Money(0.1 + 0.2), DbValue::MoneyRange(1.0, 2.0)]).unwrap();
+        assert_eq!(money(&table, id).0, 0.3);
+
+        table.set_cell(id, "price", DbValue::Money(2.675)).unwrap();
+        assert_eq!(money(&table, id).0, 2.68);
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+        assert!(table.contains(id));
+        assert!(!table.contains(id + 1));
+
+        table.delete(id).unwrap();
+        assert!(!table.contains(id));
+    }
+
+    #[test]
+    fn test_get_row_mut_missing() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        assert_eq!(table.get_row_mut(7).unwrap_err().to_string(), "Row not found");
+        assert!(table.update(7, create_test_row()).is_err());
+    }
+
+    #[test]
+    fn test_filter_numeric() {
+        use crate::types::filter::FilterOp;
+
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for n in [5, 15, 25] {
+            table.insert(vec![DbValue::Integer(n), DbValue::String(n.to_string())]).unwrap();
+        }
+
+        let values = |predicates: &[ColumnPredicate]| table.filter(predicates).unwrap()
+            .into_iter().map(|r| r.values[0].clone()).collect::<Vec<_>>();
+        assert_eq!(values(&[ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Integer(10))]), vec![DbValue::Integer(15), DbValue::Integer(25)]);
+        assert_eq!(values(&[ColumnPredicate::new("col1", FilterOp::Lt, DbValue::Integer(10))]), vec![DbValue::Integer(5)]);
+        assert_eq!(values(&[
+            ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Integer(10)),
+            ColumnPredicate::new("col1", FilterOp::Lt, DbValue::Integer(20)),
+        ]), vec![DbValue::Integer(15)]);
+        // A Real operand never matches an Integer column.
+        assert!(values(&[ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Real(1.0))]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_string() {
+        use crate::types::filter::FilterOp;
+
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for (n, name) in [(1, "John"), (2, "Jane"), (3, "Bob")] {
+            table.insert(vec![DbValue::Integer(n), DbValue::String(name.to_string())]).unwrap();
+        }
+
+        let names = |predicate: ColumnPredicate| table.filter(&[predicate]).unwrap()
+            .into_iter().map(|r| r.values[1].clone()).collect::<Vec<_>>();
+        assert_eq!(names(ColumnPredicate::new("col2", FilterOp::Contains, DbValue::String("J".to_string()))), vec![
+            DbValue::String("John".to_string()),
+            DbValue::String("Jane".to_string()),
+        ]);
+        assert_eq!(names(ColumnPredicate::new("col2", FilterOp::Eq, DbValue::String("Bob".to_string()))), vec![DbValue::String("Bob".to_string())]);
+        assert!(names(ColumnPredicate::new("col1", FilterOp::Contains, DbValue::String("1".to_string()))).is_empty());
+
+        let missing = ColumnPredicate::new("missing", FilterOp::Eq, DbValue::Integer(1));
+        assert_eq!(table.filter(&[missing]).unwrap_err().to_string(), "Column 'missing' not found");
+    }
+
+    #[test]
+    fn test_update_where() {
+        use crate::types::filter::FilterOp;
+
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for n in [5, 15, 25] {
+            table.insert(vec![DbValue::Integer(n), DbValue::String("pending".to_string())]).unwrap();
+        }
+
+        let filter = Filter::new(vec![ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Integer(10))]);
+        let updated = table.update_where(&filter, "col2", DbValue::String("overdue".to_string())).unwrap();
+        assert_eq!(updated, 2);
+
+        for row in table.get_rows() {
+            let expected = if row.values[0] == DbValue::Integer(5) { "pending" } else { "overdue" };
+            assert_eq!(row.values[1], DbValue::String(expected.to_string()));
+        }
+
+        assert!(table.update_where(&filter, "col2", DbValue::Integer(1)).is_err());
+        assert!(table.update_where(&filter, "missing", DbValue::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_delete_where() {
+        use crate::types::filter::FilterOp;
+
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for n in [5, 15, 25, 35] {
+            table.insert(vec![DbValue::Integer(n), DbValue::String(n.to_string())]).unwrap();
+        }
+
+        let deleted = table.delete_where(&[
+            ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Integer(10)),
+            ColumnPredicate::new("col1", FilterOp::Lt, DbValue::Integer(30)),
+        ]).unwrap();
+        assert_eq!(deleted, 2);
+        let remaining: Vec<DbValue> = table.get_rows_ordered().iter().map(|r| r.values[0].clone()).collect();
+        assert_eq!(remaining, vec![DbValue::Integer(5), DbValue::Integer(35)]);
+        assert!(table.find_by("col2", &DbValue::String("15".to_string())).is_empty());
+
+        assert_eq!(table.delete_where(&[ColumnPredicate::new("col1", FilterOp::Gt, DbValue::Integer(100))]).unwrap(), 0);
+        assert!(table.delete_where(&[ColumnPredicate::new("missing", FilterOp::Eq, DbValue::Integer(5))]).is_err());
+        assert_eq!(table.len(), 2);
+
+        // Ids keep growing past the deleted rows.
+        assert_eq!(table.insert(vec![DbValue::Integer(1), DbValue::String("1".to_string())]).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_to_html_escapes_values() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        table.insert(vec![DbValue::Integer(1), DbValue::String("<b>Tom & Jerry</b>".to_string())]).unwrap();
+
+        let html = table.to_html();
+        assert!(html.contains("<th>col1</th><th>col2</th>"));
+        assert!(html.contains("<td>1</td><td>&lt;b&gt;Tom &amp; Jerry&lt;/b&gt;</td>"));
+        assert!(!html.contains("<b>"));
+    }
+
+    #[test]
+    fn test_set_cell() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+
+        table.set_cell(id, "col2", DbValue::String("changed".to_string())).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values, vec![
+            DbValue::Integer(42),
+            DbValue::String("changed".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_set_cell_type_mismatch() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+
+        assert!(table.set_cell(id, "col1", DbValue::String("oops".to_string())).is_err());
+        assert!(table.set_cell(id, "missing", DbValue::Integer(1)).is_err());
+        assert!(table.set_cell(id + 1, "col1", DbValue::Integer(1)).is_err());
+        assert_eq!(table.get_row(id).unwrap().values, create_test_row());
+    }
+
+    fn create_unique_table() -> Table {
+        let schema = DbSchema::builder()
+            .column("col1", DbColumnType::Integer)
+            .unique("col1")
+            .column("col2", DbColumnType::String)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        table.insert(create_test_row()).unwrap();
+        table
+    }
+
+    fn duplicate_key_row() -> Vec<DbValue> {
+        vec![DbValue::Integer(42), DbValue::String("duplicate".to_string())]
+    }
+
+    #[test]
+    fn test_insert_conflict_error() {
+        let mut table = create_unique_table();
+        assert!(table.insert(duplicate_key_row()).is_err());
+        assert!(table.insert_with(duplicate_key_row(), OnConflict::Error).is_err());
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.get_row(0).unwrap().values, create_test_row());
+    }
+
+    #[test]
+    fn test_insert_conflict_ignore() {
+        let mut table = create_unique_table();
+        let outcome = table.insert_with(duplicate_key_row(), OnConflict::Ignore).unwrap();
+        assert_eq!(outcome, InsertOutcome::Ignored(0));
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.get_row(0).unwrap().values, create_test_row());
+    }
+
+    #[test]
+    fn test_insert_conflict_replace() {
+        let mut table = create_unique_table();
+        let outcome = table.insert_with(duplicate_key_row(), OnConflict::Replace).unwrap();
+        assert_eq!(outcome, InsertOutcome::Replaced(0));
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.get_row(0).unwrap().values, duplicate_key_row());
+    }
+
+    #[test]
+    fn test_unique_real_column_matches_exactly() {
+        let schema = DbSchema::builder()
+            .column("x", DbColumnType::Real)
+            .unique("x")
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        table.insert(vec![DbValue::Real(1.0)]).unwrap();
+
+        // Within DbValue's epsilon but not the same value: no conflict, on
+        // insert or when validating the stored rows
+        table.insert(vec![DbValue::Real(1.000_000_6)]).unwrap();
+        assert!(table.validate_unique().is_ok());
+
+        assert!(table.insert(vec![DbValue::Real(1.0)]).is_err());
+        assert_eq!(table.insert_with(vec![DbValue::Real(1.000_000_6)], OnConflict::Ignore).unwrap(), InsertOutcome::Ignored(1));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_insert_many() {
+        let mut table = create_unique_table();
+        let rows = (1..4)
+            .map(|i| vec![DbValue::Integer(i), DbValue::String(format!("row{}", i))])
+            .collect();
+        assert_eq!(table.insert_many(rows).unwrap(), vec![1, 2, 3]);
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.get_row(3).unwrap().values[1], DbValue::String("row3".to_string()));
+        assert_eq!(table.find_by("col1", &DbValue::Integer(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_insert_many_is_all_or_nothing() {
+        let mut table = create_unique_table();
+        let before = table.clone();
+
+        let bad_type = vec![
+            vec![DbValue::Integer(1), DbValue::String("ok".to_string())],
+            vec![DbValue::String("oops".to_string()), DbValue::String("bad".to_string())],
+        ];
+        let err = table.insert_many(bad_type).unwrap_err();
+        assert!(err.to_string().starts_with("Row 1 of the batch"));
+        assert_eq!(table, before);
+
+        let clashing = vec![
+            vec![DbValue::Integer(5), DbValue::String("first".to_string())],
+            vec![DbValue::Integer(5), DbValue::String("second".to_string())],
+        ];
+        assert!(table.insert_many(clashing).is_err());
+        assert_eq!(table, before);
+        assert!(table.find_by("col1", &DbValue::Integer(5)).is_empty());
+        assert_eq!(table.insert(vec![DbValue::Integer(5), DbValue::String("x".to_string())]).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(Table::try_new("ok".to_string(), create_test_schema()).is_ok());
+        assert!(Table::try_new(" ".to_string(), create_test_schema()).is_err());
+
+        let mut duplicate = create_test_schema();
+        duplicate.columns[1].name = "col1".to_string();
+        let err = Table::try_new("dupes".to_string(), duplicate).unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate column 'col1'");
+
+        let mut unnamed = create_test_schema();
+        unnamed.columns[0].name.clear();
+        assert!(Table::try_new("unnamed".to_string(), unnamed).is_err());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut table = create_indexed_table();
+        for _ in 0..3 {
+            table.insert(create_test_row()).unwrap();
+        }
+        table.move_row(2, 0).unwrap();
+        let schema = table.schema.clone();
+
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(table.schema, schema);
+        assert!(table.find_by("col2", &DbValue::String("test".to_string())).is_empty());
+
+        assert_eq!(table.insert(create_test_row()).unwrap(), 0);
+        assert_eq!(table.get_rows_ordered().len(), 1);
+    }
+
+    #[test]
+    fn test_insert_reuse_ids() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        for _ in 0..3 {
+            table.insert(create_test_row()).unwrap();
+        }
+        table.delete(0).unwrap();
+        table.delete(2).unwrap();
+
+        assert_eq!(table.insert_reuse_ids(create_test_row()).unwrap(), 0);
+        assert_eq!(table.insert_reuse_ids(create_test_row()).unwrap(), 2);
+        assert_eq!(table.insert_reuse_ids(create_test_row()).unwrap(), 3);
+        assert_eq!(table.index, 4);
+        assert_eq!(table.insert(create_test_row()).unwrap(), 4);
+
+        table.delete(1).unwrap();
+        assert_eq!(table.insert(create_test_row()).unwrap(), 5);
+        assert_eq!(table.len(), 5);
+    }
+
+    #[test]
+    fn test_row_timestamps() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+        let row = table.get_row(id).unwrap();
+        assert!(row.created_at > 0);
+        assert_eq!(row.created_at, row.updated_at);
+
+        // Backdate the row so a refresh is visible without sleeping.
+        let row = table.get_row_mut(id).unwrap();
+        row.created_at = 100;
+        row.updated_at = 100;
+        table.update(id, vec![DbValue::Integer(7), DbValue::String("changed".to_string())]).unwrap();
+        let row = table.get_row(id).unwrap();
+        assert_eq!(row.created_at, 100);
+        assert!(row.updated_at > 100);
+
+        table.get_row_mut(id).unwrap().updated_at = 100;
+        table.set_cell(id, "col1", DbValue::Integer(8)).unwrap();
+        assert!(table.get_row(id).unwrap().updated_at > 100);
+
+        let old: Row = serde_json::from_str(r#"{"id": 3, "values": []}"#).unwrap();
+        assert_eq!((old.created_at, old.updated_at), (0, 0));
+    }
+
+    #[test]
+    fn test_update_partial() {
+        let mut table = Table::new("test_table".to_string(), create_test_schema());
+        let id = table.insert(create_test_row()).unwrap();
+
+        let changes = HashMap::from([("col2".to_string(), DbValue::String("changed".to_string()))]);
+        table.update_partial(id, changes).unwrap();
+        assert_eq!(table.get_row(id).unwrap().values, vec![DbValue::Integer(42), DbValue::String("changed".to_string())]);
+
+        let wrong_type = HashMap::from([("col1".to_string(), DbValue::String("oops".to_string()))]);
+        assert!(table.update_partial(id, wrong_type).is_err());
+        let unknown = HashMap::from([("missing".to_string(), DbValue::Integer(1))]);
+        assert_eq!(table.update_partial(id, unknown).unwrap_err().to_string(), "Column 'missing' not found");
+        assert!(table.update_partial(id + 1, HashMap::new()).is_err());
+        assert_eq!(table.get_row(id).unwrap().values[1], DbValue::String("changed".to_string()));
+    }
+
+    #[test]
+    fn test_unique_enforced_on_update() {
+        let mut table = create_unique_table();
+        let id = table.insert(vec![DbValue::Integer(7), DbValue::String("other".to_string())]).unwrap();
+        assert!(table.update(id, duplicate_key_row()).is_err());
+        assert!(table.set_cell(id, "col1", DbValue::Integer(42)).is_err());
+        assert!(table.update(0, duplicate_key_row()).is_ok());
+    }
+
+    #[test]
+    fn test_unique_allows_many_nulls() {
+        let schema = DbSchema::builder()
+            .column("col1", DbColumnType::Integer)
+            .unique("col1")
+            .nullable("col1")
+            .column("col2", DbColumnType::String)
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        let first = table.insert(vec![DbValue::Null, DbValue::String("a".to_string())]).unwrap();
+        let second = table.insert(vec![DbValue::Null, DbValue::String("b".to_string())]).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(table.len(), 2);
+
+        let third = table.insert(vec![DbValue::Integer(1), DbValue::String("c".to_string())]).unwrap();
+        assert!(table.set_cell(third, "col1", DbValue::Null).is_ok());
+        assert!(table.insert(vec![DbValue::Integer(2), DbValue::String("d".to_string())]).is_ok());
+        assert!(table.insert(vec![DbValue::Integer(2), DbValue::String("e".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_intersection_sorted_matches_hash_intersection() {
+        let mut table1 = Table::new("table1".to_string(), create_test_schema());
+        let mut table2 = Table::new("table2".to_string(), create_test_schema());
+
+        for i in 0..200 {
+            let row = vec![DbValue::Integer(i % 50), DbValue::String(format!("name{}", i % 7))];
+            table1.insert(row).unwrap();
+        }
+        for i in 0..150 {
+            let row = vec![DbValue::Integer((i * 3) % 70), DbValue::String(format!("name{}", i % 5))];
+            table2.insert(row).unwrap();
+        }
+
+        let hashed = table1.intersection(&table2).unwrap();
+        let sorted = table1.intersection_sorted(&table2).unwrap();
+
+        assert!(!sorted.is_empty());
+        assert_eq!(hashed, sorted);
+    }
+
+    #[test]
+    fn test_intersection_sorted_near_equal_reals() {
+        let schema = DbSchema::builder().column("x", DbColumnType::Real).build().unwrap();
+        let mut table1 = Table::new("table1".to_string(), schema.clone());
+        let mut table2 = Table::new("table2".to_string(), schema);
+        for x in [1.0, 1.000_001_2, 2.0] {
+            table1.insert(vec![DbValue::Real(x)]).unwrap();
+        }
+        for x in [1.000_000_6, 2.0] {
+            table2.insert(vec![DbValue::Real(x)]).unwrap();
+        }
+
+        let sorted = table1.intersection_sorted(&table2).unwrap();
+        assert_eq!(table1.intersection(&table2).unwrap(), sorted);
+        assert_eq!(sorted.len(), 1);
+
+        // Union matches values exactly too; difference, and so symmetric
+        // difference, lets each near-equal real match its neighbours
+        assert_eq!(table1.union(&table2).unwrap().len(), table1.len() + table2.len() - sorted.len());
+        assert!(table1.difference(&table2).unwrap().is_empty());
+        assert!(table2.difference(&table1).unwrap().is_empty());
+        assert!(table1.symmetric_difference(&table2).unwrap().is_empty());
+    }
+
+    fn create_bounded_table() -> Table {
+        let schema = DbSchema::builder()
+            .column("col1", DbColumnType::Integer)
+            .range("col1", 0.0, 150.0)
+            .column("col2", DbColumnType::String)
+            .build()
+            .unwrap();
+        Table::new("test_table".to_string(), schema)
+    }
+
+    #[test]
+    fn test_insert_below_min() {
+        let mut table = create_bounded_table();
+        let err = table.insert(vec![DbValue::Integer(-1), DbValue::String("a".to_string())]).unwrap_err();
+        assert!(err.to_string().contains("minimum of 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_insert_above_max() {
+        let mut table = create_bounded_table();
+        let err = table.insert(vec![DbValue::Integer(151), DbValue::String("a".to_string())]).unwrap_err();
+        assert!(err.to_string().contains("maximum of 150"), "{}", err);
+    }
+
+    #[test]
+    fn test_insert_at_bounds() {
+        let mut table = create_bounded_table();
+        assert!(table.insert(vec![DbValue::Integer(0), DbValue::String("a".to_string())]).is_ok());
+        assert!(table.insert(vec![DbValue::Integer(150), DbValue::String("a".to_string())]).is_ok());
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut table = create_indexed_table();
+        for i in 0..4 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("row{}", i))]).unwrap();
+        }
+        table.delete(0).unwrap();
+        table.delete(2).unwrap();
+
+        table.renumber();
+        assert_eq!(table.index, 2);
+        assert_eq!(table.get_row(0).unwrap().values[0], DbValue::Integer(1));
+        assert_eq!(table.get_row(1).unwrap().values[0], DbValue::Integer(3));
+
+        let found = table.find_by("col2", &DbValue::String("row3".to_string()));
+        assert_eq!(found[0].id, 1);
+        assert_eq!(table.insert(create_test_row()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_compact_keeps_ids() {
+        let mut table = create_indexed_table();
+        for i in 0..100 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("row{}", i))]).unwrap();
+        }
+        table.move_row(99, 0).unwrap();
+        for id in 0..90 {
+            table.delete(id).unwrap();
+        }
+        assert!(table.fragmentation() > 0.5);
+
+        assert!(table.compact());
+        assert!(table.fragmentation() < 0.5);
+        assert!(!table.compact());
+
+        let ids: Vec<u32> = table.get_rows_ordered().iter().map(|r| r.id).collect();
+        assert_eq!(ids, [99].into_iter().chain(90..99).collect::<Vec<_>>());
+        assert_eq!(table.index, 100);
+        assert_eq!(table.find_by("col2", &DbValue::String("row95".to_string()))[0].id, 95);
+        assert_eq!(table.insert(create_test_row()).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_intersection_order_is_stable() {
+        let rows: Vec<Vec<DbValue>> = (0..20)
+            .map(|i| vec![DbValue::Integer((i * 7) % 20), DbValue::String(format!("name{}", i % 3))])
+            .collect();
+
+        let mut table1 = Table::new("table1".to_string(), create_test_schema());
+        for row in &rows {
+            table1.insert(row.clone()).unwrap();
+        }
+
+        let first = table1.intersection(&table1.clone()).unwrap();
+        for _ in 0..5 {
+            // A fresh HashMap gets a new iteration order each time
+            let mut other = Table::new("table2".to_string(), create_test_schema());
+            for row in &rows {
+                other.insert(row.clone()).unwrap();
+            }
+            assert_eq!(table1.intersection(&other).unwrap(), first);
+        }
+
+        assert!(first.windows(2).all(|w| w[0].values <= w[1].values));
+    }
+
+    #[test]
+    fn test_list_rows_drops_excluded_columns() {
+        let mut schema = create_test_schema();
+        schema.columns[1].include_in_list = false;
+        let mut table = Table::new("test_table".to_string(), schema);
+        let id = table.insert(create_test_row()).unwrap();
+
+        let rows = table.list_rows();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].id, &rows[0].values), (id, &vec![DbValue::Integer(42)]));
+        assert_eq!(table.get_row(id).unwrap().values.len(), 2);
+    }
+
+    #[test]
+    fn test_symmetric_difference() {
+        let mut table1 = create_test_table("table1");
+        let mut table2 = create_test_table("table2");
+        for (id, name) in [(1, "John"), (2, "Jane")] {
+            table1.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+        for (id, name) in [(2, "Jane"), (3, "Bob")] {
+            table2.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+
+        let only_first = table1.difference(&table2).unwrap();
+        assert_eq!(only_first.len(), 1);
+        assert_eq!(only_first[0].values[0], DbValue::Integer(1));
+
+        let result = table1.symmetric_difference(&table2).unwrap();
+        let ids: Vec<_> = result.iter().map(|r| r.values[0].clone()).collect();
+        assert_eq!(ids, vec![DbValue::Integer(1), DbValue::Integer(3)]);
+    }
+
+    #[test]
+    fn test_symmetric_difference_is_union_minus_intersection() {
+        let mut table1 = create_test_table("table1");
+        let mut table2 = create_test_table("table2");
+        for (id, name) in [(1, "John"), (1, "John"), (2, "Jane")] {
+            table1.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+        for (id, name) in [(2, "Jane"), (3, "Bob"), (3, "Bob")] {
+            table2.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+
+        let shared: Vec<_> = table1.intersection(&table2).unwrap().into_iter().map(|r| r.values).collect();
+        let mut expected: Vec<_> = table1.union(&table2).unwrap().into_iter()
+            .map(|r| r.values)
+            .filter(|values| !shared.contains(values))
+            .collect();
+        let mut result: Vec<_> = table1.symmetric_difference(&table2).unwrap().into_iter().map(|r| r.values).collect();
+        expected.sort();
+        result.sort();
+        assert_eq!(result, expected);
+
+        // The first of the duplicate rows keeps its id.
+        assert_eq!(table1.symmetric_difference(&table2).unwrap()[0].id, 0);
+    }
+
+    #[test]
+    fn test_intersection_with_epsilon() {
+        let mut schema = create_test_schema();
+        schema.columns[0].column_type = DbColumnType::Money;
+        let mut table1 = Table::new("table1".to_string(), schema.clone());
+        let mut table2 = Table::new("table2".to_string(), schema);
+        for amount in [1.0, 2.0] {
+            table1.insert(vec![DbValue::Money(amount), DbValue::String("x".to_string())]).unwrap();
+        }
+        for amount in [1.04, 2.0] {
+            table2.insert(vec![DbValue::Money(amount), DbValue::String("x".to_string())]).unwrap();
+        }
+
+        assert_eq!(table1.intersection_with_epsilon(&table2, 0.01).unwrap().len(), 1);
+        assert_eq!(table1.intersection_with_epsilon(&table2, 0.1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_move_row_order() {
+        let mut table = create_test_table("test_table");
+        for i in 0..4 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("row{}", i))]).unwrap();
+        }
+        let ids = |table: &Table| table.get_rows_ordered().iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&table), vec![0, 1, 2, 3]);
+
+        table.move_row(3, 0).unwrap();
+        assert_eq!(ids(&table), vec![3, 0, 1, 2]);
+        assert!(table.move_row(9, 0).is_err());
+        assert_eq!(table.get_rows().iter().map(|r| r.id).collect::<Vec<_>>(), vec![3, 0, 1, 2]);
+        assert_eq!(table.list_rows().iter().map(|r| r.id).collect::<Vec<_>>(), vec![3, 0, 1, 2]);
+
+        table.delete(0).unwrap();
+        let id = table.insert(vec![DbValue::Integer(4), DbValue::String("row4".to_string())]).unwrap();
+        assert_eq!(ids(&table), vec![3, 1, 2, id]);
+        assert_eq!(table.order.as_ref().unwrap().len(), table.rows.len());
+
+        table.renumber();
+        assert_eq!(ids(&table), vec![2, 0, 1, 3]);
+        assert_eq!(table.get_rows_ordered()[0].values[0], DbValue::Integer(3));
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut table = create_test_table("test_table");
+        table.schema.columns[1].max_len = Some(5);
+
+        assert!(table.insert(vec![DbValue::Integer(1), DbValue::String("héllo".to_string())]).is_ok());
+
+        let err = table.insert(vec![DbValue::Integer(2), DbValue::String("toolong".to_string())]).unwrap_err();
+        assert_eq!(err.to_string(), "Value for column 'name' is 7 characters long, above the limit of 5");
+    }
+
+    #[test]
+    fn test_sorted_by_multiple_keys() {
+        let mut table = create_test_table("test_table");
+        for (amount, status) in [(5, "open"), (7, "closed"), (9, "open"), (7, "open"), (1, "closed")] {
+            table.insert(vec![DbValue::Integer(amount), DbValue::String(status.to_string())]).unwrap();
+        }
+
+        let keys = SortKey::parse_list("name:asc,id:desc").unwrap();
+        let sorted: Vec<_> = table.sorted_by(&keys).unwrap().into_iter()
+            .map(|r| (r.values[1].clone(), r.values[0].clone()))
+            .collect();
+        let expected: Vec<_> = [("closed", 7), ("closed", 1), ("open", 9), ("open", 7), ("open", 5)].iter()
+            .map(|&(status, amount)| (DbValue::String(status.to_string()), DbValue::Integer(amount)))
+            .collect();
+        assert_eq!(sorted, expected);
+
+        assert!(SortKey::parse_list("name:sideways").is_err());
+        assert!(table.sorted_by(&SortKey::parse_list("missing").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_sorted_rows() {
+        let schema = DbSchema::builder()
+            .column("int", DbColumnType::Integer)
+            .column("real", DbColumnType::Real)
+            .column("char", DbColumnType::Char)
+            .column("text", DbColumnType::String)
+            .column("money", DbColumnType::Money)
+            .column("range", DbColumnType::MoneyRange)
+            .build()
+            .unwrap();
+        let mut table = Table::new("typed".to_string(), schema);
+        for (int, real, char, text, money, range) in [
+            (10, 2.5, 'b', "pear", 9.5, (5.0, 6.0)),
+            (-3, 10.0, 'a', "apple", 100.0, (1.0, 50.0)),
+            (2, -1.0, 'c', "banana", 0.25, (3.0, 4.0)),
+        ] {
+            table.insert(vec![
+                DbValue::Integer(int),
+                DbValue::Real(real),
+                DbValue::Char(char),
+                DbValue::String(text.to_string()),
+                DbValue::Money(money),
+                DbValue::MoneyRange(range.0, range.1),
+            ]).unwrap();
+        }
+
+        let ids = |column: &str, descending: bool| -> Vec<u32> {
+            table.sorted_rows(column, descending).unwrap().iter().map(|r| r.id).collect()
+        };
+        assert_eq!(ids("int", false), vec![1, 2, 0]);
+        assert_eq!(ids("int", true), vec![0, 2, 1]);
+        assert_eq!(ids("real", false), vec![2, 0, 1]);
+        assert_eq!(ids("char", false), vec![1, 0, 2]);
+        assert_eq!(ids("text", true), vec![0, 2, 1]);
+        assert_eq!(ids("money", false), vec![2, 0, 1]);
+        assert_eq!(ids("range", false), vec![1, 2, 0]);
+
+        let err = table.sorted_rows("missing", false).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot sort by unknown column 'missing'");
+    }
+
+    #[test]
+    fn test_sort_key_default_direction() {
+        let keys = SortKey::parse_list_with_default("a,b:asc", true).unwrap();
+        assert_eq!(keys, vec![
+            SortKey { column: "a".to_string(), descending: true },
+            SortKey { column: "b".to_string(), descending: false },
+        ]);
+        let err = SortKey::parse_list("a:up").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid sort direction 'up' for column 'a', expected asc or desc");
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut table = create_test_table("test_table");
+        assert_eq!(table.aggregate("id", AggregateOp::Count).unwrap(), 0.0);
+        assert_eq!(table.aggregate("id", AggregateOp::Sum).unwrap(), 0.0);
+        assert!(table.aggregate("id", AggregateOp::Avg).is_err());
+        assert!(table.aggregate("id", AggregateOp::Max).is_err());
+
+        for (id, name) in [(4, "ann"), (1, "bob"), (7, "cid")] {
+            table.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+        assert_eq!(table.aggregate("id", AggregateOp::Sum).unwrap(), 12.0);
+        assert_eq!(table.aggregate("id", AggregateOp::Avg).unwrap(), 4.0);
+        assert_eq!(table.aggregate("id", AggregateOp::Min).unwrap(), 1.0);
+        assert_eq!(table.aggregate("id", AggregateOp::Max).unwrap(), 7.0);
+        assert_eq!(table.aggregate("name", AggregateOp::Count).unwrap(), 3.0);
+
+        assert!(table.aggregate("name", AggregateOp::Sum).is_err());
+        assert!(table.aggregate("missing", AggregateOp::Count).is_err());
+        assert_eq!("avg".parse::<AggregateOp>().unwrap(), AggregateOp::Avg);
+        assert!("median".parse::<AggregateOp>().is_err());
+    }
+
+    #[test]
+    fn test_aggregate_with_nulls() {
+        let schema = DbSchema::builder()
+            .column("amount", DbColumnType::Integer)
+            .nullable("amount")
+            .build()
+            .unwrap();
+        let mut table = Table::new("test_table".to_string(), schema);
+        for amount in [DbValue::Integer(2), DbValue::Null, DbValue::Integer(4), DbValue::Null] {
+            table.insert(vec![amount]).unwrap();
+        }
+
+        let aggregate = |op, ignore_nulls| table.aggregate_with("amount", op, ignore_nulls).unwrap();
+        assert_eq!(aggregate(AggregateOp::Count, true), 2.0);
+        assert_eq!(aggregate(AggregateOp::Sum, true), 6.0);
+        assert_eq!(aggregate(AggregateOp::Avg, true), 3.0);
+        assert_eq!(aggregate(AggregateOp::Min, true), 2.0);
+
+        assert_eq!(aggregate(AggregateOp::Count, false), 4.0);
+        assert_eq!(aggregate(AggregateOp::Sum, false), 6.0);
+        assert_eq!(aggregate(AggregateOp::Avg, false), 1.5);
+        assert_eq!(aggregate(AggregateOp::Max, false), 4.0);
+        assert_eq!(table.aggregate("amount", AggregateOp::Avg).unwrap(), 3.0);
+
+        // Only nulls: no average when ignoring them, zero when counting them
+        let mut nulls = Table::new("nulls".to_string(), table.schema.clone());
+        nulls.insert(vec![DbValue::Null]).unwrap();
+        assert!(nulls.aggregate_with("amount", AggregateOp::Avg, true).is_err());
+        assert_eq!(nulls.aggregate_with("amount", AggregateOp::Avg, false).unwrap(), 0.0);
+        assert!(nulls.aggregate_with("amount", AggregateOp::Min, false).is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut table = create_test_table("test_table");
+        for (id, name) in [(1, "ann"), (4, "bob"), (4, "ann")] {
+            table.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+
+        let stats = table.describe();
+        assert_eq!(stats[0], ColumnStats {
+            column: "id".to_string(),
+            column_type: DbColumnType::Integer,
+            count: 3,
+            distinct: 2,
+            min: Some(1.0),
+            max: Some(4.0),
+            mean: Some(3.0),
+        });
+        assert_eq!(stats[1].distinct, 2);
+        assert_eq!(stats[1].min, None);
+        assert_eq!(stats[1].mean, None);
+    }
+
+    #[test]
+    fn test_rows_after() {
+        let mut table = create_test_table("test_table");
+        for i in 0..5 {
+            table.insert(vec![DbValue::Integer(i), DbValue::String(format!("row{}", i))]).unwrap();
+        }
+        table.delete(2).unwrap();
+
+        let ids = |rows: Vec<&Row>| rows.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(table.rows_after(None, 2)), vec![0, 1]);
+        assert_eq!(ids(table.rows_after(Some(1), 2)), vec![3, 4]);
+        assert!(table.rows_after(Some(4), 2).is_empty());
+    }
+
+    #[test]
+    fn test_coerce_row() {
+        let table = create_test_table("test_table");
+
+        let row = table.coerce_row(&["7".to_string(), "Ann".to_string()]).unwrap();
+        assert_eq!(row, vec![DbValue::Integer(7), DbValue::String("Ann".to_string())]);
+
+        let err = table.coerce_row(&["seven".to_string(), "Ann".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "Column 'id': 'seven' is not an integer");
+    }
+
+    #[test]
+    fn test_distinct() {
+        let mut table = create_test_table("dupes");
+        table.insert(vec![DbValue::Integer(1), DbValue::String("Ann".to_string())]).unwrap();
+        table.insert(vec![DbValue::Integer(2), DbValue::String("Bob".to_string())]).unwrap();
+        table.insert(vec![DbValue::Integer(1), DbValue::String("Ann".to_string())]).unwrap();
+
+        let rows = table.distinct();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.iter().map(|r| r.id).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    fn create_orders_table() -> Table {
+        let schema = DbSchema::builder()
+            .column("user_id", DbColumnType::Integer)
+            .column("item", DbColumnType::String)
+            .build()
+            .unwrap();
+        let mut orders = Table::new("orders".to_string(), schema);
+        for (user, item) in [(2, "pen"), (1, "book"), (2, "ink")] {
+            orders.insert(vec![DbValue::Integer(user), DbValue::String(item.to_string())]).unwrap();
+        }
+        orders
+    }
+
+    #[test]
+    fn test_join_one_to_many() {
+        let mut users = create_test_table("users");
+        for (id, name) in [(1, "ann"), (2, "bob"), (3, "cid")] {
+            users.insert(vec![DbValue::Integer(id), DbValue::String(name.to_string())]).unwrap();
+        }
+
+        let (schema, rows) = users.join(&create_orders_table(), "id", "user_id").unwrap();
+        let names: Vec<&str> = schema.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "user_id", "item"]);
+
+        let pairs: Vec<(DbValue, DbValue)> = rows.iter().map(|r| (r.values[1].clone(), r.values[3].clone())).collect();
+        let expected: Vec<(DbValue, DbValue)> = [("ann", "book"), ("bob", "pen"), ("bob", "ink")].iter()
+            .map(|&(name, item)| (DbValue::String(name.to_string()), DbValue::String(item.to_string())))
+            .collect();
+        assert_eq!(pairs, expected);
+        assert_eq!(rows.iter().map(|r| r.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let (schema, _) = users.join(&users, "id", "id").unwrap();
+        assert_eq!(schema.columns[2].name, "users.id");
+    }
+
+    #[test]
+    fn test_join_without_matches() {
+        let mut users = create_test_table("users");
+        users.insert(vec![DbValue::Integer(9), DbValue::String("zed".to_string())]).unwrap();
+        let orders = create_orders_table();
+
+        let (schema, rows) = users.join(&orders, "id", "user_id").unwrap();
+        assert_eq!(schema.columns.len(), 4);
+        assert!(rows.is_empty());
+
+        assert!(users.join(&orders, "missing", "user_id").is_err());
+        assert!(users.join(&orders, "id", "missing").is_err());
+        assert!(users.join(&orders, "name", "user_id").is_err());
+    }
+
+    #[test]
+    fn test_union() {
+        let row = |id: i32, name: &str| vec![DbValue::Integer(id), DbValue::String(name.to_string())];
+        let table_with = |name: &str, rows: &[Vec<DbValue>]| {
+            let mut table = create_test_table(name);
+            for r in rows {
+                table.insert(r.clone()).unwrap();
+            }
+            table
+        };
+
+        let a = table_with("a", &[row(1, "x"), row(2, "y")]);
+        let disjoint = table_with("b", &[row(3, "z")]);
+        let overlapping = table_with("c", &[row(2, "y"), row(3, "z")]);
+
+        assert_eq!(a.union(&disjoint).unwrap().len(), 3);
+        assert_eq!(a.union(&overlapping).unwrap().len(), 3);
+
+        let identical = a.union(&a).unwrap();
+        assert_eq!(identical.len(), 2);
+        assert_eq!(identical.iter().map(|r| r.values.clone()).collect::<Vec<_>>(), vec![row(1, "x"), row(2, "y")]);
+
+        let mismatched = Table::new("m".to_string(), create_test_schema());
+        assert!(a.union(&mismatched).is_err());
+    }
+
+    #[test]
+    fn test_difference_is_asymmetric() {
+        let mut schema = create_test_schema();
+        schema.columns[0].column_type = DbColumnType::Money;
+        let mut a = Table::new("a".to_string(), schema.clone());
+        let mut b = Table::new("b".to_string(), schema);
+        let row = |amount: f64| vec![DbValue::Money(amount), DbValue::String("x".to_string())];
+        for amount in [1.0, 2.0] {
+            a.insert(row(amount)).unwrap();
+        }
+        // Equal to 2.0 within the Money epsilon, but not bit-for-bit.
+        for amount in [2.0 + 1e-12, 3.0] {
+            b.insert(row(amount)).unwrap();
+        }
+
+        let values = |rows: Vec<Row>| rows.into_iter().map(|r| r.values).collect::<Vec<_>>();
+        assert_eq!(values(a.difference(&b).unwrap()), vec![row(1.0)]);
+        assert_eq!(values(b.difference(&a).unwrap()), vec![row(3.0)]);
+        assert_ne!(values(a.difference(&b).unwrap()), values(b.difference(&a).unwrap()));
+    }
 }
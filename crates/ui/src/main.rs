@@ -1,10 +1,156 @@
+use core::io::{save_to_file, with_retry, ImportOptions, RetryPolicy};
+use core::io::csv::{export_table_csv, import_table_csv};
 use core::types::database::Database;
-use core::types::schema::{DbSchema, DbColumn, DbColumnType, DbValue};
+use core::types::schema::{DbSchema, DbColumn, DbColumnType, DbValue, format_date, parse_date, validate_identifier};
 use core::types::table::{Table, Row};
 use eframe::egui;
 use rfd::FileDialog;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Text typed into the "Add Row" form, one entry per column. `range_ends`
+/// holds the upper bound for `MoneyRange` columns; `nulls` marks nullable
+/// columns left empty on purpose.
+#[derive(Default)]
+struct RowForm {
+    inputs: Vec<String>,
+    range_ends: Vec<String>,
+    nulls: Vec<bool>,
+    field_errors: HashMap<usize, String>,
+    error: Option<String>,
+}
+
+impl RowForm {
+    fn for_schema(schema: &DbSchema) -> Self {
+        let len = schema.columns.len();
+        RowForm {
+            inputs: schema.columns.iter()
+                .map(|c| if c.column_type == DbColumnType::Boolean { false.to_string() } else { String::new() })
+                .collect(),
+            range_ends: vec![String::new(); len],
+            nulls: vec![false; len],
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses the form text for one column into a value of the column's type.
+fn parse_field(column_type: &DbColumnType, text: &str, range_end: &str) -> Result<DbValue, String> {
+    let text = match column_type {
+        DbColumnType::MoneyRange => format!("{}..{}", text, range_end),
+        _ => text.to_string(),
+    };
+    DbValue::parse(&text, column_type).map_err(|e| e.to_string())
+}
+
+/// Whether any value of `row`, as displayed, contains `query`, ignoring case.
+/// `query` must already be lowercase.
+fn row_matches(row: &Row, query: &str) -> bool {
+    row.values.iter().any(|v| v.to_string().to_lowercase().contains(query))
+}
+
+/// Shows "used/max" characters, in red once the limit is exceeded.
+fn length_counter(ui: &mut egui::Ui, text: &str, max_len: usize) {
+    let len = text.chars().count();
+    let label = egui::RichText::new(format!("{}/{}", len, max_len)).small();
+    ui.label(if len > max_len { label.color(egui::Color32::RED) } else { label });
+}
+
+/// How many undo steps are kept; each one is a full copy of the database.
+const HISTORY_DEPTH: usize = 50;
+
+/// A table cell: (table name, row id, column).
+type CellKey = (String, u32, usize);
+
+/// Database snapshots for undo and redo. `current` is the database as of the
+/// last recorded change, so recording the next change pushes the state from
+/// just before it. Successive edits of one cell are folded into a single
+/// step until `end_edit` is called.
+#[derive(Default)]
+struct History {
+    undo: Vec<Database>,
+    redo: Vec<Database>,
+    current: Option<Database>,
+    editing: Option<CellKey>,
+}
+
+impl History {
+    /// Forgets every step and starts over from `db`.
+    fn reset(&mut self, db: Option<&Database>) {
+        self.undo.clear();
+        self.redo.clear();
+        self.current = db.cloned();
+        self.editing = None;
+    }
+
+    /// Call after each change with the database as it now is.
+    fn record(&mut self, db: &Database) {
+        self.editing = None;
+        self.push(db);
+    }
+
+    /// Like `record`, for a change to `cell` made by a text field or slider;
+    /// joins the previous step if that changed the same cell.
+    fn record_edit(&mut self, db: &Database, cell: CellKey) {
+        if self.editing.as_ref() == Some(&cell) {
+            self.current = Some(db.clone());
+            self.redo.clear();
+        } else {
+            self.push(db);
+            self.editing = Some(cell);
+        }
+    }
+
+    /// Call when the field editing `cell` loses focus or its drag stops, so
+    /// the next edit starts a new step.
+    fn end_edit(&mut self, cell: &CellKey) {
+        if self.editing.as_ref() == Some(cell) {
+            self.editing = None;
+        }
+    }
+
+    fn push(&mut self, db: &Database) {
+        if let Some(previous) = self.current.replace(db.clone()) {
+            self.undo.push(previous);
+            if self.undo.len() > HISTORY_DEPTH {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) -> Option<Database> {
+        let previous = self.undo.pop()?;
+        self.editing = None;
+        self.redo.extend(self.current.replace(previous.clone()));
+        Some(previous)
+    }
+
+    fn redo(&mut self) -> Option<Database> {
+        let next = self.redo.pop()?;
+        self.editing = None;
+        self.undo.extend(self.current.replace(next.clone()));
+        Some(next)
+    }
+}
+
+/// Which set operation the intersection window runs against the other table.
+#[derive(Default, Clone, Copy, PartialEq)]
+enum SetOperation {
+    #[default]
+    Intersection,
+    Difference,
+}
+
+impl SetOperation {
+    fn name(self) -> &'static str {
+        match self {
+            SetOperation::Intersection => "intersection",
+            SetOperation::Difference => "difference",
+        }
+    }
+}
+
 #[derive(Default)]
 struct DatabaseApp {
     database: Option<Database>,
@@ -15,12 +161,37 @@ struct DatabaseApp {
     new_schema: Vec<DbColumn>,
     temp_column_name: String,
     temp_column_type: DbColumnType,
+    temp_column_indexed: bool,
+    temp_column_unique: bool,
+    temp_column_nullable: bool,
+    schema_error: Option<String>,
     new_db_name: String,
     has_unsaved_changes: bool,
     show_close_confirmation: bool,
     show_intersection_window: bool,
     intersection_table: Option<String>,
     intersection_result: Option<Vec<Row>>,
+    set_operation: SetOperation,
+    intersection_save_error: Option<String>,
+    row_errors: HashMap<u32, String>,
+    /// Date cells being edited, keyed by (row id, column): the typed text and
+    /// whether it failed to parse when the field lost focus.
+    date_edits: HashMap<(u32, usize), (String, bool)>,
+    /// Table being renamed in the tables list, with the name typed so far.
+    renaming: Option<(String, String)>,
+    rename_error: Option<String>,
+    row_form: Option<RowForm>,
+    /// Set while the "Clear All Rows" confirmation is showing.
+    confirm_clear: bool,
+    history: History,
+    /// Filter typed into the table view's search box.
+    row_search: String,
+    /// Column the table view is sorted by, set by clicking its header; the
+    /// table's own order is used when unset.
+    sort_column: Option<String>,
+    sort_descending: bool,
+    /// Why the last CSV export or import from the table view failed.
+    csv_error: Option<String>,
 }
 
 impl DatabaseApp {
@@ -36,17 +207,59 @@ impl DatabaseApp {
         }
     }
 
+    /// Call after every change to the database; also records an undo step.
     fn mark_as_modified(&mut self) {
         self.has_unsaved_changes = true;
+        if let Some(db) = &self.database {
+            self.history.record(db);
+        }
+    }
+
+    /// `mark_as_modified` for an edit typed or dragged into one cell, which
+    /// shares an undo step with the edits to that cell just before it.
+    fn mark_cell_modified(&mut self, cell: CellKey) {
+        self.has_unsaved_changes = true;
+        if let Some(db) = &self.database {
+            self.history.record_edit(db, cell);
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some(db) = self.history.undo() {
+            self.restore(db);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(db) = self.history.redo() {
+            self.restore(db);
+        }
+    }
+
+    /// Swaps in a snapshot from the history, dropping edits in progress that
+    /// may refer to rows or tables it lacks.
+    fn restore(&mut self, db: Database) {
+        if let Some(name) = &self.selected_table {
+            if db.get_table(name).is_none() {
+                self.selected_table = None;
+            }
+        }
+        self.database = Some(db);
+        self.has_unsaved_changes = true;
+        self.row_errors.clear();
+        self.date_edits.clear();
+        self.row_form = None;
+        self.renaming = None;
+        self.confirm_clear = false;
     }
 
     fn save_database(&mut self) -> bool {
-        if let (Some(db), Some(path)) = (&self.database, &self.database_path) {
-            if let Ok(json) = serde_json::to_string_pretty(db) {
-                if std::fs::write(path, json).is_ok() {
-                    self.has_unsaved_changes = false;
-                    return true;
-                }
+        let path = self.database_path.as_ref().and_then(|p| p.to_str());
+        if let (Some(db), Some(path)) = (&self.database, path) {
+            // Goes through `save_to_file` so the checksum sidecar stays in step.
+            if with_retry(&RetryPolicy::default(), || save_to_file(db, path)).is_ok() {
+                self.has_unsaved_changes = false;
+                return true;
             }
         }
         false
@@ -101,6 +314,7 @@ impl DatabaseApp {
         self.selected_table = None;
         self.has_unsaved_changes = false;
         self.show_close_confirmation = false;
+        self.history.reset(None);
     }
 
     fn show_database_selection(&mut self, ui: &mut egui::Ui) {
@@ -119,6 +333,7 @@ impl DatabaseApp {
                         .save_file()
                     {
                         self.database = Some(Database::new(&self.new_db_name));
+                        self.history.reset(self.database.as_ref());
                         self.database_path = Some(path.clone());
                         self.save_database();
                         self.new_db_name.clear();
@@ -141,6 +356,7 @@ impl DatabaseApp {
                     if let Ok(file_content) = std::fs::read_to_string(&path) {
                         if let Ok(db) = serde_json::from_str(&file_content) {
                             self.database = Some(db);
+                            self.history.reset(self.database.as_ref());
                             self.has_unsaved_changes = false;
                         }
                     }
@@ -202,9 +418,60 @@ impl DatabaseApp {
             for table_name in table_names {
                 let table_name_clone = table_name.clone();
                 ui.horizontal(|ui| {
+                    if let Some((old, new)) = &mut self.renaming {
+                        if *old == table_name {
+                            ui.text_edit_singleline(new);
+                            if ui.button("Save").clicked() {
+                                let (old, new) = (old.clone(), new.clone());
+                                if let Some(db) = &mut self.database {
+                                    match db.rename_table(&old, &new) {
+                                        Ok(()) => {
+                                            if self.selected_table.as_deref() == Some(&old) {
+                                                self.selected_table = Some(new);
+                                            }
+                                            self.renaming = None;
+                                            self.rename_error = None;
+                                            self.mark_as_modified();
+                                        }
+                                        Err(e) => self.rename_error = Some(e.to_string()),
+                                    }
+                                }
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.renaming = None;
+                                self.rename_error = None;
+                            }
+                            if let Some(error) = &self.rename_error {
+                                ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                            }
+                            return;
+                        }
+                    }
+
                     if ui.button(&table_name).clicked() {
                         self.selected_table = Some(table_name.clone());
                     }
+                    if ui.button("✏").on_hover_text("Rename").clicked() {
+                        self.renaming = Some((table_name.clone(), table_name.clone()));
+                        self.rename_error = None;
+                    }
+                    if ui.button("⧉").on_hover_text("Duplicate").clicked() {
+                        if let Some(db) = &mut self.database {
+                            let copy_name = (1..)
+                                .map(|n| if n == 1 { format!("{}_copy", table_name) } else { format!("{}_copy{}", table_name, n) })
+                                .find(|name| db.get_table(name).is_none())
+                                .unwrap_or_default();
+                            match db.duplicate_table(&table_name, &copy_name) {
+                                Ok(()) => {
+                                    self.mark_as_modified();
+                                    // Let the user name the copy straight away.
+                                    self.renaming = Some((copy_name.clone(), copy_name));
+                                    self.rename_error = None;
+                                }
+                                Err(e) => self.rename_error = Some(e.to_string()),
+                            }
+                        }
+                    }
                     if ui.button("🗑").clicked() {
                         if let Some(db) = &mut self.database {
                             db.delete_table(&table_name_clone);
@@ -277,14 +544,33 @@ impl DatabaseApp {
                                 ui.selectable_value(&mut self.temp_column_type, DbColumnType::Char, "Char");
                                 ui.selectable_value(&mut self.temp_column_type, DbColumnType::Money, "Money");
                                 ui.selectable_value(&mut self.temp_column_type, DbColumnType::MoneyRange, "Money Range");
+                                ui.selectable_value(&mut self.temp_column_type, DbColumnType::Boolean, "Boolean");
+                                ui.selectable_value(&mut self.temp_column_type, DbColumnType::Date, "Date");
+                                ui.selectable_value(&mut self.temp_column_type, DbColumnType::Percentage, "Percentage");
                             });
+                        ui.checkbox(&mut self.temp_column_indexed, "Indexed");
+                        ui.checkbox(&mut self.temp_column_unique, "Unique");
+                        ui.checkbox(&mut self.temp_column_nullable, "Nullable");
                         if (ui.button("Add Column").clicked() || text_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) 
                             && !self.temp_column_name.is_empty() {
-                            self.new_schema.push(DbColumn {
-                                name: self.temp_column_name.clone(),
-                                column_type: self.temp_column_type.clone(),
-                            });
-                            self.temp_column_name.clear();
+                            match validate_identifier(&self.temp_column_name) {
+                                Ok(()) => {
+                                    self.new_schema.push(DbColumn {
+                                        name: self.temp_column_name.clone(),
+                                        column_type: self.temp_column_type.clone(),
+                                        indexed: self.temp_column_indexed,
+                                        unique: self.temp_column_unique,
+                                        nullable: self.temp_column_nullable,
+                                        ..Default::default()
+                                    });
+                                    self.temp_column_name.clear();
+                                    self.temp_column_indexed = false;
+                                    self.temp_column_unique = false;
+                                    self.temp_column_nullable = false;
+                                    self.schema_error = None;
+                                }
+                                Err(e) => self.schema_error = Some(e.to_string()),
+                            }
                         }
                     });
                 });
@@ -297,6 +583,11 @@ impl DatabaseApp {
                         for (i, col) in self.new_schema.iter().enumerate() {
                             ui.horizontal(|ui| {
                                 ui.label(format!("{}: {:?}", col.name, col.column_type));
+                                if col.unique {
+                                    ui.label("(unique)");
+                                } else if col.indexed {
+                                    ui.label("(indexed)");
+                                }
                                 if ui.button("Remove").clicked() {
                                     to_remove = Some(i);
                                 }
@@ -308,6 +599,10 @@ impl DatabaseApp {
                     });
                 }
 
+                if let Some(error) = &self.schema_error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                }
+
                 ui.separator();
 
                 // Buttons at the bottom
@@ -319,14 +614,20 @@ impl DatabaseApp {
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         let can_create = !self.new_schema.is_empty() && !self.new_table_name.is_empty();
                         if ui.add_enabled(can_create, egui::Button::new("Create Table")).clicked() {
-                            if let Some(db) = &mut self.database {
-                                let schema = DbSchema {
-                                    columns: self.new_schema.clone(),
-                                };
-                                let table = Table::new(self.new_table_name.clone(), schema);
-                                db.add_table(table);
-                                self.mark_as_modified();
-                                close_window = true;
+                            let schema = DbSchema {
+                                columns: self.new_schema.clone(),
+                            };
+                            match Table::try_new(self.new_table_name.clone(), schema) {
+                                Err(e) => self.schema_error = Some(e.to_string()),
+                                Ok(table) => if let Some(db) = &mut self.database {
+                                    match db.add_table(table) {
+                                        Ok(()) => {
+                                            self.mark_as_modified();
+                                            close_window = true;
+                                        }
+                                        Err(e) => self.schema_error = Some(e.to_string()),
+                                    }
+                                },
                             }
                         }
                         if !can_create {
@@ -340,6 +641,7 @@ impl DatabaseApp {
             self.show_schema_window = false;
             self.new_schema.clear();
             self.new_table_name.clear();
+            self.schema_error = None;
         }
     }
 
@@ -364,6 +666,17 @@ impl DatabaseApp {
                     }
                 });
 
+                ui.horizontal(|ui| {
+                    let before = self.set_operation;
+                    ui.radio_value(&mut self.set_operation, SetOperation::Intersection, "Intersection");
+                    ui.radio_value(&mut self.set_operation, SetOperation::Difference, "Difference");
+                    if self.set_operation != before {
+                        self.intersection_result = None;
+                        self.intersection_save_error = None;
+                        self.intersection_table = None;
+                    }
+                });
+
                 if let Some(db) = &self.database {
                     if let Some(current_table) = &self.selected_table {
                         let table_names: Vec<_> = db.tables.iter()
@@ -405,9 +718,13 @@ impl DatabaseApp {
                                                             if !schemas_match {
                                                                 error_message = Some("Column types do not match".to_string());
                                                             } else {
-                                                                match table1.intersection(table) {
+                                                                let result = match self.set_operation {
+                                                                    SetOperation::Intersection => table1.intersection(table),
+                                                                    SetOperation::Difference => table1.difference(table),
+                                                                };
+                                                                match result {
                                                                     Ok(result) => {
-                                                                        println!("Found intersection with {} rows", result.len());
+                                                                        println!("Found {} with {} rows", self.set_operation.name(), result.len());
                                                                         self.intersection_result = Some(result);
                                                                         self.intersection_table = Some(table_name.clone());
                                                                         error_message = None;
@@ -441,7 +758,10 @@ impl DatabaseApp {
                         ui.separator();
 
                         if let (Some(result), Some(other_table)) = (&self.intersection_result, &self.intersection_table) {
-                            ui.heading(format!("Intersection with {}", other_table));
+                            match self.set_operation {
+                                SetOperation::Intersection => ui.heading(format!("Intersection with {}", other_table)),
+                                SetOperation::Difference => ui.heading(format!("Difference with {}", other_table)),
+                            };
                             
                             if let Some(table) = db.get_table(current_table) {
                                 // Create a scrollable area for the results
@@ -459,15 +779,7 @@ impl DatabaseApp {
                                         for row in result {
                                             ui.horizontal(|ui| {
                                                 for value in &row.values {
-                                                    let text = match value {
-                                                        DbValue::Integer(n) => n.to_string(),
-                                                        DbValue::Real(n) => format!("{:.2}", n),
-                                                        DbValue::String(s) => s.clone(),
-                                                        DbValue::Char(c) => c.to_string(),
-                                                        DbValue::Money(m) => format!("${:.2}", m),
-                                                        DbValue::MoneyRange(start, end) => format!("${:.2}-${:.2}", start, end),
-                                                    };
-                                                    ui.label(text);
+                                                    ui.label(value.to_string());
                                                 }
                                             });
                                         }
@@ -482,8 +794,11 @@ impl DatabaseApp {
                                 // Add a button to save intersection as a new table
                                 if !result.is_empty() {
                                     ui.separator();
+                                    if let Some(error) = &self.intersection_save_error {
+                                        ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                                    }
                                     if ui.button("Save as New Table").clicked() {
-                                        let new_table_name = format!("{}_{}_intersection", current_table, other_table);
+                                        let new_table_name = format!("{}_{}_{}", current_table, other_table, self.set_operation.name());
                                         let schema = DbSchema {
                                             columns: table.schema.columns.clone(),
                                         };
@@ -504,8 +819,13 @@ impl DatabaseApp {
         // Handle the new table creation outside the UI closure
         if let Some(new_table) = new_intersection_table {
             if let Some(db) = &mut self.database {
-                db.add_table(new_table);
-                self.mark_as_modified();
+                match db.add_table(new_table) {
+                    Ok(()) => self.mark_as_modified(),
+                    Err(e) => {
+                        self.intersection_save_error = Some(e.to_string());
+                        close_window = false;
+                    }
+                }
             }
         }
 
@@ -513,6 +833,118 @@ impl DatabaseApp {
             self.show_intersection_window = false;
             self.intersection_result = None;
             self.intersection_table = None;
+            self.intersection_save_error = None;
+        }
+    }
+
+    fn show_row_form(&mut self, ctx: &egui::Context) {
+        let (Some(form), Some(table_name)) = (&mut self.row_form, &self.selected_table) else {
+            return;
+        };
+        let Some(table) = self.database.as_mut().and_then(|db| db.get_table_mut(table_name)) else {
+            return;
+        };
+
+        let mut close_window = false;
+        let mut inserted = false;
+
+        egui::Window::new("Add Row")
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(format!("New row in {}", table_name));
+                    if ui.button("✕").clicked() {
+                        close_window = true;
+                    }
+                });
+
+                egui::Grid::new("row_form_grid").num_columns(2).show(ui, |ui| {
+                    for (i, col) in table.schema.columns.iter().enumerate() {
+                        match col.range_label() {
+                            Some(range) => ui.label(format!("{} ({:?}) {}", col.name, col.column_type, range)),
+                            None => ui.label(format!("{} ({:?})", col.name, col.column_type)),
+                        };
+                        ui.vertical(|ui| {
+                            if col.nullable {
+                                ui.checkbox(&mut form.nulls[i], "null");
+                            }
+                            if form.nulls[i] {
+                                // Nothing to type for a null value.
+                            } else if col.column_type == DbColumnType::MoneyRange {
+                                ui.horizontal(|ui| {
+                                    ui.text_edit_singleline(&mut form.inputs[i]);
+                                    ui.label("-");
+                                    ui.text_edit_singleline(&mut form.range_ends[i]);
+                                });
+                            } else if col.column_type == DbColumnType::Boolean {
+                                let mut checked = form.inputs[i] == "true";
+                                if ui.checkbox(&mut checked, "").changed() {
+                                    form.inputs[i] = checked.to_string();
+                                }
+                            } else {
+                                ui.text_edit_singleline(&mut form.inputs[i]);
+                                if let (DbColumnType::String, Some(max_len)) = (&col.column_type, col.max_len) {
+                                    length_counter(ui, &form.inputs[i], max_len);
+                                }
+                            }
+                            if let Some(error) = form.field_errors.get(&i) {
+                                ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(error) = &form.error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        close_window = true;
+                    }
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Insert").clicked() {
+                            form.field_errors.clear();
+                            form.error = None;
+
+                            let mut values = Vec::new();
+                            for (i, col) in table.schema.columns.iter().enumerate() {
+                                let value = if form.nulls[i] {
+                                    Ok(DbValue::Null)
+                                } else {
+                                    parse_field(&col.column_type, &form.inputs[i], &form.range_ends[i])
+                                };
+                                let value = value
+                                    .and_then(|v| table.validate_cell(i, &v).map(|_| v).map_err(|e| e.to_string()));
+                                match value {
+                                    Ok(v) => values.push(v),
+                                    Err(e) => {
+                                        form.field_errors.insert(i, e);
+                                    }
+                                }
+                            }
+
+                            if form.field_errors.is_empty() {
+                                match table.insert(values) {
+                                    Ok(_) => inserted = true,
+                                    Err(e) => form.error = Some(format!("Could not add row: {}", e)),
+                                }
+                            }
+                        }
+                    });
+                });
+            });
+
+        if inserted {
+            self.mark_as_modified();
+        }
+        if inserted || close_window {
+            self.row_form = None;
         }
     }
 
@@ -521,7 +953,9 @@ impl DatabaseApp {
             if let Some(db) = &mut self.database {
                 if let Some(table) = db.get_table_mut(table_name) {
                     let mut go_back = false;
-                    let mut add_row = false;
+                    let mut open_row_form = false;
+                    let mut export_csv = false;
+                    let mut import_csv = false;
                     let schema = table.schema.clone();
                     
                     ui.horizontal(|ui| {
@@ -533,149 +967,334 @@ impl DatabaseApp {
                             if ui.button("Find Intersection").clicked() {
                                 self.show_intersection_window = true;
                                 self.intersection_result = None;
+                                self.intersection_save_error = None;
                                 self.intersection_table = None;
                             }
                             ui.add_space(8.0);
                             if ui.button("Add Row").clicked() {
-                                add_row = true;
+                                open_row_form = true;
+                            }
+                            ui.add_space(8.0);
+                            if ui.button("Clear All Rows").clicked() {
+                                self.confirm_clear = true;
+                            }
+                            ui.add_space(8.0);
+                            if ui.button("Import CSV").clicked() {
+                                import_csv = true;
+                            }
+                            if ui.button("Export CSV").clicked() {
+                                export_csv = true;
                             }
                         });
                     });
 
+                    if export_csv {
+                        if let Some(path) = FileDialog::new()
+                            .add_filter("CSV", &["csv"])
+                            .set_file_name(format!("{}.csv", table_name))
+                            .save_file()
+                        {
+                            self.csv_error = path.to_str()
+                                .ok_or_else(|| format!("Invalid path: {}", path.display()))
+                                .and_then(|path| export_table_csv(table, path).map_err(|e| e.to_string()))
+                                .err();
+                        }
+                    }
+
+                    // Read into a new table named after the file, with this table's
+                    // schema; it is added to the database once `table` is done with.
+                    let mut imported = None;
+                    if import_csv {
+                        if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).pick_file() {
+                            match path.to_str()
+                                .ok_or_else(|| format!("Invalid path: {}", path.display()))
+                                .and_then(|path| import_table_csv(path, &schema, ImportOptions::default()).map_err(|e| e.to_string()))
+                            {
+                                Ok(new_table) => imported = Some(new_table),
+                                Err(e) => self.csv_error = Some(e),
+                            }
+                        }
+                    }
+
+                    if let Some(error) = &self.csv_error {
+                        ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                    }
+
+                    let mut cleared = false;
+                    if self.confirm_clear {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(format!("Delete all {} rows of '{}'?", table.len(), table_name))
+                                .color(egui::Color32::RED));
+                            if ui.button("Clear").clicked() {
+                                table.clear();
+                                self.row_errors.clear();
+                                self.date_edits.clear();
+                                self.confirm_clear = false;
+                                cleared = true;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_clear = false;
+                            }
+                        });
+                    }
+
+                    if cleared {
+                        self.mark_as_modified();
+                        return;
+                    }
+
                     if go_back {
                         self.selected_table = None;
+                        self.confirm_clear = false;
+                        self.row_search.clear();
+                        self.sort_column = None;
+                        self.csv_error = None;
+                        self.row_errors.clear();
+                        self.date_edits.clear();
+                        self.row_form = None;
                         return;
                     }
 
-                    // Table header
+                    if open_row_form {
+                        self.row_form = Some(RowForm::for_schema(&schema));
+                    }
+
+                    // Search filters the rows shown, in display order, without touching the table
+                    let mut rows: Vec<Row> = match &self.sort_column {
+                        Some(column) => table.sorted_rows(column, self.sort_descending)
+                            .unwrap_or_else(|_| table.get_rows_ordered().into_iter().cloned().collect()),
+                        None => table.get_rows_ordered().into_iter().cloned().collect(),
+                    };
+                    let total = rows.len();
+                    let query = self.row_search.trim().to_lowercase();
+                    if !query.is_empty() {
+                        rows.retain(|row| row_matches(row, &query));
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Search:");
+                        ui.text_edit_singleline(&mut self.row_search);
+                        if ui.add_enabled(!self.row_search.is_empty(), egui::Button::new("Clear")).clicked() {
+                            self.row_search.clear();
+                        }
+                        if !query.is_empty() {
+                            ui.label(format!("{} of {} rows", rows.len(), total));
+                        }
+                    });
+
+                    // Table header; clicking a column sorts by it, again reverses
                     ui.horizontal(|ui| {
                         for col in &schema.columns {
-                            ui.label(&col.name);
+                            let mut label = match col.range_label() {
+                                Some(range) => format!("{} {}", col.name, range),
+                                None => col.name.clone(),
+                            };
+                            let active = self.sort_column.as_deref() == Some(col.name.as_str());
+                            if active {
+                                label.push_str(if self.sort_descending { " ▼" } else { " ▲" });
+                            }
+                            if ui.button(label).clicked() {
+                                self.sort_descending = active && !self.sort_descending;
+                                self.sort_column = Some(col.name.clone());
+                                ui.ctx().request_repaint();
+                            }
                         }
                         ui.label("Actions");
                     });
 
                     // Table rows
-                    let mut rows: Vec<_> = table.rows.iter().collect();
-                    rows.sort_by_key(|(id, _)| **id);
-                    
                     let mut to_delete = None;
                     let mut updates = Vec::new();
+                    // Cells whose text field lost focus or slider was let go
+                    let mut finished = Vec::new();
 
-                    for (&id, row) in &rows {
+                    for row in &rows {
+                        let id = row.id;
                         let mut new_values = row.values.clone();
                         let mut changed = false;
+                        // Column changed by typing or dragging, as opposed to a
+                        // one-off change such as a checkbox click
+                        let mut edited_cell = None;
 
                         ui.horizontal(|ui| {
-                            for (value, _col) in new_values.iter_mut().zip(&schema.columns) {
+                            for (i, (value, col)) in new_values.iter_mut().zip(&schema.columns).enumerate() {
                                 match value {
-                                    DbValue::Integer(n) => {
-                                        let mut text = n.to_string();
-                                        if ui.text_edit_singleline(&mut text).changed() {
-                                            if let Ok(new_val) = text.parse() {
-                                                *n = new_val;
-                                                changed = true;
+                                    DbValue::Integer(_) | DbValue::Real(_) | DbValue::Char(_) | DbValue::Money(_) => {
+                                        let mut text = value.to_text();
+                                        let response = ui.text_edit_singleline(&mut text);
+                                        if response.changed() {
+                                            if let Ok(new_val) = DbValue::parse(&text, &col.column_type) {
+                                                *value = new_val;
+                                                edited_cell = Some(i);
                                             }
                                         }
-                                    }
-                                    DbValue::Real(n) => {
-                                        let mut text = n.to_string();
-                                        if ui.text_edit_singleline(&mut text).changed() {
-                                            if let Ok(new_val) = text.parse() {
-                                                *n = new_val;
-                                                changed = true;
-                                            }
+                                        if response.lost_focus() {
+                                            finished.push((id, i));
                                         }
                                     }
                                     DbValue::String(s) => {
                                         let mut text = s.clone();
-                                        if ui.text_edit_singleline(&mut text).changed() {
+                                        let response = ui.text_edit_singleline(&mut text);
+                                        if response.changed() {
                                             *s = text;
-                                            changed = true;
+                                            edited_cell = Some(i);
+                                        }
+                                        if response.lost_focus() {
+                                            finished.push((id, i));
+                                        }
+                                        if let Some(max_len) = col.max_len {
+                                            length_counter(ui, s, max_len);
                                         }
                                     }
-                                    DbValue::Char(c) => {
-                                        let mut text = c.to_string();
-                                        if ui.text_edit_singleline(&mut text).changed() && !text.is_empty() {
-                                            if let Some(new_char) = text.chars().next() {
-                                                *c = new_char;
-                                                changed = true;
+                                    DbValue::MoneyRange(start, end) => {
+                                        let mut start_text = start.to_string();
+                                        let mut end_text = end.to_string();
+                                        let mut edited = false;
+                                        let mut left = false;
+                                        ui.horizontal(|ui| {
+                                            let start = ui.text_edit_singleline(&mut start_text);
+                                            ui.label("-");
+                                            let end = ui.text_edit_singleline(&mut end_text);
+                                            edited = start.changed() || end.changed();
+                                            left = start.lost_focus() || end.lost_focus();
+                                        });
+                                        if edited {
+                                            if let Ok(new_val) = parse_field(&col.column_type, &start_text, &end_text) {
+                                                *value = new_val;
+                                                edited_cell = Some(i);
                                             }
                                         }
+                                        if left {
+                                            finished.push((id, i));
+                                        }
                                     }
-                                    DbValue::Money(m) => {
-                                        let mut text = m.to_string();
-                                        if ui.text_edit_singleline(&mut text).changed() {
-                                            if let Ok(new_val) = text.parse() {
-                                                *m = new_val;
-                                                changed = true;
-                                            }
+                                    DbValue::Boolean(b) => {
+                                        if ui.checkbox(b, "").changed() {
+                                            changed = true;
                                         }
                                     }
-                                    DbValue::MoneyRange(start, end) => {
-                                        ui.horizontal(|ui| {
-                                            let mut start_text = start.to_string();
-                                            let mut end_text = end.to_string();
-                                            if ui.text_edit_singleline(&mut start_text).changed() {
-                                                if let Ok(new_val) = start_text.parse() {
-                                                    *start = new_val;
+                                    DbValue::Percentage(p) => {
+                                        let response = ui.add(egui::Slider::new(p, 0.0..=100.0).suffix("%"));
+                                        if response.changed() {
+                                            edited_cell = Some(i);
+                                        }
+                                        if response.drag_released() || response.lost_focus() {
+                                            finished.push((id, i));
+                                        }
+                                    }
+                                    DbValue::Date(timestamp) => {
+                                        let key = (id, i);
+                                        let (mut text, invalid) = self.date_edits.get(&key).cloned()
+                                            .unwrap_or_else(|| (format_date(*timestamp), false));
+                                        let mut edit = egui::TextEdit::singleline(&mut text);
+                                        if invalid {
+                                            edit = edit.text_color(egui::Color32::RED);
+                                        }
+                                        let response = ui.add(edit);
+                                        if response.changed() {
+                                            self.date_edits.insert(key, (text.clone(), false));
+                                        }
+                                        if response.lost_focus() && self.date_edits.contains_key(&key) {
+                                            match parse_date(&text) {
+                                                Ok(new_val) => {
+                                                    self.date_edits.remove(&key);
+                                                    *timestamp = new_val;
                                                     changed = true;
                                                 }
-                                            }
-                                            ui.label("-");
-                                            if ui.text_edit_singleline(&mut end_text).changed() {
-                                                if let Ok(new_val) = end_text.parse() {
-                                                    *end = new_val;
-                                                    changed = true;
+                                                Err(_) => {
+                                                    self.date_edits.insert(key, (text, true));
                                                 }
                                             }
-                                        });
+                                        }
                                     }
+                                    DbValue::Null => {
+                                        let mut text = String::new();
+                                        let edit = egui::TextEdit::singleline(&mut text).hint_text("null");
+                                        let response = ui.add(edit);
+                                        if response.changed() {
+                                            if let Ok(new_val) = DbValue::parse(&text, &col.column_type) {
+                                                *value = new_val;
+                                                edited_cell = Some(i);
+                                            }
+                                        }
+                                        if response.lost_focus() {
+                                            finished.push((id, i));
+                                        }
+                                    }
+                                }
+                                if col.nullable && !value.is_null() && ui.small_button("∅").on_hover_text("Set null").clicked() {
+                                    *value = DbValue::Null;
+                                    changed = true;
                                 }
                             }
 
                             if ui.button("🗑").clicked() {
                                 to_delete = Some(id);
                             }
+
+                            if let Some(error) = self.row_errors.get(&id) {
+                                ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                            }
                         });
 
-                        if changed {
-                            updates.push((id, new_values));
+                        if changed || edited_cell.is_some() {
+                            updates.push((id, new_values, edited_cell.filter(|_| !changed)));
                         }
                     }
 
-                    // Apply updates
+                    // Apply updates; a lone typed or dragged cell edit is kept
+                    // apart so it can join that cell's previous undo step
                     let mut modified = false;
+                    let mut edited = None;
                     if let Some(id) = to_delete {
                         if table.delete(id).is_ok() {
                             modified = true;
                         }
                     }
 
-                    for (id, values) in updates {
-                        if table.update(id, values).is_ok() {
-                            modified = true;
+                    for (id, values, cell) in updates {
+                        match table.update(id, values) {
+                            Ok(()) => {
+                                self.row_errors.remove(&id);
+                                match cell {
+                                    Some(col) if edited.is_none() => edited = Some((id, col)),
+                                    _ => modified = true,
+                                }
+                            }
+                            Err(e) => {
+                                self.row_errors.insert(id, e.to_string());
+                            }
                         }
                     }
 
-                    if add_row {
-                        let new_row = schema.columns.iter().map(|col| {
-                            match col.column_type {
-                                DbColumnType::Integer => DbValue::Integer(0),
-                                DbColumnType::Real => DbValue::Real(0.0),
-                                DbColumnType::String => DbValue::String(String::new()),
-                                DbColumnType::Char => DbValue::Char(' '),
-                                DbColumnType::Money => DbValue::Money(0.0),
-                                DbColumnType::MoneyRange => DbValue::MoneyRange(0.0, 0.0),
+                    let mut switch_to = None;
+                    if let Some(new_table) = imported {
+                        let name = new_table.name().to_string();
+                        match db.add_table(new_table) {
+                            Ok(()) => {
+                                self.csv_error = None;
+                                switch_to = Some(name);
+                                modified = true;
                             }
-                        }).collect();
-                        if table.insert(new_row).is_ok() {
-                            modified = true;
+                            Err(e) => self.csv_error = Some(e.to_string()),
                         }
                     }
 
                     if modified {
                         self.mark_as_modified();
+                    } else if let Some((id, col)) = edited {
+                        self.mark_cell_modified((table_name.clone(), id, col));
+                    }
+                    for (id, col) in finished {
+                        self.history.end_edit(&(table_name.clone(), id, col));
+                    }
+
+                    if let Some(name) = switch_to {
+                        self.selected_table = Some(name);
+                        self.row_search.clear();
+                        self.sort_column = None;
+                        self.row_errors.clear();
+                        self.date_edits.clear();
                     }
                 }
             }
@@ -685,10 +1304,24 @@ impl DatabaseApp {
 
 impl eframe::App for DatabaseApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Left to text fields while one has focus, so they keep their own undo.
+        if self.database.is_some() && ctx.memory(|m| m.focus().is_none()) {
+            let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+            let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+            if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+                self.undo();
+            } else if ctx.input_mut(|i| i.consume_shortcut(&redo)) {
+                self.redo();
+            }
+        }
+
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            if self.show_intersection_window {
+            if self.row_form.is_some() {
+                self.row_form = None;
+            } else if self.show_intersection_window {
                 self.show_intersection_window = false;
                 self.intersection_result = None;
+                self.intersection_save_error = None;
                 self.intersection_table = None;
             } else if self.show_schema_window {
                 self.show_schema_window = false;
@@ -711,6 +1344,12 @@ impl eframe::App for DatabaseApp {
                             self.save_database();
                         }
                     }
+                    if ui.add_enabled(!self.history.undo.is_empty(), egui::Button::new("⟲ Undo")).clicked() {
+                        self.undo();
+                    }
+                    if ui.add_enabled(!self.history.redo.is_empty(), egui::Button::new("⟳ Redo")).clicked() {
+                        self.redo();
+                    }
                     if ui.button("Close Database").clicked() {
                         self.try_close_database();
                     }
@@ -741,6 +1380,8 @@ impl eframe::App for DatabaseApp {
         if self.show_intersection_window {
             self.show_intersection_window(ctx);
         }
+
+        self.show_row_form(ctx);
     }
 }
 
@@ -757,3 +1398,81 @@ fn main() -> eframe::Result<()> {
         Box::new(|cc| Box::new(DatabaseApp::new(cc)))
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database_with(value: i32) -> Database {
+        let schema = DbSchema {
+            columns: vec![DbColumn { name: "n".to_string(), column_type: DbColumnType::Integer, ..Default::default() }],
+        };
+        let mut table = Table::new("numbers".to_string(), schema);
+        table.insert(vec![DbValue::Integer(value)]).unwrap();
+        let mut db = Database::new("test");
+        db.add_table(table).unwrap();
+        db
+    }
+
+    fn cell() -> CellKey {
+        ("numbers".to_string(), 0, 0)
+    }
+
+    #[test]
+    fn test_history_undo_redo() {
+        let mut history = History::default();
+        history.reset(Some(&database_with(0)));
+        history.record(&database_with(1));
+        history.record(&database_with(2));
+
+        assert_eq!(history.undo(), Some(database_with(1)));
+        assert_eq!(history.undo(), Some(database_with(0)));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(database_with(1)));
+
+        history.record(&database_with(3));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(database_with(1)));
+    }
+
+    #[test]
+    fn test_history_depth_is_capped() {
+        let mut history = History::default();
+        history.reset(Some(&database_with(0)));
+        for n in 1..=HISTORY_DEPTH as i32 + 10 {
+            history.record(&database_with(n));
+        }
+        assert_eq!(history.undo.len(), HISTORY_DEPTH);
+        assert_eq!(history.undo.first(), Some(&database_with(10)));
+    }
+
+    #[test]
+    fn test_history_coalesces_cell_edits() {
+        let mut history = History::default();
+        history.reset(Some(&database_with(0)));
+
+        // Typing "123" into one cell is a single step
+        for n in [1, 12, 123] {
+            history.record_edit(&database_with(n), cell());
+        }
+        assert_eq!(history.undo.len(), 1);
+
+        // Leaving the field ends the step
+        history.end_edit(&cell());
+        history.record_edit(&database_with(1234), cell());
+        assert_eq!(history.undo.len(), 2);
+
+        // So does an edit of another cell, or any other change
+        history.record_edit(&database_with(5), ("numbers".to_string(), 0, 1));
+        history.record(&database_with(6));
+        history.record_edit(&database_with(7), cell());
+        assert_eq!(history.undo.len(), 5);
+
+        assert_eq!(history.undo(), Some(database_with(6)));
+        assert_eq!(history.undo(), Some(database_with(5)));
+        assert_eq!(history.undo(), Some(database_with(1234)));
+        assert_eq!(history.undo(), Some(database_with(123)));
+        assert_eq!(history.undo(), Some(database_with(0)));
+        assert_eq!(history.redo(), Some(database_with(123)));
+    }
+}